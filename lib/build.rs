@@ -6,6 +6,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let products_proto_path = get_canonical_path("products", "products")?;
     let orders_proto_path = get_canonical_path("orders", "orders")?;
     let payments_proto_path = get_canonical_path("payments", "payments")?;
+    let shared_proto_path = get_canonical_path("shared", "shared")?;
 
     tonic_build::compile_protos(acl_proto_path)?;
     tonic_build::compile_protos(email_proto_path)?;
@@ -13,6 +14,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     tonic_build::compile_protos(products_proto_path)?;
     tonic_build::compile_protos(orders_proto_path)?;
     tonic_build::compile_protos(payments_proto_path)?;
+    tonic_build::compile_protos(shared_proto_path)?;
 
     Ok(())
 }
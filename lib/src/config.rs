@@ -0,0 +1,1349 @@
+use std::{
+    collections::HashMap,
+    env, fmt, fs,
+    str::FromStr,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use hyper::{header::HeaderValue, Uri};
+use serde::Deserialize;
+use tracing_subscriber::EnvFilter;
+
+use crate::{
+    database::{
+        connection::{DbAuthLevel, DbConfig},
+        migrations::MigrationMode,
+    },
+    schema,
+};
+
+/// The typed configuration of a service. Loaded once at startup from an optional TOML file (CONFIG_FILE)
+/// with the environment variables taking precedence, then shared with the handlers.
+#[derive(Clone, Debug)]
+pub struct AppConfig {
+    pub service: String,
+    /// "prod" unless configured otherwise because it's the most secure
+    pub environment: String,
+    pub http_port: u16,
+    pub grpc_port: u16,
+    /// Prometheus' scrape port. `/metrics` is only served there, off the public app, and not at all
+    /// unless it's configured.
+    pub metrics_port: Option<u16>,
+    pub cors_allowed_origins: Vec<HeaderValue>,
+    pub endpoints: Endpoints,
+    pub database: DbConfig,
+    pub paystack: Option<PaystackConfig>,
+    pub email: EmailConfig,
+    pub admin_user_ids: Vec<String>,
+    /// The downstream services (by endpoint name, e.g. "acl") that must be reachable for the service to be ready
+    pub readiness_checks: Vec<String>,
+    pub telemetry: TelemetryConfig,
+    pub logging: LoggingConfig,
+    pub graphql: GraphqlConfig,
+    pub rate_limit: RateLimitConfig,
+    pub grpc_client: GrpcClientConfig,
+    pub service_auth: ServiceAuthConfig,
+    pub events: EventsConfig,
+}
+
+/// The gRPC endpoints of the downstream services
+#[derive(Clone, Debug)]
+pub struct Endpoints {
+    pub acl: String,
+    pub email: String,
+    pub files: String,
+    pub products: String,
+    pub orders: String,
+    pub payments: String,
+    pub shared: String,
+    /// The HTTP address the files service serves file contents from
+    pub files_http: Option<String>,
+}
+
+impl Endpoints {
+    /// The gRPC endpoint of a downstream service by name, e.g. "acl"
+    pub fn by_name(&self, name: &str) -> Option<&str> {
+        match name {
+            "acl" => Some(&self.acl),
+            "email" => Some(&self.email),
+            "files" => Some(&self.files),
+            "products" => Some(&self.products),
+            "orders" => Some(&self.orders),
+            "payments" => Some(&self.payments),
+            "shared" => Some(&self.shared),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self {
+            acl: "http://[::1]:50051".to_string(),
+            email: "http://[::1]:50052".to_string(),
+            files: "http://[::1]:50053".to_string(),
+            products: "http://[::1]:50054".to_string(),
+            orders: "http://[::1]:50055".to_string(),
+            payments: "http://[::1]:50056".to_string(),
+            shared: "http://[::1]:50057".to_string(),
+            files_http: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PaystackConfig {
+    pub secret: String,
+}
+
+/// What outgoing emails link to and are signed with
+#[derive(Clone, Debug)]
+pub struct EmailConfig {
+    pub account_url: String,
+    pub sender_name: String,
+}
+
+impl Default for EmailConfig {
+    fn default() -> Self {
+        Self {
+            account_url: "https://rustytemplates.com/account".to_string(),
+            sender_name: "The Rusty Templates Team".to_string(),
+        }
+    }
+}
+
+/// Where traces are exported to. Nothing is exported unless an OTLP endpoint is configured.
+#[derive(Clone, Debug)]
+pub struct TelemetryConfig {
+    /// The OTLP/gRPC collector, e.g. "http://otel-collector:4317"
+    pub otlp_endpoint: Option<String>,
+    /// The share of the traces started by this service that are sampled, from 0.0 to 1.0.
+    /// Traces started upstream follow the caller's sampling decision.
+    pub sample_ratio: f64,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            otlp_endpoint: None,
+            sample_ratio: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    /// One JSON object per line, for log shippers
+    Json,
+    /// Human readable, for local development
+    Text,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format.to_lowercase().as_str() {
+            "json" => Ok(LogFormat::Json),
+            "text" | "pretty" => Ok(LogFormat::Text),
+            other => Err(format!("Invalid log format: {}", other)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LoggingConfig {
+    /// `tracing_subscriber::EnvFilter` directives, e.g. "info,orders=debug"
+    pub filter: String,
+    pub format: LogFormat,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            filter: "info".to_string(),
+            format: LogFormat::Json,
+        }
+    }
+}
+
+/// The limits of the public GraphQL endpoints, applied by `schema::configure_schema`
+#[derive(Clone, Debug)]
+pub struct GraphqlConfig {
+    /// Only enforced in production, the playground's introspection query is deeper
+    pub max_depth: usize,
+    /// The cost budget of an operation. Fields cost 1 unless annotated with a `complexity`.
+    pub max_complexity: usize,
+    pub operation_timeout: Duration,
+    /// How many automatic persisted queries (hash to query) are kept
+    pub persisted_query_cache_size: usize,
+    /// Loaded from a persisted query manifest (GRAPHQL_ALLOW_LIST). When set, only the operations
+    /// it lists are executed, by hash or by their exact body. That includes the gateway's federation
+    /// queries, the manifest has to list the subgraph queries of its query plans.
+    pub allow_list: Option<Arc<HashMap<String, String>>>,
+}
+
+impl Default for GraphqlConfig {
+    fn default() -> Self {
+        Self {
+            max_depth: 5,
+            max_complexity: 1000,
+            operation_timeout: Duration::from_secs(10),
+            persisted_query_cache_size: 1024,
+            allow_list: None,
+        }
+    }
+}
+
+/// A token bucket: bursts of up to `capacity` requests, refilled at `capacity` per `period`.
+/// Written "5/60" for five requests per sixty seconds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimit {
+    pub capacity: u32,
+    pub period: Duration,
+}
+
+impl RateLimit {
+    /// The tokens refilled per second
+    pub fn refill_rate(&self) -> f64 {
+        self.capacity as f64 / self.period.as_secs_f64()
+    }
+}
+
+impl FromStr for RateLimit {
+    type Err = String;
+
+    fn from_str(limit: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid rate limit: {}, expected requests/seconds", limit);
+        let (capacity, period) = limit.split_once('/').ok_or_else(invalid)?;
+        let capacity: u32 = capacity.trim().parse().map_err(|_| invalid())?;
+        let period: u64 = period.trim().parse().map_err(|_| invalid())?;
+
+        if capacity == 0 || period == 0 {
+            return Err(invalid());
+        }
+
+        Ok(RateLimit {
+            capacity,
+            period: Duration::from_secs(period),
+        })
+    }
+}
+
+/// Where the rate limit buckets are kept
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RateLimitBackend {
+    /// Per instance, the default
+    Memory,
+    /// Shared by the instances of a service, in the service's database
+    SurrealDb,
+}
+
+impl FromStr for RateLimitBackend {
+    type Err = String;
+
+    fn from_str(backend: &str) -> Result<Self, Self::Err> {
+        match backend.to_lowercase().as_str() {
+            "memory" => Ok(RateLimitBackend::Memory),
+            "surrealdb" => Ok(RateLimitBackend::SurrealDb),
+            other => Err(format!("Invalid rate limit backend: {}", other)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RateLimitConfig {
+    pub backend: RateLimitBackend,
+    /// By GraphQL root field, e.g. "createOrder", or REST path, e.g. "/paystack/webhook".
+    /// Everything else is unlimited.
+    pub limits: HashMap<String, RateLimit>,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        let limit = |capacity, period_secs| RateLimit {
+            capacity,
+            period: Duration::from_secs(period_secs),
+        };
+
+        Self {
+            backend: RateLimitBackend::Memory,
+            limits: HashMap::from([
+                ("createOrUpdateCart".to_string(), limit(30, 60)),
+                // Every order initiates a Paystack transaction
+                ("createOrder".to_string(), limit(5, 60)),
+                ("postComment".to_string(), limit(10, 60)),
+                ("rateProduct".to_string(), limit(10, 60)),
+                ("/paystack/webhook".to_string(), limit(120, 60)),
+            ]),
+        }
+    }
+}
+
+/// The resilience policy of the outbound gRPC calls, applied by `utils::resilience`
+#[derive(Clone, Debug)]
+pub struct GrpcClientConfig {
+    /// The deadline of a call, retries included
+    pub timeout: Duration,
+    pub connect_timeout: Duration,
+    /// How many times an idempotent call is sent again after a transient failure
+    pub max_retries: u32,
+    /// The delay before the first retry, doubled for every next one and jittered
+    pub retry_backoff: Duration,
+    /// The consecutive failures that open the circuit breaker of a downstream service
+    pub breaker_failures: u32,
+    /// How long an open circuit breaker fails the calls fast before letting a probe through
+    pub breaker_open: Duration,
+}
+
+impl Default for GrpcClientConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(5),
+            connect_timeout: Duration::from_secs(1),
+            max_retries: 2,
+            retry_backoff: Duration::from_millis(50),
+            breaker_failures: 5,
+            breaker_open: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Mutual TLS on the gRPC servers and clients, PEM encoded. Servers only accept clients presenting a
+/// certificate signed by the CA, and clients only trust servers signed by it.
+#[derive(Clone, Debug)]
+pub struct GrpcTlsConfig {
+    /// The certificate and key a service presents, as a server and as a client
+    pub cert: String,
+    pub key: String,
+    pub ca: String,
+}
+
+/// How the services authenticate each other, see `middleware::auth::service`
+#[derive(Clone, Debug, Default)]
+pub struct ServiceAuthConfig {
+    /// Plaintext gRPC unless configured. Clients only use TLS for `https://` endpoints.
+    pub tls: Option<GrpcTlsConfig>,
+    /// Shared by every service to sign and verify the service tokens (SERVICE_TOKEN_SECRET, required)
+    pub token_secret: String,
+}
+
+/// The delivery of the domain events recorded in the outbox, see `events::dispatcher`
+#[derive(Clone, Debug)]
+pub struct EventsConfig {
+    /// How often the outbox is checked for undelivered events
+    pub poll_interval: Duration,
+    /// The most events delivered per check
+    pub batch_size: usize,
+    /// Failed deliveries are retried until then, the event is kept in the outbox for inspection after
+    pub max_attempts: u32,
+    /// The `/events` endpoints of the services subscribed to this service's events
+    pub subscribers: Vec<String>,
+}
+
+impl Default for EventsConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_secs(1),
+            batch_size: 100,
+            max_attempts: 10,
+            subscribers: vec![],
+        }
+    }
+}
+
+impl AppConfig {
+    /// Load and validate the configuration of a service, e.g. "orders". Every problem is collected so they
+    /// can all be fixed at once instead of one boot at a time.
+    pub fn load(service: &str) -> Result<Self, ConfigError> {
+        let mut errors = vec![];
+
+        let file = match env::var("CONFIG_FILE") {
+            Ok(path) => match fs::read_to_string(&path) {
+                Ok(contents) => toml::from_str::<FileConfig>(&contents).unwrap_or_else(|e| {
+                    errors.push(format!("Invalid config file {}: {}", path, e));
+                    FileConfig::default()
+                }),
+                Err(e) => {
+                    errors.push(format!("Failed to read the config file {}: {}", path, e));
+                    FileConfig::default()
+                }
+            },
+            Err(_) => FileConfig::default(),
+        };
+
+        let mut source = Source {
+            section: file.services.get(service).cloned().unwrap_or_default(),
+            errors: &mut errors,
+        };
+
+        let prefix = service.to_uppercase();
+
+        let environment = source
+            .optional("ENVIRONMENT", file.environment.clone())
+            .unwrap_or_else(|| "prod".to_string());
+
+        let http_port = source.required_parsed(
+            &format!("{}_HTTP_PORT", prefix),
+            source.section.http_port.map(|port| port.to_string()),
+        );
+        let grpc_port = source.required_parsed(
+            &format!("{}_GRPC_PORT", prefix),
+            source.section.grpc_port.map(|port| port.to_string()),
+        );
+        let metrics_port = source.optional_parsed(
+            &format!("{}_METRICS_PORT", prefix),
+            source.section.metrics_port.map(|port| port.to_string()),
+        );
+
+        let cors_allowed_origins = source
+            .required_list(
+                "ALLOWED_SERVICES_CORS",
+                source
+                    .section
+                    .allowed_origins
+                    .clone()
+                    .or_else(|| file.cors.allowed_origins.clone()),
+            )
+            .into_iter()
+            .filter_map(|origin| match origin.parse::<HeaderValue>() {
+                Ok(origin) => Some(origin),
+                Err(_) => {
+                    source.error(format!("Invalid CORS origin: {}", origin));
+                    None
+                }
+            })
+            .collect();
+
+        let defaults = Endpoints::default();
+        let endpoints = Endpoints {
+            acl: source.endpoint(
+                "ACL_GRPC_ENDPOINT",
+                file.endpoints.acl.clone(),
+                defaults.acl,
+            ),
+            email: source.endpoint(
+                "EMAIL_GRPC_ENDPOINT",
+                file.endpoints.email.clone(),
+                defaults.email,
+            ),
+            files: source.endpoint(
+                "FILES_GRPC_ENDPOINT",
+                file.endpoints.files.clone(),
+                defaults.files,
+            ),
+            products: source.endpoint(
+                "PRODUCTS_GRPC_ENDPOINT",
+                file.endpoints.products.clone(),
+                defaults.products,
+            ),
+            orders: source.endpoint(
+                "ORDERS_GRPC_ENDPOINT",
+                file.endpoints.orders.clone(),
+                defaults.orders,
+            ),
+            payments: source.endpoint(
+                "PAYMENTS_GRPC_ENDPOINT",
+                file.endpoints.payments.clone(),
+                defaults.payments,
+            ),
+            shared: source.endpoint(
+                "SHARED_GRPC_ENDPOINT",
+                file.endpoints.shared.clone(),
+                defaults.shared,
+            ),
+            files_http: source
+                .optional("FILES_SERVICE", file.endpoints.files_http.clone())
+                .map(|endpoint| source.validated_url("FILES_SERVICE", endpoint)),
+        };
+
+        // The products service links to the product details served by the files service
+        if service == "products" && endpoints.files_http.is_none() {
+            source.error("Missing FILES_SERVICE".to_string());
+        }
+
+        let database_section = source.section.database.clone();
+        let auth_level = source
+            .optional("DATABASE_AUTH_LEVEL", file.database.auth_level.clone())
+            .map(|auth_level| {
+                auth_level.parse().unwrap_or_else(|_| {
+                    source.error(format!("Invalid DATABASE_AUTH_LEVEL: {}", auth_level));
+                    DbAuthLevel::Root
+                })
+            })
+            .unwrap_or(DbAuthLevel::Root);
+        let max_retries = source
+            .optional_parsed(
+                "DATABASE_CONNECT_RETRIES",
+                file.database.connect_retries.map(|r| r.to_string()),
+            )
+            .unwrap_or(10);
+        let health_check_interval_secs = source
+            .optional_parsed(
+                "DATABASE_HEALTH_CHECK_INTERVAL_SECS",
+                file.database
+                    .health_check_interval_secs
+                    .map(|s| s.to_string()),
+            )
+            .unwrap_or(10);
+        let migrations_mode = source
+            .optional_parsed(
+                "DATABASE_MIGRATIONS_MODE",
+                file.database.migrations_mode.clone(),
+            )
+            .unwrap_or(MigrationMode::Apply);
+
+        let database = DbConfig {
+            host: source.required(
+                &format!("DATABASE_HOST_{}", prefix),
+                database_section.host.clone(),
+            ),
+            port: source.required(
+                &format!("DATABASE_PORT_{}", prefix),
+                database_section.port.map(|port| port.to_string()),
+            ),
+            namespace: source.required("DATABASE_NAMESPACE", file.database.namespace.clone()),
+            database: source.required(
+                &format!("DATABASE_NAME_{}", prefix),
+                database_section.name.clone(),
+            ),
+            username: source.required("DATABASE_USER", file.database.user.clone()),
+            password: source.required("DATABASE_PASSWORD", file.database.password.clone()),
+            auth_level,
+            max_retries,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            health_check_interval: Duration::from_secs(health_check_interval_secs),
+            migrations_mode,
+        };
+
+        // Only the payments service talks to Paystack
+        let paystack_secret = source.optional("PAYSTACK_SECRET", file.paystack.secret.clone());
+        let paystack = match (service, paystack_secret) {
+            (_, Some(secret)) => Some(PaystackConfig { secret }),
+            ("payments", None) => {
+                source.error("Missing PAYSTACK_SECRET".to_string());
+                None
+            }
+            (_, None) => None,
+        };
+
+        let email_defaults = EmailConfig::default();
+        let email = EmailConfig {
+            account_url: source
+                .optional("EMAIL_ACCOUNT_URL", file.email.account_url.clone())
+                .map(|url| source.validated_url("EMAIL_ACCOUNT_URL", url))
+                .unwrap_or(email_defaults.account_url),
+            sender_name: source
+                .optional("EMAIL_SENDER_NAME", file.email.sender_name.clone())
+                .unwrap_or(email_defaults.sender_name),
+        };
+
+        let admin_user_ids = source
+            .optional(
+                "ADMIN_USER_IDS",
+                file.admin_user_ids.clone().map(|ids| ids.join(",")),
+            )
+            .map(|ids| split_list(&ids))
+            .unwrap_or_default();
+
+        let readiness_checks = source
+            .optional(
+                "READINESS_CHECKS",
+                source
+                    .section
+                    .readiness_checks
+                    .clone()
+                    .map(|checks| checks.join(",")),
+            )
+            .map(|checks| split_list(&checks))
+            .unwrap_or_default();
+
+        for check in &readiness_checks {
+            if endpoints.by_name(check).is_none() {
+                source.error(format!(
+                    "Invalid READINESS_CHECKS: unknown service {}",
+                    check
+                ));
+            }
+        }
+
+        let telemetry = TelemetryConfig {
+            otlp_endpoint: source
+                .optional(
+                    "OTEL_EXPORTER_OTLP_ENDPOINT",
+                    file.telemetry.otlp_endpoint.clone(),
+                )
+                .map(|endpoint| source.validated_url("OTEL_EXPORTER_OTLP_ENDPOINT", endpoint)),
+            sample_ratio: source
+                .optional_parsed(
+                    "TRACES_SAMPLE_RATIO",
+                    file.telemetry.sample_ratio.map(|ratio| ratio.to_string()),
+                )
+                .filter(|ratio: &f64| {
+                    let is_valid = (0.0..=1.0).contains(ratio);
+                    if !is_valid {
+                        source.error(format!(
+                            "Invalid TRACES_SAMPLE_RATIO: {} is not between 0 and 1",
+                            ratio
+                        ));
+                    }
+                    is_valid
+                })
+                .unwrap_or(1.0),
+        };
+
+        let logging_defaults = LoggingConfig::default();
+        let logging = LoggingConfig {
+            filter: source
+                .optional("RUST_LOG", file.logging.filter.clone())
+                .filter(|filter| {
+                    let is_valid = EnvFilter::try_new(filter).is_ok();
+                    if !is_valid {
+                        source.error(format!("Invalid RUST_LOG: {}", filter));
+                    }
+                    is_valid
+                })
+                .unwrap_or(logging_defaults.filter),
+            format: source
+                .optional_parsed("LOG_FORMAT", file.logging.format.clone())
+                .unwrap_or(logging_defaults.format),
+        };
+
+        let graphql_defaults = GraphqlConfig::default();
+        let graphql = GraphqlConfig {
+            max_depth: source
+                .optional_parsed(
+                    "GRAPHQL_MAX_DEPTH",
+                    file.graphql.max_depth.map(|depth| depth.to_string()),
+                )
+                .unwrap_or(graphql_defaults.max_depth),
+            max_complexity: source
+                .optional_parsed(
+                    "GRAPHQL_MAX_COMPLEXITY",
+                    file.graphql
+                        .max_complexity
+                        .map(|complexity| complexity.to_string()),
+                )
+                .unwrap_or(graphql_defaults.max_complexity),
+            operation_timeout: source
+                .optional_parsed(
+                    "GRAPHQL_TIMEOUT_SECS",
+                    file.graphql.timeout_secs.map(|secs| secs.to_string()),
+                )
+                .map(Duration::from_secs)
+                .unwrap_or(graphql_defaults.operation_timeout),
+            persisted_query_cache_size: source
+                .optional_parsed(
+                    "GRAPHQL_PERSISTED_QUERY_CACHE_SIZE",
+                    file.graphql
+                        .persisted_query_cache_size
+                        .map(|size| size.to_string()),
+                )
+                .unwrap_or(graphql_defaults.persisted_query_cache_size),
+            allow_list: source
+                .optional("GRAPHQL_ALLOW_LIST", file.graphql.allow_list.clone())
+                .and_then(|path| match schema::load_allow_list(&path) {
+                    Ok(allow_list) => Some(Arc::new(allow_list)),
+                    Err(e) => {
+                        source.error(format!("Invalid GRAPHQL_ALLOW_LIST {}: {}", path, e));
+                        None
+                    }
+                }),
+        };
+
+        let mut rate_limit = RateLimitConfig::default();
+        if let Some(backend) =
+            source.optional_parsed("RATE_LIMIT_BACKEND", file.rate_limit.backend.clone())
+        {
+            rate_limit.backend = backend;
+        }
+
+        // The file's limits then RATE_LIMITS ("createOrder=5/60,postComment=10/60") override the
+        // defaults one operation at a time
+        let mut limits: Vec<(String, String)> =
+            file.rate_limit.limits.clone().into_iter().collect();
+        if let Some(env_limits) = source.optional("RATE_LIMITS", None) {
+            for entry in split_list(&env_limits) {
+                match entry.split_once('=') {
+                    Some((operation, limit)) => {
+                        limits.push((operation.trim().to_string(), limit.to_string()))
+                    }
+                    None => source.error(format!("Invalid RATE_LIMITS entry: {}", entry)),
+                }
+            }
+        }
+        for (operation, limit) in limits {
+            match limit.parse() {
+                Ok(limit) => {
+                    rate_limit.limits.insert(operation, limit);
+                }
+                Err(e) => source.error(format!("{} ({})", e, operation)),
+            }
+        }
+
+        let grpc_client_defaults = GrpcClientConfig::default();
+        let grpc_client = GrpcClientConfig {
+            timeout: source
+                .optional_parsed(
+                    "GRPC_TIMEOUT_MS",
+                    file.grpc_client.timeout_ms.map(|ms| ms.to_string()),
+                )
+                .map(Duration::from_millis)
+                .unwrap_or(grpc_client_defaults.timeout),
+            connect_timeout: source
+                .optional_parsed(
+                    "GRPC_CONNECT_TIMEOUT_MS",
+                    file.grpc_client.connect_timeout_ms.map(|ms| ms.to_string()),
+                )
+                .map(Duration::from_millis)
+                .unwrap_or(grpc_client_defaults.connect_timeout),
+            max_retries: source
+                .optional_parsed(
+                    "GRPC_MAX_RETRIES",
+                    file.grpc_client
+                        .max_retries
+                        .map(|retries| retries.to_string()),
+                )
+                .unwrap_or(grpc_client_defaults.max_retries),
+            retry_backoff: source
+                .optional_parsed(
+                    "GRPC_RETRY_BACKOFF_MS",
+                    file.grpc_client.retry_backoff_ms.map(|ms| ms.to_string()),
+                )
+                .map(Duration::from_millis)
+                .unwrap_or(grpc_client_defaults.retry_backoff),
+            breaker_failures: source
+                .optional_parsed(
+                    "GRPC_BREAKER_FAILURES",
+                    file.grpc_client
+                        .breaker_failures
+                        .map(|failures| failures.to_string()),
+                )
+                .unwrap_or(grpc_client_defaults.breaker_failures),
+            breaker_open: source
+                .optional_parsed(
+                    "GRPC_BREAKER_OPEN_SECS",
+                    file.grpc_client
+                        .breaker_open_secs
+                        .map(|secs| secs.to_string()),
+                )
+                .map(Duration::from_secs)
+                .unwrap_or(grpc_client_defaults.breaker_open),
+        };
+
+        let tls_cert = source.pem("GRPC_TLS_CERT", file.service_auth.tls_cert.clone());
+        let tls_key = source.pem("GRPC_TLS_KEY", file.service_auth.tls_key.clone());
+        let tls_ca = source.pem("GRPC_TLS_CA", file.service_auth.tls_ca.clone());
+        let tls = match (tls_cert, tls_key, tls_ca) {
+            (Some(cert), Some(key), Some(ca)) => Some(GrpcTlsConfig { cert, key, ca }),
+            (None, None, None) => None,
+            _ => {
+                let settings = [
+                    ("GRPC_TLS_CERT", &file.service_auth.tls_cert),
+                    ("GRPC_TLS_KEY", &file.service_auth.tls_key),
+                    ("GRPC_TLS_CA", &file.service_auth.tls_ca),
+                ];
+                // The others are set, and reported if they can't be read
+                let missing: Vec<&str> = settings
+                    .into_iter()
+                    .filter(|(name, file_value)| {
+                        source.optional(name, (*file_value).clone()).is_none()
+                    })
+                    .map(|(name, _)| name)
+                    .collect();
+                if !missing.is_empty() {
+                    source.error(format!("Missing {}", missing.join(", ")));
+                }
+                None
+            }
+        };
+        let service_auth = ServiceAuthConfig {
+            tls,
+            token_secret: source.required(
+                "SERVICE_TOKEN_SECRET",
+                file.service_auth.token_secret.clone(),
+            ),
+        };
+
+        let events_defaults = EventsConfig::default();
+        let events = EventsConfig {
+            poll_interval: source
+                .optional_parsed(
+                    "EVENTS_POLL_INTERVAL_MS",
+                    file.events.poll_interval_ms.map(|ms| ms.to_string()),
+                )
+                .map(Duration::from_millis)
+                .unwrap_or(events_defaults.poll_interval),
+            batch_size: source
+                .optional_parsed(
+                    "EVENTS_BATCH_SIZE",
+                    file.events.batch_size.map(|size| size.to_string()),
+                )
+                .unwrap_or(events_defaults.batch_size),
+            max_attempts: source
+                .optional_parsed(
+                    "EVENTS_MAX_ATTEMPTS",
+                    file.events
+                        .max_attempts
+                        .map(|attempts| attempts.to_string()),
+                )
+                .unwrap_or(events_defaults.max_attempts),
+            subscribers: source
+                .optional(
+                    "EVENT_SUBSCRIBERS",
+                    source
+                        .section
+                        .event_subscribers
+                        .clone()
+                        .map(|subscribers| subscribers.join(",")),
+                )
+                .map(|subscribers| split_list(&subscribers))
+                .unwrap_or_default()
+                .into_iter()
+                .map(|subscriber| source.validated_url("EVENT_SUBSCRIBERS", subscriber))
+                .collect(),
+        };
+
+        if errors.is_empty() {
+            Ok(Self {
+                service: service.to_string(),
+                environment,
+                http_port: http_port.unwrap_or_default(),
+                grpc_port: grpc_port.unwrap_or_default(),
+                metrics_port,
+                cors_allowed_origins,
+                endpoints,
+                database,
+                paystack,
+                email,
+                admin_user_ids,
+                readiness_checks,
+                telemetry,
+                logging,
+                graphql,
+                rate_limit,
+                grpc_client,
+                service_auth,
+                events,
+            })
+        } else {
+            Err(ConfigError { errors })
+        }
+    }
+
+    /// A configuration for local development and tests. Downstream services are expected on their
+    /// well-known local ports.
+    pub fn local(service: &str) -> Self {
+        Self {
+            service: service.to_string(),
+            environment: "dev".to_string(),
+            http_port: 0,
+            grpc_port: 0,
+            metrics_port: None,
+            cors_allowed_origins: vec![],
+            endpoints: Endpoints::default(),
+            database: DbConfig {
+                host: "mem://".to_string(),
+                port: "".to_string(),
+                namespace: "test".to_string(),
+                database: "test".to_string(),
+                username: "".to_string(),
+                password: "".to_string(),
+                auth_level: DbAuthLevel::Root,
+                max_retries: 0,
+                initial_backoff: Duration::from_millis(500),
+                max_backoff: Duration::from_secs(30),
+                health_check_interval: Duration::from_secs(10),
+                migrations_mode: MigrationMode::Apply,
+            },
+            paystack: None,
+            email: EmailConfig::default(),
+            admin_user_ids: vec![],
+            readiness_checks: vec![],
+            telemetry: TelemetryConfig::default(),
+            logging: LoggingConfig {
+                filter: "debug".to_string(),
+                format: LogFormat::Text,
+            },
+            graphql: GraphqlConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+            grpc_client: GrpcClientConfig::default(),
+            service_auth: ServiceAuthConfig {
+                tls: None,
+                token_secret: "local-service-token-secret".to_string(),
+            },
+            events: EventsConfig::default(),
+        }
+    }
+
+    pub fn is_prod(&self) -> bool {
+        self.environment == "prod"
+    }
+}
+
+/// Every problem found while loading the configuration
+pub struct ConfigError {
+    pub errors: Vec<String>,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Invalid configuration:")?;
+        for error in &self.errors {
+            writeln!(f, "  - {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+// Shown by `expect` at boot, so keep it as readable as Display
+impl fmt::Debug for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+static CONFIG: RwLock<Option<Arc<AppConfig>>> = RwLock::new(None);
+
+/// Load the configuration of a service and make it the process wide configuration. Call it first thing in main.
+pub fn init(service: &str) -> Result<Arc<AppConfig>, ConfigError> {
+    let config = Arc::new(AppConfig::load(service)?);
+    install(config.clone());
+    Ok(config)
+}
+
+/// Replace the process wide configuration, e.g. with a tweaked `AppConfig::local` in tests
+pub fn install(config: Arc<AppConfig>) {
+    *CONFIG.write().unwrap() = Some(config);
+}
+
+/// The process wide configuration, for code that is not handed the shared state such as the auth
+/// helpers and gRPC clients. Falls back to `AppConfig::local` when nothing was installed.
+pub fn get() -> Arc<AppConfig> {
+    if let Some(config) = CONFIG.read().unwrap().as_ref() {
+        return config.clone();
+    }
+
+    CONFIG
+        .write()
+        .unwrap()
+        .get_or_insert_with(|| Arc::new(AppConfig::local("unknown")))
+        .clone()
+}
+
+/// The layout of the optional TOML file
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileConfig {
+    environment: Option<String>,
+    admin_user_ids: Option<Vec<String>>,
+    cors: FileCors,
+    endpoints: FileEndpoints,
+    database: FileDatabase,
+    paystack: FilePaystack,
+    email: FileEmail,
+    telemetry: FileTelemetry,
+    logging: FileLogging,
+    graphql: FileGraphql,
+    rate_limit: FileRateLimit,
+    grpc_client: FileGrpcClient,
+    service_auth: FileServiceAuth,
+    events: FileEvents,
+    /// Per-service sections, e.g. [services.orders]
+    services: HashMap<String, FileService>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileCors {
+    allowed_origins: Option<Vec<String>>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileEndpoints {
+    acl: Option<String>,
+    email: Option<String>,
+    files: Option<String>,
+    products: Option<String>,
+    orders: Option<String>,
+    payments: Option<String>,
+    shared: Option<String>,
+    files_http: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileDatabase {
+    namespace: Option<String>,
+    user: Option<String>,
+    password: Option<String>,
+    auth_level: Option<String>,
+    connect_retries: Option<u32>,
+    health_check_interval_secs: Option<u64>,
+    migrations_mode: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+struct FilePaystack {
+    secret: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileEmail {
+    account_url: Option<String>,
+    sender_name: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileTelemetry {
+    otlp_endpoint: Option<String>,
+    sample_ratio: Option<f64>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileLogging {
+    filter: Option<String>,
+    format: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileGraphql {
+    max_depth: Option<usize>,
+    max_complexity: Option<usize>,
+    timeout_secs: Option<u64>,
+    persisted_query_cache_size: Option<usize>,
+    allow_list: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileRateLimit {
+    backend: Option<String>,
+    /// e.g. createOrder = "5/60"
+    limits: HashMap<String, String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileGrpcClient {
+    timeout_ms: Option<u64>,
+    connect_timeout_ms: Option<u64>,
+    max_retries: Option<u32>,
+    retry_backoff_ms: Option<u64>,
+    breaker_failures: Option<u32>,
+    breaker_open_secs: Option<u64>,
+}
+
+/// The TLS settings are paths to PEM files
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileServiceAuth {
+    tls_cert: Option<String>,
+    tls_key: Option<String>,
+    tls_ca: Option<String>,
+    token_secret: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileEvents {
+    poll_interval_ms: Option<u64>,
+    batch_size: Option<usize>,
+    max_attempts: Option<u32>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileService {
+    http_port: Option<u16>,
+    grpc_port: Option<u16>,
+    metrics_port: Option<u16>,
+    allowed_origins: Option<Vec<String>>,
+    readiness_checks: Option<Vec<String>>,
+    event_subscribers: Option<Vec<String>>,
+    database: FileServiceDatabase,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileServiceDatabase {
+    host: Option<String>,
+    port: Option<u16>,
+    name: Option<String>,
+}
+
+/// Resolves values from the environment first, then the file, and collects the errors
+struct Source<'a> {
+    section: FileService,
+    errors: &'a mut Vec<String>,
+}
+
+impl Source<'_> {
+    fn error(&mut self, error: String) {
+        self.errors.push(error);
+    }
+
+    fn optional(&self, name: &str, file_value: Option<String>) -> Option<String> {
+        env::var(name)
+            .ok()
+            .or(file_value)
+            .filter(|value| !value.trim().is_empty())
+    }
+
+    fn required(&mut self, name: &str, file_value: Option<String>) -> String {
+        self.optional(name, file_value).unwrap_or_else(|| {
+            self.error(format!("Missing {}", name));
+            String::new()
+        })
+    }
+
+    fn optional_parsed<V: FromStr>(&mut self, name: &str, file_value: Option<String>) -> Option<V> {
+        let value = self.optional(name, file_value)?;
+
+        match value.trim().parse() {
+            Ok(value) => Some(value),
+            Err(_) => {
+                self.error(format!("Invalid {}: {}", name, value));
+                None
+            }
+        }
+    }
+
+    fn required_parsed<V: FromStr>(&mut self, name: &str, file_value: Option<String>) -> Option<V> {
+        if self.optional(name, file_value.clone()).is_none() {
+            self.error(format!("Missing {}", name));
+            return None;
+        }
+
+        self.optional_parsed(name, file_value)
+    }
+
+    fn required_list(&mut self, name: &str, file_value: Option<Vec<String>>) -> Vec<String> {
+        let value = self.required(name, file_value.map(|list| list.join(",")));
+        let list = split_list(&value);
+
+        if list.is_empty() && !value.is_empty() {
+            self.error(format!("Invalid {}: {}", name, value));
+        }
+
+        list
+    }
+
+    fn endpoint(&mut self, name: &str, file_value: Option<String>, default: String) -> String {
+        match self.optional(name, file_value) {
+            Some(endpoint) => self.validated_url(name, endpoint),
+            None => default,
+        }
+    }
+
+    /// The contents of the PEM file at the configured path
+    fn pem(&mut self, name: &str, file_value: Option<String>) -> Option<String> {
+        let path = self.optional(name, file_value)?;
+
+        match fs::read_to_string(&path) {
+            Ok(pem) => Some(pem),
+            Err(e) => {
+                self.error(format!("Invalid {} {}: {}", name, path, e));
+                None
+            }
+        }
+    }
+
+    fn validated_url(&mut self, name: &str, url: String) -> String {
+        let is_valid = (url.starts_with("http://") || url.starts_with("https://"))
+            && url.parse::<Uri>().is_ok();
+
+        if !is_valid {
+            self.error(format!("Invalid {}: {} is not an http(s) URL", name, url));
+        }
+
+        url
+    }
+}
+
+fn split_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(|item| item.trim())
+        .filter(|item| !item.is_empty())
+        .map(|item| item.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Environment variables are process wide, so everything touching them runs in this one test
+    #[test]
+    fn loads_from_file_and_env_and_reports_every_error() {
+        env::set_var("WIDGETS_HTTP_PORT", "not-a-port");
+        env::set_var("ACL_GRPC_ENDPOINT", "[::1]:50051");
+        env::set_var("DATABASE_MIGRATIONS_MODE", "sideways");
+
+        let errors = AppConfig::load("widgets").unwrap_err().errors;
+        assert!(errors.contains(&"Invalid WIDGETS_HTTP_PORT: not-a-port".to_string()));
+        assert!(errors.contains(&"Missing WIDGETS_GRPC_PORT".to_string()));
+        assert!(errors.contains(&"Missing DATABASE_HOST_WIDGETS".to_string()));
+        assert!(errors.contains(&"Missing SERVICE_TOKEN_SECRET".to_string()));
+        assert!(errors.contains(&"Invalid DATABASE_MIGRATIONS_MODE: sideways".to_string()));
+        assert!(errors
+            .iter()
+            .any(|error| error.starts_with("Invalid ACL_GRPC_ENDPOINT")));
+
+        let path = env::temp_dir().join("widgets-config.toml");
+        fs::write(
+            &path,
+            r#"
+            environment = "staging"
+            admin_user_ids = ["admin-1"]
+
+            [cors]
+            allowed_origins = ["https://rustytemplates.com"]
+
+            [database]
+            namespace = "rusty"
+            user = "widgets"
+            password = "secret"
+            migrations_mode = "status"
+
+            [endpoints]
+            acl = "http://acl:50051"
+
+            [telemetry]
+            otlp_endpoint = "http://otel-collector:4317"
+            sample_ratio = 0.25
+
+            [logging]
+            filter = "info,widgets=debug"
+            format = "text"
+
+            [graphql]
+            max_complexity = 500
+            timeout_secs = 5
+
+            [rate_limit]
+            backend = "surrealdb"
+
+            [rate_limit.limits]
+            createOrder = "3/60"
+            postComment = "20/60"
+
+            [grpc_client]
+            timeout_ms = 2000
+            max_retries = 1
+
+            [service_auth]
+            token_secret = "shared-secret"
+
+            [events]
+            max_attempts = 3
+
+            [services.widgets]
+            http_port = 3020
+            grpc_port = 50060
+            metrics_port = 9060
+            readiness_checks = ["acl"]
+            event_subscribers = ["http://orders:3013/events"]
+
+            [services.widgets.database]
+            host = "widgets-db"
+            port = 8010
+            name = "widgets"
+            "#,
+        )
+        .unwrap();
+        env::set_var("CONFIG_FILE", &path);
+        env::remove_var("ACL_GRPC_ENDPOINT");
+        env::remove_var("DATABASE_MIGRATIONS_MODE");
+        env::remove_var("RUST_LOG");
+
+        // The environment wins over the file
+        env::set_var("WIDGETS_HTTP_PORT", "3021");
+        env::set_var("RATE_LIMITS", "createOrder=1/10");
+
+        let config = AppConfig::load("widgets").unwrap();
+        assert_eq!(config.environment, "staging");
+        assert_eq!(config.http_port, 3021);
+        assert_eq!(config.grpc_port, 50060);
+        assert_eq!(config.metrics_port, Some(9060));
+        assert_eq!(config.endpoints.acl, "http://acl:50051");
+        assert_eq!(config.endpoints.email, "http://[::1]:50052");
+        assert_eq!(config.database.url(), "ws://widgets-db:8010");
+        assert_eq!(config.database.migrations_mode, MigrationMode::Status);
+        assert_eq!(config.admin_user_ids, vec!["admin-1".to_string()]);
+        assert!(config.paystack.is_none());
+        assert_eq!(config.readiness_checks, vec!["acl".to_string()]);
+        assert_eq!(
+            config.telemetry.otlp_endpoint.as_deref(),
+            Some("http://otel-collector:4317")
+        );
+        assert_eq!(config.telemetry.sample_ratio, 0.25);
+        assert_eq!(config.logging.filter, "info,widgets=debug");
+        assert_eq!(config.logging.format, LogFormat::Text);
+        assert_eq!(config.graphql.max_complexity, 500);
+        assert_eq!(config.graphql.operation_timeout, Duration::from_secs(5));
+        assert_eq!(config.graphql.max_depth, 5);
+        assert_eq!(config.rate_limit.backend, RateLimitBackend::SurrealDb);
+        assert_eq!(
+            config.rate_limit.limits["createOrder"],
+            RateLimit {
+                capacity: 1,
+                period: Duration::from_secs(10)
+            }
+        );
+        assert_eq!(config.rate_limit.limits["postComment"].capacity, 20);
+        assert_eq!(config.rate_limit.limits["rateProduct"].capacity, 10);
+        assert_eq!(config.grpc_client.timeout, Duration::from_secs(2));
+        assert_eq!(config.grpc_client.max_retries, 1);
+        assert_eq!(config.grpc_client.breaker_failures, 5);
+
+        assert_eq!(config.service_auth.token_secret, "shared-secret");
+        assert!(config.service_auth.tls.is_none());
+        assert_eq!(config.events.max_attempts, 3);
+        assert_eq!(config.events.batch_size, 100);
+        assert_eq!(
+            config.events.subscribers,
+            vec!["http://orders:3013/events".to_string()]
+        );
+
+        // The TLS settings are read from PEM files
+        let cert_path = env::temp_dir().join("widgets-cert.pem");
+        fs::write(&cert_path, "widgets certificate").unwrap();
+        env::set_var("GRPC_TLS_CERT", &cert_path);
+        env::set_var("GRPC_TLS_KEY", "/missing/widgets-key.pem");
+        let errors = AppConfig::load("widgets").unwrap_err().errors;
+        assert!(errors
+            .iter()
+            .any(|error| error.starts_with("Invalid GRPC_TLS_KEY /missing/widgets-key.pem")));
+        assert!(errors.contains(&"Missing GRPC_TLS_CA".to_string()));
+
+        env::set_var("GRPC_TLS_KEY", &cert_path);
+        env::set_var("GRPC_TLS_CA", &cert_path);
+        let tls = AppConfig::load("widgets")
+            .unwrap()
+            .service_auth
+            .tls
+            .unwrap();
+        assert_eq!(tls.cert, "widgets certificate");
+        assert_eq!(tls.ca, "widgets certificate");
+
+        env::remove_var("CONFIG_FILE");
+        env::remove_var("WIDGETS_HTTP_PORT");
+        env::remove_var("RATE_LIMITS");
+        env::remove_var("GRPC_TLS_CERT");
+        env::remove_var("GRPC_TLS_KEY");
+        env::remove_var("GRPC_TLS_CA");
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(cert_path);
+    }
+}
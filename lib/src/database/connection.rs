@@ -0,0 +1,235 @@
+use std::{
+    io::{Error, ErrorKind},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+
+use surrealdb::{
+    engine::any::{self, Any},
+    opt::auth::{Database, Namespace, Root},
+    Response, Surreal,
+};
+
+use super::migrations::{run_migrations, Migration, MigrationMode};
+
+/// The level a database user is defined on. Services should use namespace or database users in production.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DbAuthLevel {
+    Root,
+    Namespace,
+    Database,
+}
+
+/// Everything needed to connect to a service's database. Part of the service's `lib::config::AppConfig`.
+#[derive(Clone, Debug)]
+pub struct DbConfig {
+    pub host: String,
+    pub port: String,
+    pub namespace: String,
+    pub database: String,
+    pub username: String,
+    pub password: String,
+    pub auth_level: DbAuthLevel,
+    /// How many times to retry the initial connection before giving up
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// How often the connection is probed and re-established if the session was lost
+    pub health_check_interval: Duration,
+    /// Anything but `Apply` only reports the migrations, the service exits after
+    pub migrations_mode: MigrationMode,
+}
+
+impl FromStr for DbAuthLevel {
+    type Err = Error;
+
+    fn from_str(auth_level: &str) -> Result<Self, Self::Err> {
+        match auth_level.to_lowercase().as_str() {
+            "root" => Ok(DbAuthLevel::Root),
+            "namespace" | "ns" => Ok(DbAuthLevel::Namespace),
+            "database" | "db" => Ok(DbAuthLevel::Database),
+            other => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Invalid database auth level: {}", other),
+            )),
+        }
+    }
+}
+
+impl DbConfig {
+    /// The engine URL. Hosts without a scheme use the websocket engine, e.g. "mem://" selects the in-memory engine.
+    pub fn url(&self) -> String {
+        if self.host.contains("://") {
+            match self.port.as_str() {
+                "" => self.host.clone(),
+                port => format!("{}:{}", self.host, port),
+            }
+        } else {
+            format!("ws://{}:{}", self.host, self.port)
+        }
+    }
+}
+
+/// Connect to the database, retrying with exponential backoff until `max_retries` is reached.
+/// A background task keeps probing the connection and re-establishes the session when it is lost.
+pub async fn connect(config: &DbConfig) -> Result<Arc<Surreal<Any>>, Error> {
+    let mut backoff = config.initial_backoff;
+    let mut attempt = 0;
+
+    tracing::info!("Connecting to the database at {}", config.url());
+
+    let db = loop {
+        attempt += 1;
+
+        match try_connect(config).await {
+            Ok(db) => break Arc::new(db),
+            Err(e) if attempt <= config.max_retries => {
+                tracing::warn!(
+                    "Database connection attempt {} failed: {}. Retrying in {:?}",
+                    attempt,
+                    e,
+                    backoff
+                );
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(config.max_backoff);
+            }
+            Err(e) => {
+                tracing::error!(
+                    "Giving up on the database after {} attempts: {}",
+                    attempt,
+                    e
+                );
+                return Err(e);
+            }
+        }
+    };
+
+    tokio::spawn(keep_alive(db.clone(), config.clone()));
+
+    Ok(db)
+}
+
+/// Connect to a service's database and apply its pending migrations. The dry-run and status modes
+/// only report them, the service exits after.
+pub async fn connect_and_migrate(
+    config: &DbConfig,
+    migrations: &[Migration],
+) -> Result<Arc<Surreal<Any>>, Error> {
+    let db = connect(config).await?;
+
+    let migration_statuses = run_migrations(&db, migrations, config.migrations_mode).await?;
+
+    for migration_status in &migration_statuses {
+        tracing::info!("Migration {}", migration_status);
+    }
+
+    Ok(db)
+}
+
+async fn try_connect(config: &DbConfig) -> Result<Surreal<Any>, Error> {
+    let db = any::connect(config.url()).await.map_err(|e| {
+        Error::new(
+            ErrorKind::ConnectionRefused,
+            format!("Failed to connect to {}: {}", config.url(), e),
+        )
+    })?;
+
+    establish_session(&db, config).await?;
+
+    Ok(db)
+}
+
+/// Sign in and select the namespace and database
+async fn establish_session(db: &Surreal<Any>, config: &DbConfig) -> Result<(), Error> {
+    let signin_result = match config.auth_level {
+        DbAuthLevel::Root => db
+            .signin(Root {
+                username: config.username.as_str(),
+                password: config.password.as_str(),
+            })
+            .await
+            .map(|_| ()),
+        DbAuthLevel::Namespace => db
+            .signin(Namespace {
+                namespace: config.namespace.as_str(),
+                username: config.username.as_str(),
+                password: config.password.as_str(),
+            })
+            .await
+            .map(|_| ()),
+        DbAuthLevel::Database => db
+            .signin(Database {
+                namespace: config.namespace.as_str(),
+                database: config.database.as_str(),
+                username: config.username.as_str(),
+                password: config.password.as_str(),
+            })
+            .await
+            .map(|_| ()),
+    };
+
+    signin_result.map_err(|e| {
+        Error::new(
+            ErrorKind::PermissionDenied,
+            format!("Database sign in failed: {}", e),
+        )
+    })?;
+
+    db.use_ns(config.namespace.as_str())
+        .use_db(config.database.as_str())
+        .await
+        .map_err(|e| {
+            Error::other(format!(
+                "Failed to select the namespace and database: {}",
+                e
+            ))
+        })?;
+
+    Ok(())
+}
+
+/// Health probe. Succeeds only if the database is reachable and the session is signed in with a database selected.
+pub async fn ping(db: &Surreal<Any>) -> Result<(), Error> {
+    db.query("INFO FOR DB")
+        .await
+        .and_then(Response::check)
+        .map(|_| ())
+        .map_err(|e| {
+            Error::new(
+                ErrorKind::NotConnected,
+                format!("Database ping failed: {}", e),
+            )
+        })
+}
+
+/// Probe the connection periodically. The websocket client reconnects on its own, but a restarted
+/// SurrealDB instance forgets the session, so it is re-established here.
+async fn keep_alive(db: Arc<Surreal<Any>>, config: DbConfig) {
+    let mut backoff = config.initial_backoff;
+
+    loop {
+        tokio::time::sleep(config.health_check_interval).await;
+
+        if ping(&db).await.is_ok() {
+            backoff = config.initial_backoff;
+            continue;
+        }
+
+        tracing::warn!("Lost the database session, re-establishing it");
+
+        loop {
+            match establish_session(&db, &config).await {
+                Ok(_) => {
+                    tracing::info!("Database session re-established");
+                    break;
+                }
+                Err(e) => {
+                    tracing::error!("Failed to re-establish the database session: {}", e);
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(config.max_backoff);
+                }
+            }
+        }
+    }
+}
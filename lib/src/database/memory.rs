@@ -0,0 +1,27 @@
+use std::{io::Error, sync::Arc};
+
+use surrealdb::{
+    engine::any::{self, Any},
+    Surreal,
+};
+
+use super::migrations::{run_migrations, Migration, MigrationMode};
+
+/// Start a fresh in-memory database with the migrations applied. Every call is isolated, so tests can
+/// exercise the database layer end-to-end without a running SurrealDB.
+pub async fn connect_in_memory(migrations: &[Migration]) -> Result<Arc<Surreal<Any>>, Error> {
+    let db = any::connect("mem://")
+        .await
+        .map_err(|e| Error::other(format!("Failed to start the in-memory database: {}", e)))?;
+
+    db.use_ns("test").use_db("test").await.map_err(|e| {
+        Error::other(format!(
+            "Failed to select the namespace and database: {}",
+            e
+        ))
+    })?;
+
+    run_migrations(&db, migrations, MigrationMode::Apply).await?;
+
+    Ok(Arc::new(db))
+}
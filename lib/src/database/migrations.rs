@@ -0,0 +1,371 @@
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{Error, ErrorKind},
+    str::FromStr,
+};
+
+use surrealdb::Response;
+
+use crate::utils::custom_traits::AsSurrealClient;
+
+/// A versioned `.surql` migration. Services embed their migrations with `include_str!` so the binary
+/// carries its own schema. An applied migration must never be edited, add a new one instead.
+#[derive(Clone, Copy, Debug)]
+pub struct Migration {
+    pub version: u32,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+impl Migration {
+    /// The hex encoded SHA-256 of the migration's SQL
+    pub fn checksum(&self) -> String {
+        hex::encode(Sha256::digest(self.sql.as_bytes()))
+    }
+}
+
+/// What the runner does with pending migrations. Set with DATABASE_MIGRATIONS_MODE, see `lib::config`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MigrationMode {
+    /// Apply the pending migrations (default)
+    Apply,
+    /// Validate the applied migrations and report the pending ones without applying them
+    DryRun,
+    /// Report the state of every migration
+    Status,
+}
+
+impl FromStr for MigrationMode {
+    type Err = Error;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode.to_lowercase().as_str() {
+            "apply" => Ok(MigrationMode::Apply),
+            "dry-run" | "dry_run" | "dryrun" => Ok(MigrationMode::DryRun),
+            "status" => Ok(MigrationMode::Status),
+            other => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Invalid migrations mode: {}", other),
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MigrationState {
+    Applied,
+    Pending,
+    ChecksumMismatch,
+}
+
+#[derive(Clone, Debug)]
+pub struct MigrationStatus {
+    pub version: u32,
+    pub name: String,
+    pub checksum: String,
+    pub applied_at: Option<String>,
+    pub state: MigrationState,
+}
+
+impl fmt::Display for MigrationStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}_{} [{:?}] {}",
+            self.version,
+            self.name,
+            self.state,
+            self.applied_at.as_deref().unwrap_or("-")
+        )
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AppliedMigration {
+    version: u32,
+    name: String,
+    checksum: String,
+    applied_at: String,
+}
+
+/// Run the migrations against the database according to the mode. Refuses to continue if an applied
+/// migration's checksum changed or if the migrations are not in strictly ascending version order.
+pub async fn run_migrations<T: AsSurrealClient>(
+    db: &T,
+    migrations: &[Migration],
+    mode: MigrationMode,
+) -> Result<Vec<MigrationStatus>, Error> {
+    if migrations
+        .windows(2)
+        .any(|pair| pair[0].version >= pair[1].version)
+    {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Migrations must have strictly ascending versions",
+        ));
+    }
+
+    if mode == MigrationMode::Apply {
+        db.as_client()
+            .query(
+                "
+                DEFINE TABLE IF NOT EXISTS _migrations SCHEMAFULL;
+                DEFINE FIELD IF NOT EXISTS version ON TABLE _migrations TYPE int;
+                DEFINE FIELD IF NOT EXISTS name ON TABLE _migrations TYPE string;
+                DEFINE FIELD IF NOT EXISTS checksum ON TABLE _migrations TYPE string;
+                DEFINE FIELD IF NOT EXISTS applied_at ON TABLE _migrations TYPE datetime DEFAULT time::now() READONLY;
+                DEFINE INDEX IF NOT EXISTS migrationVersionIndex ON TABLE _migrations COLUMNS version UNIQUE;
+                ",
+            )
+            .await
+            .and_then(Response::check)
+            .map_err(|e| {
+                tracing::error!("DB Query Failed: {}", e);
+                Error::other("Failed to create the migrations table")
+            })?;
+    }
+
+    let mut applied = get_applied_migrations(db).await?;
+
+    let mut statuses = vec![];
+
+    for migration in migrations {
+        let checksum = migration.checksum();
+
+        match applied.remove(&migration.version) {
+            Some(applied_migration) if applied_migration.checksum == checksum => {
+                statuses.push(MigrationStatus {
+                    version: migration.version,
+                    name: applied_migration.name,
+                    checksum,
+                    applied_at: Some(applied_migration.applied_at),
+                    state: MigrationState::Applied,
+                });
+            }
+            Some(applied_migration) => {
+                if mode != MigrationMode::Status {
+                    tracing::error!(
+                        "Checksum of applied migration {:04}_{} changed",
+                        migration.version,
+                        migration.name
+                    );
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!(
+                            "Checksum of applied migration {:04}_{} changed",
+                            migration.version, migration.name
+                        ),
+                    ));
+                }
+
+                statuses.push(MigrationStatus {
+                    version: migration.version,
+                    name: applied_migration.name,
+                    checksum,
+                    applied_at: Some(applied_migration.applied_at),
+                    state: MigrationState::ChecksumMismatch,
+                });
+            }
+            None if mode == MigrationMode::Apply => {
+                let applied_at = apply_migration(db, migration, &checksum).await?;
+
+                tracing::info!("Applied migration {:04}_{}", migration.version, migration.name);
+
+                statuses.push(MigrationStatus {
+                    version: migration.version,
+                    name: migration.name.to_string(),
+                    checksum,
+                    applied_at: Some(applied_at),
+                    state: MigrationState::Applied,
+                });
+            }
+            None => statuses.push(MigrationStatus {
+                version: migration.version,
+                name: migration.name.to_string(),
+                checksum,
+                applied_at: None,
+                state: MigrationState::Pending,
+            }),
+        }
+    }
+
+    // Migrations recorded by a newer build, e.g. after a rollback
+    for unknown in applied.values() {
+        tracing::warn!(
+            "Applied migration {:04}_{} is unknown to this build",
+            unknown.version,
+            unknown.name
+        );
+    }
+
+    Ok(statuses)
+}
+
+async fn get_applied_migrations<T: AsSurrealClient>(
+    db: &T,
+) -> Result<HashMap<u32, AppliedMigration>, Error> {
+    let mut applied_query = db
+        .as_client()
+        .query("SELECT version, name, checksum, <string> applied_at AS applied_at FROM _migrations")
+        .await
+        .map_err(|e| {
+            tracing::error!("DB Query Failed: {}", e);
+            Error::other("DB Query Failed")
+        })?;
+
+    let applied: Vec<AppliedMigration> = applied_query.take(0).map_err(|e| {
+        tracing::error!("Deserialization Failed: {}", e);
+        Error::other("Deserialization Failed")
+    })?;
+
+    Ok(applied
+        .into_iter()
+        .map(|migration| (migration.version, migration))
+        .collect())
+}
+
+/// Apply a migration and record it in a single transaction. Returns when it was applied.
+async fn apply_migration<T: AsSurrealClient>(
+    db: &T,
+    migration: &Migration,
+    checksum: &str,
+) -> Result<String, Error> {
+    let statements = migration.sql.trim_end();
+    let separator = if statements.ends_with(';') { "" } else { ";" };
+
+    let mut apply_query = db
+        .as_client()
+        .query(format!(
+            "
+            BEGIN TRANSACTION;
+            {}{}
+            LET $record = (CREATE ONLY _migrations CONTENT {{
+                version: $version,
+                name: $name,
+                checksum: $checksum
+            }});
+            RETURN <string> $record.applied_at;
+            COMMIT TRANSACTION;
+            ",
+            statements, separator
+        ))
+        .bind(("version", migration.version))
+        .bind(("name", migration.name))
+        .bind(("checksum", checksum.to_string()))
+        .await
+        .and_then(Response::check)
+        .map_err(|e| {
+            tracing::error!(
+                "Migration {:04}_{} failed: {}",
+                migration.version,
+                migration.name,
+                e
+            );
+            Error::other(format!(
+                "Migration {:04}_{} failed",
+                migration.version, migration.name
+            ))
+        })?;
+
+    let applied_at: Option<String> = apply_query.take(0).map_err(|e| {
+        tracing::error!("Deserialization Failed: {}", e);
+        Error::other("Deserialization Failed")
+    })?;
+
+    Ok(applied_at.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::memory::connect_in_memory;
+
+    const CREATE_WIDGET: Migration = Migration {
+        version: 1,
+        name: "create_widget",
+        sql: "DEFINE TABLE widget SCHEMAFULL; DEFINE FIELD name ON TABLE widget TYPE string;",
+    };
+
+    #[tokio::test]
+    async fn applies_pending_migrations_once() {
+        let db = connect_in_memory(&[CREATE_WIDGET]).await.unwrap();
+
+        let statuses = run_migrations(&db, &[CREATE_WIDGET], MigrationMode::Apply)
+            .await
+            .unwrap();
+
+        assert_eq!(statuses.len(), 1);
+        assert_eq!(statuses[0].state, MigrationState::Applied);
+
+        let mut count_query = db
+            .query("RETURN count(SELECT * FROM _migrations)")
+            .await
+            .unwrap();
+        let count: Option<u64> = count_query.take(0).unwrap();
+        assert_eq!(count, Some(1));
+    }
+
+    #[tokio::test]
+    async fn dry_run_reports_without_applying() {
+        let db = connect_in_memory(&[CREATE_WIDGET]).await.unwrap();
+
+        let add_price = Migration {
+            version: 2,
+            name: "add_price",
+            sql: "DEFINE FIELD price ON TABLE widget TYPE int;",
+        };
+
+        let statuses = run_migrations(&db, &[CREATE_WIDGET, add_price], MigrationMode::DryRun)
+            .await
+            .unwrap();
+
+        assert_eq!(statuses[0].state, MigrationState::Applied);
+        assert_eq!(statuses[1].state, MigrationState::Pending);
+
+        let statuses = run_migrations(&db, &[CREATE_WIDGET, add_price], MigrationMode::Status)
+            .await
+            .unwrap();
+        assert_eq!(statuses[1].state, MigrationState::Pending);
+    }
+
+    #[tokio::test]
+    async fn refuses_changed_migrations() {
+        let db = connect_in_memory(&[CREATE_WIDGET]).await.unwrap();
+
+        let edited = Migration {
+            sql: "DEFINE TABLE widget SCHEMALESS;",
+            ..CREATE_WIDGET
+        };
+
+        let result = run_migrations(&db, &[edited], MigrationMode::Apply).await;
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidData);
+
+        let statuses = run_migrations(&db, &[edited], MigrationMode::Status)
+            .await
+            .unwrap();
+        assert_eq!(statuses[0].state, MigrationState::ChecksumMismatch);
+    }
+
+    #[tokio::test]
+    async fn failed_migrations_are_not_recorded() {
+        let broken = Migration {
+            version: 1,
+            name: "broken",
+            sql: "THROW 'broken';",
+        };
+
+        let db = connect_in_memory(&[]).await.unwrap();
+
+        assert!(run_migrations(&db, &[broken], MigrationMode::Apply)
+            .await
+            .is_err());
+
+        let statuses = run_migrations(&db, &[broken], MigrationMode::Status)
+            .await
+            .unwrap();
+        assert_eq!(statuses[0].state, MigrationState::Pending);
+    }
+}
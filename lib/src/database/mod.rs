@@ -0,0 +1,4 @@
+pub mod connection;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod memory;
+pub mod migrations;
@@ -0,0 +1,54 @@
+use serde::Serialize;
+
+use super::EmailTemplate;
+
+/// Sent once Paystack confirmed the payment of an order
+#[derive(Clone, Debug, Serialize)]
+pub struct OrderConfirmation {
+    /// The Paystack reference, i.e. the order ID
+    pub reference: String,
+    pub download_url: String,
+}
+
+impl EmailTemplate for OrderConfirmation {
+    const NAME: &'static str = "order_confirmation";
+}
+
+/// Sent once the payment of an order was refunded
+#[derive(Clone, Debug, Serialize)]
+pub struct Refund {
+    pub reference: String,
+    /// In the currency's subunit, as Paystack sends it
+    pub amount: u64,
+    pub currency: String,
+    pub reason: Option<String>,
+}
+
+impl EmailTemplate for Refund {
+    const NAME: &'static str = "refund";
+}
+
+/// A reminder about a cart that wasn't checked out
+#[derive(Clone, Debug, Serialize)]
+pub struct AbandonedCart {
+    /// The names of the products in the cart
+    pub products: Vec<String>,
+    pub cart_url: String,
+}
+
+impl EmailTemplate for AbandonedCart {
+    const NAME: &'static str = "abandoned_cart";
+}
+
+/// Someone commented on a product the recipient owns or commented on
+#[derive(Clone, Debug, Serialize)]
+pub struct NewComment {
+    pub author_name: String,
+    pub product_name: String,
+    pub comment: String,
+    pub product_url: String,
+}
+
+impl EmailTemplate for NewComment {
+    const NAME: &'static str = "new_comment";
+}
@@ -0,0 +1,324 @@
+//! The emails sent to customers, rendered here before they're handed to the email service's
+//! `SendEmail`. Every email has a typed context in `contexts` and a template pair per locale in
+//! `templates/<locale>/`: the plain-text version, which also holds the `subject` and `title` blocks,
+//! and the HTML one. Both extend the locale's layout.
+
+pub mod contexts;
+
+use std::sync::LazyLock;
+
+use minijinja::{context, Environment, Template, Value};
+use serde::Serialize;
+
+use crate::{config::EmailConfig, error::AppError};
+
+/// The languages the emails are written in. A template a locale doesn't have yet falls back to
+/// English.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Locale {
+    #[default]
+    En,
+    Fr,
+}
+
+impl Locale {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::Fr => "fr",
+        }
+    }
+
+    /// The locale of a language tag such as `fr-FR`, English for the languages without templates
+    pub fn from_tag(tag: &str) -> Self {
+        let language = tag.split(['-', '_']).next().unwrap_or_default();
+
+        match language.to_ascii_lowercase().as_str() {
+            "fr" => Locale::Fr,
+            _ => Locale::En,
+        }
+    }
+
+    /// The locale of the preferred language of an `Accept-Language` header such as
+    /// `fr-FR,fr;q=0.9,en;q=0.8`
+    pub fn from_accept_language(header: &str) -> Self {
+        let preferred = header.split([',', ';']).next().unwrap_or_default();
+
+        Self::from_tag(preferred.trim())
+    }
+}
+
+/// The context of an email, rendered with `templates/<locale>/<NAME>.txt` and `.html`
+pub trait EmailTemplate: Serialize {
+    const NAME: &'static str;
+}
+
+/// An email ready for `SendEmail`
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderedEmail {
+    pub subject: String,
+    pub title: String,
+    pub html: String,
+    pub text: String,
+}
+
+const TEMPLATE_SOURCES: &[(&str, &str)] = &[
+    ("components.html", include_str!("templates/components.html")),
+    ("en/layout.html", include_str!("templates/en/layout.html")),
+    ("en/layout.txt", include_str!("templates/en/layout.txt")),
+    (
+        "en/order_confirmation.html",
+        include_str!("templates/en/order_confirmation.html"),
+    ),
+    (
+        "en/order_confirmation.txt",
+        include_str!("templates/en/order_confirmation.txt"),
+    ),
+    ("en/refund.html", include_str!("templates/en/refund.html")),
+    ("en/refund.txt", include_str!("templates/en/refund.txt")),
+    (
+        "en/abandoned_cart.html",
+        include_str!("templates/en/abandoned_cart.html"),
+    ),
+    (
+        "en/abandoned_cart.txt",
+        include_str!("templates/en/abandoned_cart.txt"),
+    ),
+    (
+        "en/new_comment.html",
+        include_str!("templates/en/new_comment.html"),
+    ),
+    (
+        "en/new_comment.txt",
+        include_str!("templates/en/new_comment.txt"),
+    ),
+    ("fr/layout.html", include_str!("templates/fr/layout.html")),
+    ("fr/layout.txt", include_str!("templates/fr/layout.txt")),
+    (
+        "fr/order_confirmation.html",
+        include_str!("templates/fr/order_confirmation.html"),
+    ),
+    (
+        "fr/order_confirmation.txt",
+        include_str!("templates/fr/order_confirmation.txt"),
+    ),
+    ("fr/refund.html", include_str!("templates/fr/refund.html")),
+    ("fr/refund.txt", include_str!("templates/fr/refund.txt")),
+    (
+        "fr/abandoned_cart.html",
+        include_str!("templates/fr/abandoned_cart.html"),
+    ),
+    (
+        "fr/abandoned_cart.txt",
+        include_str!("templates/fr/abandoned_cart.txt"),
+    ),
+    (
+        "fr/new_comment.html",
+        include_str!("templates/fr/new_comment.html"),
+    ),
+    (
+        "fr/new_comment.txt",
+        include_str!("templates/fr/new_comment.txt"),
+    ),
+];
+
+/// Every template, parsed once. The `.html` ones are auto-escaped.
+static TEMPLATES: LazyLock<Environment<'static>> = LazyLock::new(|| {
+    let mut env = Environment::new();
+    env.set_trim_blocks(true);
+    env.set_lstrip_blocks(true);
+    env.add_filter("money", money);
+
+    for (name, source) in TEMPLATE_SOURCES {
+        env.add_template(name, source)
+            .expect("The email templates are valid");
+    }
+
+    env
+});
+
+/// Render an email in the recipient's locale, signed with the configured sender name
+pub fn render_email<T: EmailTemplate>(
+    config: &EmailConfig,
+    locale: Locale,
+    email: &T,
+) -> Result<RenderedEmail, AppError> {
+    let context = context! {
+        sender_name => &config.sender_name,
+        ..Value::from_serialize(email)
+    };
+
+    let text_template = template(locale, T::NAME, "txt")?;
+    let (subject, title) = text_template
+        .render_captured(&context)
+        .and_then(|mut captured| {
+            captured.with_state_mut(|state| {
+                Ok((state.render_block("subject")?, state.render_block("title")?))
+            })
+        })
+        .map_err(render_error)?;
+
+    let context = context! {
+        subject => subject.trim(),
+        title => title.trim(),
+        ..context
+    };
+
+    Ok(RenderedEmail {
+        subject: subject.trim().to_string(),
+        title: title.trim().to_string(),
+        html: template(locale, T::NAME, "html")?
+            .render(&context)
+            .map_err(render_error)?,
+        text: text_template.render(&context).map_err(render_error)?,
+    })
+}
+
+fn template(
+    locale: Locale,
+    name: &str,
+    extension: &str,
+) -> Result<Template<'static, 'static>, AppError> {
+    TEMPLATES
+        .get_template(&format!("{}/{}.{}", locale.as_str(), name, extension))
+        .or_else(|_| {
+            TEMPLATES.get_template(&format!("{}/{}.{}", Locale::En.as_str(), name, extension))
+        })
+        .map_err(render_error)
+}
+
+fn render_error(e: minijinja::Error) -> AppError {
+    AppError::internal("Failed to render email", e)
+}
+
+/// `{{ amount | money(currency) }}`, an amount in the currency's subunit as Paystack sends them
+fn money(amount: u64, currency: &str) -> String {
+    format!("{} {}.{:02}", currency, amount / 100, amount % 100)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::email::contexts::{AbandonedCart, NewComment, OrderConfirmation, Refund};
+
+    fn config() -> EmailConfig {
+        EmailConfig::default()
+    }
+
+    /// Both versions of an email in one snapshot
+    fn snapshot(email: &RenderedEmail) -> String {
+        format!(
+            "Subject: {}\nTitle: {}\n\n{}\n\n---\n\n{}",
+            email.subject, email.title, email.text, email.html
+        )
+    }
+
+    fn order_confirmation() -> OrderConfirmation {
+        OrderConfirmation {
+            reference: "order-1".to_string(),
+            download_url: "https://rustytemplates.com/account".to_string(),
+        }
+    }
+
+    #[test]
+    fn order_confirmation_email() {
+        let email = render_email(&config(), Locale::En, &order_confirmation()).unwrap();
+        assert_eq!(email.subject, "Payment Confirmation");
+        insta::assert_snapshot!(snapshot(&email));
+    }
+
+    #[test]
+    fn order_confirmation_email_in_french() {
+        let email =
+            render_email(&config(), Locale::from_tag("fr-FR"), &order_confirmation()).unwrap();
+        assert_eq!(email.subject, "Confirmation de paiement");
+        insta::assert_snapshot!(snapshot(&email));
+    }
+
+    fn refund() -> Refund {
+        Refund {
+            reference: "order-1".to_string(),
+            amount: 150050,
+            currency: "NGN".to_string(),
+            reason: Some("Duplicate payment".to_string()),
+        }
+    }
+
+    fn abandoned_cart() -> AbandonedCart {
+        AbandonedCart {
+            products: vec!["Axum starter".to_string(), "Leptos dashboard".to_string()],
+            cart_url: "https://rustytemplates.com/cart".to_string(),
+        }
+    }
+
+    fn new_comment() -> NewComment {
+        NewComment {
+            author_name: "Ferris".to_string(),
+            product_name: "Axum starter".to_string(),
+            comment: "Does it support <b>websockets</b>?".to_string(),
+            product_url: "https://rustytemplates.com/products/axum-starter".to_string(),
+        }
+    }
+
+    #[test]
+    fn refund_email() {
+        let email = render_email(&config(), Locale::En, &refund()).unwrap();
+        assert!(email.text.contains("NGN 1500.50"));
+        insta::assert_snapshot!(snapshot(&email));
+    }
+
+    #[test]
+    fn refund_email_in_french() {
+        let email = render_email(&config(), Locale::Fr, &refund()).unwrap();
+        assert_eq!(email.subject, "Remboursement effectué");
+        assert_eq!(email.title, "Votre remboursement est en route");
+        insta::assert_snapshot!(snapshot(&email));
+    }
+
+    #[test]
+    fn abandoned_cart_email() {
+        let email = render_email(&config(), Locale::En, &abandoned_cart()).unwrap();
+        insta::assert_snapshot!(snapshot(&email));
+    }
+
+    #[test]
+    fn abandoned_cart_email_in_french() {
+        let email = render_email(&config(), Locale::Fr, &abandoned_cart()).unwrap();
+        assert_eq!(
+            email.subject,
+            "Vous avez oublié quelque chose dans votre panier"
+        );
+        assert_eq!(email.title, "Toujours en réflexion ?");
+        insta::assert_snapshot!(snapshot(&email));
+    }
+
+    #[test]
+    fn new_comment_email() {
+        let email = render_email(&config(), Locale::En, &new_comment()).unwrap();
+        // Only the HTML version is escaped
+        assert!(email.html.contains("&lt;b&gt;websockets&lt;&#x2f;b&gt;"));
+        assert!(email.text.contains("<b>websockets</b>"));
+        insta::assert_snapshot!(snapshot(&email));
+    }
+
+    #[test]
+    fn new_comment_email_in_french() {
+        let email = render_email(&config(), Locale::Fr, &new_comment()).unwrap();
+        assert_eq!(email.subject, "Nouveau commentaire sur Axum starter");
+        assert_eq!(email.title, "Ferris a commenté Axum starter");
+        insta::assert_snapshot!(snapshot(&email));
+    }
+
+    #[test]
+    fn unsupported_languages_get_english() {
+        assert_eq!(Locale::from_tag("fr"), Locale::Fr);
+        assert_eq!(Locale::from_tag("FR_ca"), Locale::Fr);
+        assert_eq!(Locale::from_tag("de-DE"), Locale::En);
+        assert_eq!(Locale::from_tag(""), Locale::En);
+        assert_eq!(
+            Locale::from_accept_language("fr-FR,fr;q=0.9,en;q=0.8"),
+            Locale::Fr
+        );
+        assert_eq!(Locale::from_accept_language("de;q=1, fr"), Locale::En);
+    }
+}
@@ -0,0 +1,241 @@
+use std::fmt::Debug;
+
+use async_graphql::ErrorExtensions;
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use tonic::{Code, Status};
+
+use crate::utils::resilience::CircuitOpen;
+
+/// The error every service returns, whatever the transport. Each variant has a machine-readable code
+/// (`NOT_FOUND`, `FORBIDDEN`...) that clients can branch on, and converts into a GraphQL error, a gRPC
+/// `Status` or a JSON response with the matching status.
+///
+/// It deliberately doesn't implement `Display`: async-graphql converts anything displayable into a bare
+/// error, which would drop the code. Use `message()` to log or show it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AppError {
+    NotFound(String),
+    Unauthorized(String),
+    Forbidden(String),
+    Validation(String),
+    Conflict(String),
+    /// Another service or a third party API failed or could not be reached
+    Upstream(String),
+    /// Another service is known to be down, calls to it fail fast until it recovers
+    Unavailable(String),
+    /// The operation ran out of time, e.g. a GraphQL operation over its budget
+    Timeout(String),
+    /// The client sent too many requests, see `middleware::rate_limit`
+    RateLimited(String),
+    /// The cause is logged, clients only see a generic message
+    Database(String),
+    /// The cause is logged, clients only see a generic message
+    Internal(String),
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorBody<'a> {
+    code: &'static str,
+    message: &'a str,
+}
+
+impl AppError {
+    /// Log a failed database query
+    pub fn database(e: impl Debug) -> Self {
+        tracing::error!("DB Query Failed: {:?}", e);
+        AppError::Database("DB Query Failed".into())
+    }
+
+    /// Log a query result that could not be deserialized
+    pub fn deserialization(e: impl Debug) -> Self {
+        tracing::error!("Deserialization Failed: {:?}", e);
+        AppError::Database("Deserialization Failed".into())
+    }
+
+    /// Log an unexpected failure, e.g. a missing key or a serialization error
+    pub fn internal(context: &str, e: impl Debug) -> Self {
+        tracing::error!("{}: {:?}", context, e);
+        AppError::Internal(context.into())
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::NotFound(_) => "NOT_FOUND",
+            AppError::Unauthorized(_) => "UNAUTHORIZED",
+            AppError::Forbidden(_) => "FORBIDDEN",
+            AppError::Validation(_) => "VALIDATION_FAILED",
+            AppError::Conflict(_) => "CONFLICT",
+            AppError::Upstream(_) => "UPSTREAM_FAILED",
+            AppError::Unavailable(_) => "SERVICE_UNAVAILABLE",
+            AppError::Timeout(_) => "TIMEOUT",
+            AppError::RateLimited(_) => "RATE_LIMITED",
+            AppError::Database(_) => "DATABASE_ERROR",
+            AppError::Internal(_) => "INTERNAL_ERROR",
+        }
+    }
+
+    /// The message that is safe to send to clients
+    pub fn message(&self) -> &str {
+        match self {
+            AppError::NotFound(message)
+            | AppError::Unauthorized(message)
+            | AppError::Forbidden(message)
+            | AppError::Validation(message)
+            | AppError::Conflict(message)
+            | AppError::Upstream(message)
+            | AppError::Unavailable(message)
+            | AppError::Timeout(message)
+            | AppError::RateLimited(message) => message,
+            AppError::Database(_) | AppError::Internal(_) => "Internal server error",
+        }
+    }
+
+    pub fn http_status(&self) -> StatusCode {
+        match self {
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::Validation(_) => StatusCode::BAD_REQUEST,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::Upstream(_) => StatusCode::BAD_GATEWAY,
+            AppError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            AppError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::Database(_) | AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn grpc_code(&self) -> Code {
+        match self {
+            AppError::NotFound(_) => Code::NotFound,
+            AppError::Unauthorized(_) => Code::Unauthenticated,
+            AppError::Forbidden(_) => Code::PermissionDenied,
+            AppError::Validation(_) => Code::InvalidArgument,
+            AppError::Conflict(_) => Code::AlreadyExists,
+            AppError::Upstream(_) | AppError::Unavailable(_) => Code::Unavailable,
+            AppError::Timeout(_) => Code::DeadlineExceeded,
+            AppError::RateLimited(_) => Code::ResourceExhausted,
+            AppError::Database(_) | AppError::Internal(_) => Code::Internal,
+        }
+    }
+}
+
+/// The `code` extension is the machine-readable code. `status` carries the HTTP status like
+/// `ExtendedError` does, so existing clients keep working.
+impl From<AppError> for async_graphql::Error {
+    fn from(error: AppError) -> Self {
+        async_graphql::Error::new(error.message()).extend_with(|_err, e| {
+            e.set("code", error.code());
+            e.set("status", error.http_status().as_u16().to_string());
+        })
+    }
+}
+
+impl From<AppError> for Status {
+    fn from(error: AppError) -> Self {
+        Status::new(error.grpc_code(), error.message())
+    }
+}
+
+/// A `{ "code": ..., "message": ... }` body with the matching HTTP status
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let body = ErrorBody {
+            code: self.code(),
+            message: self.message(),
+        };
+
+        (self.http_status(), Json(body)).into_response()
+    }
+}
+
+impl From<surrealdb::Error> for AppError {
+    fn from(e: surrealdb::Error) -> Self {
+        AppError::database(e)
+    }
+}
+
+/// A failed call to another service. Codes the caller can act on are kept, and so are the calls
+/// failed fast by an open circuit breaker.
+impl From<Status> for AppError {
+    fn from(status: Status) -> Self {
+        let message = status.message().to_string();
+
+        if std::error::Error::source(&status).is_some_and(|source| source.is::<CircuitOpen>()) {
+            return AppError::Unavailable(message);
+        }
+
+        match status.code() {
+            Code::NotFound => AppError::NotFound(message),
+            Code::Unauthenticated => AppError::Unauthorized(message),
+            Code::PermissionDenied => AppError::Forbidden(message),
+            Code::InvalidArgument => AppError::Validation(message),
+            Code::AlreadyExists => AppError::Conflict(message),
+            Code::DeadlineExceeded => AppError::Timeout(message),
+            Code::ResourceExhausted => AppError::RateLimited(message),
+            _ => {
+                tracing::error!("Upstream call failed: {:?}", status);
+                AppError::Upstream("Upstream service failed".into())
+            }
+        }
+    }
+}
+
+/// For the helpers that still return `std::io::Error`
+impl From<std::io::Error> for AppError {
+    fn from(e: std::io::Error) -> Self {
+        let message = e.to_string();
+
+        match e.kind() {
+            std::io::ErrorKind::NotFound => AppError::NotFound(message),
+            std::io::ErrorKind::PermissionDenied => AppError::Forbidden(message),
+            std::io::ErrorKind::InvalidInput | std::io::ErrorKind::InvalidData => {
+                AppError::Validation(message)
+            }
+            std::io::ErrorKind::AlreadyExists => AppError::Conflict(message),
+            _ => AppError::Internal(message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_to_every_transport() {
+        let graphql_error: async_graphql::Error =
+            AppError::Forbidden("Not the owner".into()).into();
+        let extensions = graphql_error.extensions.unwrap();
+        assert_eq!(graphql_error.message, "Not the owner");
+        assert_eq!(extensions.get("code"), Some(&"FORBIDDEN".into()));
+        assert_eq!(extensions.get("status"), Some(&"403".into()));
+
+        let status: Status = AppError::NotFound("Order Not Found".into()).into();
+        assert_eq!(status.code(), Code::NotFound);
+        assert_eq!(status.message(), "Order Not Found");
+
+        let response = AppError::database("connection reset").into_response();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let status: Status = AppError::database("connection reset").into();
+        assert_eq!(status.message(), "Internal server error");
+    }
+
+    #[test]
+    fn keeps_actionable_upstream_codes() {
+        assert_eq!(
+            AppError::from(Status::unauthenticated("Expired token")),
+            AppError::Unauthorized("Expired token".into())
+        );
+        assert_eq!(
+            AppError::from(Status::unavailable("connection refused")).code(),
+            "UPSTREAM_FAILED"
+        );
+    }
+}
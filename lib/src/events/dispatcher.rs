@@ -0,0 +1,274 @@
+use std::sync::Arc;
+
+use surrealdb::{engine::any::Any, Surreal};
+use tokio::{
+    task::JoinHandle,
+    time::{self, MissedTickBehavior},
+};
+
+use super::{
+    outbox::{claim_pending_events, mark_dispatched, mark_failed},
+    transport::Transport,
+    EventEnvelope,
+};
+use crate::{config::EventsConfig, error::AppError, observability::record_domain_event};
+
+/// Delivers the events recorded in the service's outbox through every transport, e.g. the
+/// service's own `EventBus` and an `HttpTransport` to the other services.
+///
+/// ```ignore
+/// Dispatcher::new(db.clone(), &config.events)
+///     .with_transport(event_bus.clone())
+///     .with_transport(HttpTransport::new(&config))
+///     .spawn();
+/// ```
+pub struct Dispatcher {
+    db: Arc<Surreal<Any>>,
+    transports: Vec<Arc<dyn Transport>>,
+    config: EventsConfig,
+}
+
+impl Dispatcher {
+    pub fn new(db: Arc<Surreal<Any>>, config: &EventsConfig) -> Self {
+        Self {
+            db,
+            transports: vec![],
+            config: config.clone(),
+        }
+    }
+
+    pub fn with_transport(mut self, transport: impl Transport + 'static) -> Self {
+        self.transports.push(Arc::new(transport));
+        self
+    }
+
+    /// Deliver the undelivered events, oldest first. They are claimed first, so the dispatchers of
+    /// the other replicas skip them. An event is delivered once every transport accepted it,
+    /// otherwise it's attempted again on the next pass. Returns how many were delivered.
+    pub async fn dispatch_pending(&self) -> Result<usize, AppError> {
+        let pending =
+            claim_pending_events(&self.db, self.config.batch_size, self.config.max_attempts)
+                .await?;
+        let mut delivered = 0;
+
+        for pending_event in pending {
+            let envelope: EventEnvelope = match serde_json::from_str(&pending_event.envelope) {
+                Ok(envelope) => envelope,
+                Err(e) => {
+                    tracing::error!("Invalid event {} in the outbox: {}", pending_event.key, e);
+                    mark_failed(&self.db, &pending_event.key, &e.to_string()).await?;
+                    continue;
+                }
+            };
+            let event_type = envelope.event.event_type().as_str();
+
+            match self.deliver(&envelope).await {
+                Ok(()) => {
+                    mark_dispatched(&self.db, &pending_event.key).await?;
+                    record_domain_event(event_type, "delivered");
+                    delivered += 1;
+                }
+                Err(e) => {
+                    record_domain_event(event_type, "failed");
+
+                    if pending_event.attempts + 1 >= self.config.max_attempts {
+                        tracing::error!(
+                            "Giving up on event {} ({}) after {} attempts: {:?}",
+                            envelope.id,
+                            event_type,
+                            self.config.max_attempts,
+                            e
+                        );
+                    } else {
+                        tracing::warn!(
+                            "Failed to deliver event {} ({}): {:?}",
+                            envelope.id,
+                            event_type,
+                            e
+                        );
+                    }
+
+                    mark_failed(&self.db, &pending_event.key, &format!("{:?}", e)).await?;
+                }
+            }
+        }
+
+        Ok(delivered)
+    }
+
+    async fn deliver(&self, envelope: &EventEnvelope) -> Result<(), AppError> {
+        for transport in &self.transports {
+            transport.deliver(envelope).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Keep delivering the events in the background, every `poll_interval`
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = time::interval(self.config.poll_interval);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+            loop {
+                interval.tick().await;
+
+                if let Err(e) = self.dispatch_pending().await {
+                    tracing::error!("Failed to dispatch events: {:?}", e);
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        database::{memory::connect_in_memory, migrations::Migration},
+        events::{
+            outbox::{
+                claim_pending_events, record_event, record_event_once, OutboxEntry,
+                OUTBOX_CLAIMS_SCHEMA, OUTBOX_SCHEMA, RECORD_EVENT,
+            },
+            transport::EventBus,
+            DomainEvent, EventType,
+        },
+    };
+    use async_trait::async_trait;
+    use std::sync::Mutex;
+
+    const OUTBOX: Migration = Migration {
+        version: 1,
+        name: "event_outbox",
+        sql: OUTBOX_SCHEMA,
+    };
+    const OUTBOX_CLAIMS: Migration = Migration {
+        version: 2,
+        name: "outbox_claims",
+        sql: OUTBOX_CLAIMS_SCHEMA,
+    };
+
+    struct Unreachable;
+
+    #[async_trait]
+    impl Transport for Unreachable {
+        async fn deliver(&self, _envelope: &EventEnvelope) -> Result<(), AppError> {
+            Err(AppError::Upstream("Unreachable".into()))
+        }
+    }
+
+    fn order_confirmed() -> DomainEvent {
+        DomainEvent::OrderConfirmed {
+            order_id: "order-1".to_string(),
+            buyer_id: "buyer-1".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn delivers_committed_events_until_they_run_out_of_attempts() {
+        let db = connect_in_memory(&[OUTBOX, OUTBOX_CLAIMS]).await.unwrap();
+        let config = EventsConfig {
+            max_attempts: 2,
+            ..EventsConfig::default()
+        };
+
+        let bus = EventBus::default();
+        let received = Arc::new(Mutex::new(vec![]));
+        let confirmed = received.clone();
+        bus.subscribe(EventType::OrderConfirmed, move |envelope: EventEnvelope| {
+            let confirmed = confirmed.clone();
+            async move {
+                confirmed.lock().unwrap().push(envelope.event);
+                Ok(())
+            }
+        });
+        let dispatcher = Dispatcher::new(db.clone(), &config).with_transport(bus);
+
+        // Rolled back with the change
+        let rolled_back = db
+            .query(format!(
+                "BEGIN TRANSACTION; {} THROW 'rolled back'; COMMIT TRANSACTION;",
+                RECORD_EVENT
+            ))
+            .bind(("event", OutboxEntry::new("orders", order_confirmed())))
+            .await
+            .unwrap()
+            .check();
+        assert!(rolled_back.is_err());
+        assert_eq!(dispatcher.dispatch_pending().await.unwrap(), 0);
+
+        record_event(&db, "orders", order_confirmed())
+            .await
+            .unwrap();
+        assert_eq!(dispatcher.dispatch_pending().await.unwrap(), 1);
+        assert_eq!(dispatcher.dispatch_pending().await.unwrap(), 0);
+        assert_eq!(*received.lock().unwrap(), vec![order_confirmed()]);
+
+        record_event(&db, "orders", order_confirmed())
+            .await
+            .unwrap();
+        let failing = Dispatcher::new(db.clone(), &config).with_transport(Unreachable);
+        assert_eq!(failing.dispatch_pending().await.unwrap(), 0);
+        assert_eq!(failing.dispatch_pending().await.unwrap(), 0);
+
+        // Out of attempts, it stays in the outbox for inspection
+        assert_eq!(dispatcher.dispatch_pending().await.unwrap(), 0);
+        assert_eq!(received.lock().unwrap().len(), 1);
+
+        let mut undelivered_query = db
+            .query("SELECT VALUE attempts FROM outbox WHERE dispatched_at IS NONE")
+            .await
+            .unwrap();
+        let attempts: Vec<u32> = undelivered_query.take(0).unwrap();
+        assert_eq!(attempts, vec![2]);
+    }
+
+    #[tokio::test]
+    async fn claimed_events_are_left_to_their_dispatcher() {
+        let db = connect_in_memory(&[OUTBOX, OUTBOX_CLAIMS]).await.unwrap();
+        let config = EventsConfig::default();
+        let dispatcher = Dispatcher::new(db.clone(), &config).with_transport(EventBus::default());
+
+        record_event(&db, "orders", order_confirmed())
+            .await
+            .unwrap();
+
+        // Another replica got to it first
+        let claimed = claim_pending_events(&db, 10, config.max_attempts)
+            .await
+            .unwrap();
+        assert_eq!(claimed.len(), 1);
+        assert_eq!(dispatcher.dispatch_pending().await.unwrap(), 0);
+
+        // Once the claim expires anyone can claim it, and a failed delivery releases it
+        db.query("UPDATE outbox SET claimed_until = time::now() - 1s")
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+        let failing = Dispatcher::new(db.clone(), &config).with_transport(Unreachable);
+        assert_eq!(failing.dispatch_pending().await.unwrap(), 0);
+        assert_eq!(dispatcher.dispatch_pending().await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn keyed_events_are_recorded_once() {
+        let db = connect_in_memory(&[OUTBOX, OUTBOX_CLAIMS]).await.unwrap();
+
+        for recorded_now in [true, false] {
+            assert_eq!(
+                record_event_once(&db, "payments", "payment-order-1", order_confirmed())
+                    .await
+                    .unwrap(),
+                recorded_now
+            );
+        }
+
+        let mut envelopes_query = db.query("SELECT VALUE envelope FROM outbox").await.unwrap();
+        let envelopes: Vec<String> = envelopes_query.take(0).unwrap();
+        assert_eq!(envelopes.len(), 1);
+        let envelope: EventEnvelope = serde_json::from_str(&envelopes[0]).unwrap();
+        assert_eq!(envelope.id, "payment-order-1");
+    }
+}
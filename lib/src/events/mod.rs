@@ -0,0 +1,138 @@
+//! Domain events shared between the services. A service records its events in its own database's
+//! outbox, in the transaction making the change, and the `Dispatcher` delivers them to the
+//! subscribers through the configured transports. Delivery is at least once.
+
+pub mod dispatcher;
+pub mod outbox;
+pub mod transport;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Something that happened in a service that others may react to. The IDs are the ones the other
+/// services know, e.g. the ACL user ID rather than the internal `user_id` record.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data")]
+pub enum DomainEvent {
+    /// A buyer checked out their cart, the order waits for the payment
+    OrderCreated {
+        order_id: String,
+        buyer_id: String,
+        total_amount: u64,
+    },
+    /// The order was paid for and its cart archived
+    OrderConfirmed { order_id: String, buyer_id: String },
+    /// Paystack charged the buyer. The reference is the order ID, the amount is in the currency's
+    /// subunit.
+    PaymentSucceeded {
+        reference: String,
+        amount: Option<u64>,
+        currency: Option<String>,
+    },
+    ProductPublished {
+        product_id: String,
+        owner_id: String,
+        name: String,
+    },
+    CommentPosted {
+        comment_id: String,
+        product_id: String,
+        author_id: String,
+    },
+    RatingChanged {
+        product_id: String,
+        user_id: String,
+        rating_value: u32,
+    },
+}
+
+/// The type of a `DomainEvent`, what subscribers subscribe to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EventType {
+    OrderCreated,
+    OrderConfirmed,
+    PaymentSucceeded,
+    ProductPublished,
+    CommentPosted,
+    RatingChanged,
+}
+
+impl EventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventType::OrderCreated => "OrderCreated",
+            EventType::OrderConfirmed => "OrderConfirmed",
+            EventType::PaymentSucceeded => "PaymentSucceeded",
+            EventType::ProductPublished => "ProductPublished",
+            EventType::CommentPosted => "CommentPosted",
+            EventType::RatingChanged => "RatingChanged",
+        }
+    }
+}
+
+impl DomainEvent {
+    pub fn event_type(&self) -> EventType {
+        match self {
+            DomainEvent::OrderCreated { .. } => EventType::OrderCreated,
+            DomainEvent::OrderConfirmed { .. } => EventType::OrderConfirmed,
+            DomainEvent::PaymentSucceeded { .. } => EventType::PaymentSucceeded,
+            DomainEvent::ProductPublished { .. } => EventType::ProductPublished,
+            DomainEvent::CommentPosted { .. } => EventType::CommentPosted,
+            DomainEvent::RatingChanged { .. } => EventType::RatingChanged,
+        }
+    }
+}
+
+/// An event as stored in the outbox and delivered to the subscribers
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EventEnvelope {
+    /// Unique per event. Subscribers use it to ignore the events delivered again.
+    pub id: String,
+    /// The service that recorded the event
+    pub source: String,
+    /// Milliseconds since the epoch
+    pub occurred_at: u64,
+    pub event: DomainEvent,
+}
+
+impl EventEnvelope {
+    pub fn new(source: &str, event: DomainEvent) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            source: source.to_string(),
+            occurred_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            event,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn envelopes_are_tagged_by_event_type() {
+        let envelope = EventEnvelope::new(
+            "shared",
+            DomainEvent::RatingChanged {
+                product_id: "product-1".to_string(),
+                user_id: "user-1".to_string(),
+                rating_value: 4,
+            },
+        );
+
+        let json = serde_json::to_value(&envelope).unwrap();
+        assert_eq!(json["source"], "shared");
+        assert_eq!(json["event"]["type"], "RatingChanged");
+        assert_eq!(json["event"]["data"]["rating_value"], 4);
+
+        let parsed: EventEnvelope = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed, envelope);
+        assert_eq!(parsed.event.event_type().as_str(), "RatingChanged");
+    }
+}
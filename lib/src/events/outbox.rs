@@ -0,0 +1,171 @@
+use serde::{Deserialize, Serialize};
+use surrealdb::Response;
+
+use super::{DomainEvent, EventEnvelope};
+use crate::{error::AppError, utils::custom_traits::AsSurrealClient};
+
+/// The schema of the outbox table, every service adds it to its migrations. Changing it changes the
+/// checksum of an applied migration, add a new migration instead.
+pub const OUTBOX_SCHEMA: &str = include_str!("outbox.surql");
+
+/// Lets the dispatchers of a service's replicas claim the events they deliver, a migration after
+/// `OUTBOX_SCHEMA`
+pub const OUTBOX_CLAIMS_SCHEMA: &str = include_str!("outbox_claims.surql");
+
+/// How long a dispatcher has to deliver the events it claimed. If it dies, another one delivers
+/// them once the claim expires.
+const CLAIM_LEASE: &str = "5m";
+
+/// Records the `$event` binding, an `OutboxEntry`. Add it to the transaction making the change, so the
+/// event is recorded if and only if the change is committed. When the event refers to a record the
+/// transaction creates, generate the record's key upfront (`Id::rand()`) to build the event with it.
+pub const RECORD_EVENT: &str = "CREATE outbox CONTENT $event RETURN NONE;";
+
+/// A row of the outbox, bound as `$event` for `RECORD_EVENT`
+#[derive(Clone, Debug, Serialize)]
+pub struct OutboxEntry {
+    event_type: &'static str,
+    envelope: String,
+}
+
+impl OutboxEntry {
+    pub fn new(source: &str, event: DomainEvent) -> Self {
+        Self::from_envelope(EventEnvelope::new(source, event))
+    }
+
+    fn from_envelope(envelope: EventEnvelope) -> Self {
+        Self {
+            event_type: envelope.event.event_type().as_str(),
+            envelope: serde_json::to_string(&envelope).expect("Events serialize to JSON"),
+        }
+    }
+}
+
+/// An event that still has to be delivered
+#[derive(Debug, Deserialize)]
+pub(crate) struct PendingEvent {
+    pub key: String,
+    pub envelope: String,
+    pub attempts: u32,
+}
+
+/// Record an event on its own, for the changes that aren't made in the service's database
+pub async fn record_event<T: AsSurrealClient>(
+    db: &T,
+    source: &str,
+    event: DomainEvent,
+) -> Result<(), AppError> {
+    db.as_client()
+        .query(RECORD_EVENT)
+        .bind(("event", OutboxEntry::new(source, event)))
+        .await
+        .and_then(Response::check)
+        .map_err(AppError::database)?;
+
+    Ok(())
+}
+
+/// Record an event once per key, for the changes that are retried such as a payment provider's
+/// webhooks. The key is the envelope ID as well, so the subscribers see a single event. Returns
+/// whether the event was recorded now.
+pub async fn record_event_once<T: AsSurrealClient>(
+    db: &T,
+    source: &str,
+    key: &str,
+    event: DomainEvent,
+) -> Result<bool, AppError> {
+    let envelope = EventEnvelope {
+        id: key.to_string(),
+        ..EventEnvelope::new(source, event)
+    };
+
+    let mut record_query = db
+        .as_client()
+        .query(
+            "
+            BEGIN TRANSACTION;
+            LET $record = type::thing('outbox', $key);
+            LET $recorded = !record::exists($record);
+            IF $recorded { CREATE $record CONTENT $event RETURN NONE };
+            RETURN $recorded;
+            COMMIT TRANSACTION;
+            ",
+        )
+        .bind(("key", key.to_string()))
+        .bind(("event", OutboxEntry::from_envelope(envelope)))
+        .await
+        .and_then(Response::check)
+        .map_err(AppError::database)?;
+
+    let recorded: Option<bool> = record_query.take(0).map_err(AppError::deserialization)?;
+
+    Ok(recorded.unwrap_or(false))
+}
+
+/// Claim the oldest undelivered events that haven't run out of attempts and aren't claimed by
+/// another dispatcher
+pub(crate) async fn claim_pending_events<T: AsSurrealClient>(
+    db: &T,
+    limit: usize,
+    max_attempts: u32,
+) -> Result<Vec<PendingEvent>, AppError> {
+    let mut claim_query = db
+        .as_client()
+        .query(format!(
+            "
+            BEGIN TRANSACTION;
+            LET $pending = (
+                SELECT id, created_at FROM outbox
+                WHERE dispatched_at IS NONE AND attempts < $max_attempts
+                    AND (claimed_until IS NONE OR claimed_until < time::now())
+                ORDER BY created_at
+                LIMIT $limit
+            );
+            LET $claimed = (
+                UPDATE $pending.id SET claimed_until = time::now() + {}
+                RETURN meta::id(id) AS key, envelope, attempts
+            );
+            RETURN $claimed;
+            COMMIT TRANSACTION;
+            ",
+            CLAIM_LEASE
+        ))
+        .bind(("max_attempts", max_attempts))
+        .bind(("limit", limit))
+        .await
+        .and_then(Response::check)
+        .map_err(AppError::database)?;
+
+    claim_query.take(0).map_err(AppError::deserialization)
+}
+
+pub(crate) async fn mark_dispatched<T: AsSurrealClient>(db: &T, key: &str) -> Result<(), AppError> {
+    db.as_client()
+        .query("UPDATE type::thing('outbox', $key) SET dispatched_at = time::now()")
+        .bind(("key", key.to_string()))
+        .await
+        .and_then(Response::check)
+        .map_err(AppError::database)?;
+
+    Ok(())
+}
+
+/// Count the failed attempt and release the claim, the event is tried again on the next pass until
+/// it runs out of attempts
+pub(crate) async fn mark_failed<T: AsSurrealClient>(
+    db: &T,
+    key: &str,
+    error: &str,
+) -> Result<(), AppError> {
+    db.as_client()
+        .query(
+            "UPDATE type::thing('outbox', $key) SET attempts += 1, last_error = $error, claimed_until = NONE",
+        )
+        .bind(("key", key.to_string()))
+        .bind(("error", error.to_string()))
+        .await
+        .and_then(Response::check)
+        .map_err(AppError::database)?;
+
+    Ok(())
+}
@@ -0,0 +1,295 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use async_trait::async_trait;
+use axum::{extract::State, http::StatusCode, routing::post, Json, Router};
+use hyper::HeaderMap;
+
+use super::{EventEnvelope, EventType};
+use crate::{
+    config::{self, AppConfig},
+    error::AppError,
+    middleware::auth::service::{
+        events_audience, sign_service_token, verify_service_token, SERVICE_TOKEN_METADATA,
+    },
+    observability::record_domain_event,
+};
+
+/// Where the `Dispatcher` delivers the events. A failed delivery is attempted again later, so
+/// a transport may deliver an event more than once.
+#[async_trait]
+pub trait Transport: Send + Sync {
+    async fn deliver(&self, envelope: &EventEnvelope) -> Result<(), AppError>;
+}
+
+/// Reacts to the events of a type. Events can be delivered more than once, handlers should skip the
+/// envelope IDs they've seen or be idempotent.
+#[async_trait]
+pub trait Subscriber: Send + Sync {
+    async fn handle(&self, envelope: &EventEnvelope) -> Result<(), AppError>;
+}
+
+#[async_trait]
+impl<F, Fut> Subscriber for F
+where
+    F: Fn(EventEnvelope) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<(), AppError>> + Send,
+{
+    async fn handle(&self, envelope: &EventEnvelope) -> Result<(), AppError> {
+        self(envelope.clone()).await
+    }
+}
+
+/// The subscribers of a service, by event type
+type Subscribers = Arc<RwLock<HashMap<EventType, Vec<Arc<dyn Subscriber>>>>>;
+
+/// The in-process transport: the subscribers of this service, by event type. Gets the service's own
+/// events from the `Dispatcher` and the other services' through `events_router`.
+#[derive(Clone, Default)]
+pub struct EventBus {
+    subscribers: Subscribers,
+}
+
+impl EventBus {
+    pub fn subscribe(&self, event_type: EventType, subscriber: impl Subscriber + 'static) {
+        self.subscribers
+            .write()
+            .unwrap()
+            .entry(event_type)
+            .or_default()
+            .push(Arc::new(subscriber));
+    }
+
+    /// Hand the event to every subscriber of its type. They all get it even if one fails, the first
+    /// error is returned.
+    pub async fn publish(&self, envelope: &EventEnvelope) -> Result<(), AppError> {
+        let subscribers = self
+            .subscribers
+            .read()
+            .unwrap()
+            .get(&envelope.event.event_type())
+            .cloned()
+            .unwrap_or_default();
+
+        let mut result = Ok(());
+        for subscriber in subscribers {
+            if let Err(e) = subscriber.handle(envelope).await {
+                tracing::error!("Subscriber failed to handle event {}: {:?}", envelope.id, e);
+                result = result.and(Err(e));
+            }
+        }
+
+        result
+    }
+}
+
+#[async_trait]
+impl Transport for EventBus {
+    async fn deliver(&self, envelope: &EventEnvelope) -> Result<(), AppError> {
+        self.publish(envelope).await
+    }
+}
+
+/// The fan-out to the other services: POSTs every event to the `/events` endpoint of each
+/// subscribed service (the config's `events.subscribers`), signed with the service token
+#[derive(Clone, Debug)]
+pub struct HttpTransport {
+    http: reqwest::Client,
+    subscribers: Vec<String>,
+    service: String,
+    token_secret: String,
+    timeout: Duration,
+}
+
+impl HttpTransport {
+    pub fn new(config: &AppConfig) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            subscribers: config.events.subscribers.clone(),
+            service: config.service.clone(),
+            token_secret: config.service_auth.token_secret.clone(),
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for HttpTransport {
+    /// Succeeds once every subscriber accepted the event, so the ones that did get it again when
+    /// another one fails
+    async fn deliver(&self, envelope: &EventEnvelope) -> Result<(), AppError> {
+        if self.subscribers.is_empty() {
+            return Ok(());
+        }
+
+        for subscriber in &self.subscribers {
+            let token = sign_service_token(
+                &self.service,
+                &events_audience(&envelope.id),
+                &self.token_secret,
+                SystemTime::now(),
+            );
+
+            let response = self
+                .http
+                .post(subscriber)
+                .header(SERVICE_TOKEN_METADATA, token)
+                .timeout(self.timeout)
+                .json(envelope)
+                .send()
+                .await
+                .map_err(|e| {
+                    AppError::Upstream(format!("Failed to reach {}: {}", subscriber, e))
+                })?;
+
+            if !response.status().is_success() {
+                return Err(AppError::Upstream(format!(
+                    "{} answered {}",
+                    subscriber,
+                    response.status()
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// `POST /events` for the service's axum app, where the other services deliver their events. Only
+/// services can call it.
+pub fn events_router(bus: EventBus) -> Router {
+    Router::new()
+        .route("/events", post(receive_event))
+        .with_state(bus)
+}
+
+async fn receive_event(
+    State(bus): State<EventBus>,
+    headers: HeaderMap,
+    Json(envelope): Json<EventEnvelope>,
+) -> Result<StatusCode, AppError> {
+    let token = headers
+        .get(SERVICE_TOKEN_METADATA)
+        .and_then(|token| token.to_str().ok())
+        .ok_or_else(|| AppError::Unauthorized("Missing service token".into()))?;
+    let secret = config::get().service_auth.token_secret.clone();
+    let service = verify_service_token(
+        token,
+        &events_audience(&envelope.id),
+        &secret,
+        SystemTime::now(),
+    )?;
+
+    let event_type = envelope.event.event_type().as_str();
+    tracing::debug!(
+        "Received event {} ({}) from {}",
+        envelope.id,
+        event_type,
+        service
+    );
+    record_domain_event(event_type, "received");
+
+    bus.publish(&envelope).await?;
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::DomainEvent;
+    use std::sync::Mutex;
+
+    fn comment_posted() -> EventEnvelope {
+        EventEnvelope::new(
+            "shared",
+            DomainEvent::CommentPosted {
+                comment_id: "comment-1".to_string(),
+                product_id: "product-1".to_string(),
+                author_id: "user-1".to_string(),
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn delivers_to_the_subscribers_of_the_event_type() {
+        let bus = EventBus::default();
+        let received = Arc::new(Mutex::new(vec![]));
+
+        let comments = received.clone();
+        bus.subscribe(EventType::CommentPosted, move |envelope: EventEnvelope| {
+            let comments = comments.clone();
+            async move {
+                comments.lock().unwrap().push(envelope.id);
+                Ok(())
+            }
+        });
+        bus.subscribe(EventType::RatingChanged, |_: EventEnvelope| async {
+            Err(AppError::Internal("Not a rating".into()))
+        });
+
+        let envelope = comment_posted();
+        bus.publish(&envelope).await.unwrap();
+        assert_eq!(*received.lock().unwrap(), vec![envelope.id]);
+    }
+
+    #[tokio::test]
+    async fn only_services_deliver_events() {
+        let bus = EventBus::default();
+        let received = Arc::new(Mutex::new(0));
+
+        let count = received.clone();
+        bus.subscribe(EventType::CommentPosted, move |_: EventEnvelope| {
+            let count = count.clone();
+            async move {
+                *count.lock().unwrap() += 1;
+                Ok(())
+            }
+        });
+
+        let rejected =
+            receive_event(State(bus.clone()), HeaderMap::new(), Json(comment_posted())).await;
+        assert_eq!(
+            rejected,
+            Err(AppError::Unauthorized("Missing service token".into()))
+        );
+
+        let envelope = comment_posted();
+        let secret = config::get().service_auth.token_secret.clone();
+        let sign = |audience: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                SERVICE_TOKEN_METADATA,
+                sign_service_token("shared", audience, &secret, SystemTime::now())
+                    .parse()
+                    .unwrap(),
+            );
+            headers
+        };
+
+        // A token signed for a gRPC method, or another event, doesn't deliver this one
+        let replayed = receive_event(
+            State(bus.clone()),
+            sign("/orders.OrdersService/UpdateOrder"),
+            Json(envelope.clone()),
+        )
+        .await;
+        assert_eq!(
+            replayed,
+            Err(AppError::Unauthorized("Invalid service token".into()))
+        );
+
+        let accepted = receive_event(
+            State(bus),
+            sign(&events_audience(&envelope.id)),
+            Json(envelope),
+        )
+        .await;
+        assert_eq!(accepted, Ok(StatusCode::ACCEPTED));
+        assert_eq!(*received.lock().unwrap(), 1);
+    }
+}
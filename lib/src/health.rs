@@ -0,0 +1,203 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::{extract::State, http::StatusCode, routing::get, Json, Router};
+use serde::Serialize;
+use surrealdb::{engine::any::Any, Surreal};
+use tonic::{server::NamedService, transport::Endpoint, Code};
+use tonic_health::{
+    pb::{
+        health_client::HealthClient,
+        health_server::{Health, HealthServer},
+        HealthCheckRequest,
+    },
+    server::health_reporter,
+    ServingStatus,
+};
+
+use crate::{config::AppConfig, database::connection::ping};
+
+/// Everything the readiness probe checks. The database is always checked, the downstream services
+/// are the ones listed in the config's `readiness_checks`.
+#[derive(Clone)]
+pub struct HealthState {
+    db: Arc<Surreal<Any>>,
+    /// (name, gRPC endpoint)
+    downstream: Vec<(String, String)>,
+    timeout: Duration,
+    interval: Duration,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CheckResult {
+    pub name: String,
+    pub healthy: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub checks: Vec<CheckResult>,
+}
+
+impl HealthState {
+    pub fn new(config: &AppConfig, db: Arc<Surreal<Any>>) -> Self {
+        let downstream = config
+            .readiness_checks
+            .iter()
+            .filter_map(|name| {
+                config
+                    .endpoints
+                    .by_name(name)
+                    .map(|endpoint| (name.clone(), endpoint.to_string()))
+            })
+            .collect();
+
+        Self {
+            db,
+            downstream,
+            timeout: Duration::from_secs(2),
+            interval: config.database.health_check_interval,
+        }
+    }
+
+    /// Run every check. Each check has its own timeout so a hanging dependency can't hold the probe
+    /// past the orchestrator's own timeout.
+    pub async fn check(&self) -> Readiness {
+        let database_result = match tokio::time::timeout(self.timeout, ping(&self.db)).await {
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(e)) => Err(e.to_string()),
+            Err(_) => Err("Timed out".to_string()),
+        };
+
+        let mut checks = vec![check_result("database", database_result)];
+
+        for (name, endpoint) in &self.downstream {
+            checks.push(check_result(
+                name,
+                check_downstream(endpoint, self.timeout).await,
+            ));
+        }
+
+        Readiness {
+            ready: checks.iter().all(|check| check.healthy),
+            checks,
+        }
+    }
+}
+
+fn check_result(name: &str, result: Result<(), String>) -> CheckResult {
+    CheckResult {
+        name: name.to_string(),
+        healthy: result.is_ok(),
+        error: result.err(),
+    }
+}
+
+/// A downstream service is healthy if it reports SERVING. Services that don't implement `grpc.health.v1`
+/// (e.g. the external ACL, email and files services) only have to be reachable.
+async fn check_downstream(endpoint: &str, timeout: Duration) -> Result<(), String> {
+    let channel = Endpoint::from_shared(endpoint.to_string())
+        .map_err(|e| e.to_string())?
+        .connect_timeout(timeout)
+        .timeout(timeout)
+        .connect()
+        .await
+        .map_err(|e| format!("Unreachable: {}", e))?;
+
+    match HealthClient::new(channel)
+        .check(HealthCheckRequest {
+            service: "".to_string(),
+        })
+        .await
+    {
+        Ok(response) if response.get_ref().status == ServingStatus::Serving as i32 => Ok(()),
+        Ok(_) => Err("Not serving".to_string()),
+        Err(status) if status.code() == Code::Unimplemented => Ok(()),
+        Err(status) => Err(status.message().to_string()),
+    }
+}
+
+/// `/healthz` (liveness) and `/readyz` (readiness) for the service's axum app
+pub fn health_router(state: HealthState) -> Router {
+    Router::new()
+        .route("/healthz", get(liveness))
+        .route("/readyz", get(readiness))
+        .with_state(state)
+}
+
+/// The process is up and serving requests. Deliberately checks nothing else so a flaky dependency
+/// doesn't get healthy replicas restarted.
+pub async fn liveness() -> (StatusCode, &'static str) {
+    (StatusCode::OK, "OK")
+}
+
+/// 200 if every check passed, 503 with the failing checks otherwise
+pub async fn readiness(State(state): State<HealthState>) -> (StatusCode, Json<Readiness>) {
+    let readiness = state.check().await;
+
+    let status = match readiness.ready {
+        true => StatusCode::OK,
+        false => StatusCode::SERVICE_UNAVAILABLE,
+    };
+
+    (status, Json(readiness))
+}
+
+/// The standard `grpc.health.v1` service for the service's tonic server. Both the overall ("") and the
+/// service's own status follow the readiness checks, re-evaluated on the database health check interval.
+pub fn grpc_health_service<S: NamedService + 'static>(
+    state: HealthState,
+) -> HealthServer<impl Health> {
+    let (mut reporter, health_service) = health_reporter();
+
+    tokio::spawn(async move {
+        loop {
+            let status = match state.check().await.ready {
+                true => ServingStatus::Serving,
+                false => ServingStatus::NotServing,
+            };
+
+            reporter.set_service_status("", status).await;
+            reporter.set_service_status(S::NAME, status).await;
+
+            tokio::time::sleep(state.interval).await;
+        }
+    });
+
+    health_service
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::memory::connect_in_memory;
+
+    #[tokio::test]
+    async fn ready_when_the_database_responds() {
+        let db = connect_in_memory(&[]).await.unwrap();
+        let state = HealthState::new(&AppConfig::local("widgets"), db);
+
+        let (status, Json(readiness)) = readiness(State(state)).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(readiness.checks.len(), 1);
+        assert!(readiness.checks[0].healthy);
+    }
+
+    #[tokio::test]
+    async fn not_ready_when_a_downstream_service_is_unreachable() {
+        let db = connect_in_memory(&[]).await.unwrap();
+        let mut config = AppConfig::local("widgets");
+        config.endpoints.acl = "http://[::1]:1".to_string();
+        config.readiness_checks = vec!["acl".to_string()];
+
+        let (status, Json(readiness)) = readiness(State(HealthState::new(&config, db))).await;
+
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(readiness.checks[0].healthy);
+        assert_eq!(readiness.checks[1].name, "acl");
+        assert!(!readiness.checks[1].healthy);
+    }
+}
@@ -24,3 +24,7 @@ pub mod orders_service {
 pub mod payments_service {
     tonic::include_proto!("payments");
 }
+
+pub mod shared_service {
+    tonic::include_proto!("shared");
+}
@@ -1,3 +1,15 @@
+pub mod config;
+pub mod database;
+pub mod email;
+pub mod error;
+pub mod events;
+pub mod health;
 pub mod integration;
+pub mod logging;
 pub mod middleware;
+pub mod observability;
+pub mod schema;
+pub mod telemetry;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_support;
 pub mod utils;
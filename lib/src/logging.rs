@@ -0,0 +1,242 @@
+use std::{
+    borrow::Cow,
+    io::{self, Write},
+    sync::LazyLock,
+};
+
+use axum::{
+    extract::Request as HttpRequest,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response as HttpResponse,
+};
+use hyper::HeaderMap;
+use regex::Regex;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::{
+    fmt::{writer::MakeWriterExt, MakeWriter},
+    layer::SubscriberExt,
+    util::SubscriberInitExt,
+    EnvFilter, Layer,
+};
+use uuid::Uuid;
+
+use crate::{
+    config::{AppConfig, LogFormat},
+    telemetry,
+};
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+tokio::task_local! {
+    /// The correlation id of the request being handled, forwarded on outgoing gRPC calls
+    static REQUEST_ID: String;
+}
+
+/// Set up logging and tracing for a service. Logs go to stdout and a daily rolling file in ./logs,
+/// filtered by the configured directives (RUST_LOG) and scrubbed of credentials and emails. Spans are
+/// exported to the OTLP collector when one is configured. Keep the returned guard alive until the
+/// service exits so buffered logs are flushed.
+pub fn init_logging(config: &AppConfig) -> WorkerGuard {
+    // Persist the server logs to a file on a daily basis
+    let file_appender =
+        tracing_appender::rolling::daily("./logs", format!("{}.log", config.service));
+    let (non_blocking, guard) = tracing_appender::non_blocking(file_appender);
+
+    let secrets = config
+        .paystack
+        .iter()
+        .map(|paystack| paystack.secret.clone())
+        .chain([config.service_auth.token_secret.clone()])
+        .collect();
+    let writer = Redacting::new(io::stdout.and(non_blocking), secrets);
+
+    let fmt_layer = match config.logging.format {
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(false)
+            .with_writer(writer)
+            .boxed(),
+        LogFormat::Text => tracing_subscriber::fmt::layer().with_writer(writer).boxed(),
+    };
+
+    // Validated when the configuration was loaded
+    let filter =
+        EnvFilter::try_new(&config.logging.filter).unwrap_or_else(|_| EnvFilter::new("info"));
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt_layer)
+        .with(telemetry::otel_layer(config))
+        .init();
+
+    guard
+}
+
+/// Axum middleware that gives every request a correlation id. The caller's `X-Request-Id` is kept,
+/// otherwise one is generated. It's echoed in the response and forwarded on outgoing gRPC calls.
+/// Add it after `telemetry::trace_http` so the request span carries the id.
+pub async fn request_id(mut request: HttpRequest, next: Next) -> HttpResponse {
+    let request_id = request_id_from_headers(request.headers());
+
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        request.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+
+    let mut response = REQUEST_ID
+        .scope(request_id.clone(), next.run(request))
+        .await;
+
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+
+    response
+}
+
+/// The caller's request id if it's usable, otherwise a new one. Ids are capped so a client can't
+/// bloat every log line.
+pub fn request_id_from_headers(headers: &HeaderMap) -> String {
+    headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim())
+        .filter(|value| !value.is_empty() && value.len() <= 128)
+        .map(|value| value.to_string())
+        .unwrap_or_else(|| Uuid::new_v4().to_string())
+}
+
+/// Run a future with the given request id as the current one
+pub async fn with_request_id<F: std::future::Future>(request_id: String, future: F) -> F::Output {
+    REQUEST_ID.scope(request_id, future).await
+}
+
+/// The id of the request being handled, if any
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(|request_id| request_id.clone()).ok()
+}
+
+static REDACTIONS: LazyLock<Vec<(Regex, &'static str)>> = LazyLock::new(|| {
+    [
+        // Authorization, cookie and service token headers, whether logged as a map or as fields
+        (
+            r#"(?i)((?:authorization|cookie|set-cookie|auth_header|cookie_header|x-service-token)\\?"?\s*[:=]\s*(?:Some\()?\\?"?)[^"\\,)}\n]+"#,
+            "${1}[REDACTED]",
+        ),
+        (r"(?i)(bearer\s+)[A-Za-z0-9\-._~+/]+=*", "${1}[REDACTED]"),
+        // The ACL session token cookie
+        (r"(\bt=)[^;\s\\\x22]+", "${1}[REDACTED]"),
+        (r"\bsk_(?:live|test)_[A-Za-z0-9]+", "[REDACTED]"),
+        (
+            r"[A-Za-z0-9._%+\-]+@[A-Za-z0-9.\-]+\.[A-Za-z]{2,}",
+            "[REDACTED_EMAIL]",
+        ),
+    ]
+    .into_iter()
+    .map(|(pattern, replacement)| (Regex::new(pattern).unwrap(), replacement))
+    .collect()
+});
+
+/// Scrub credentials, the configured secrets and email addresses from a formatted log line
+pub fn redact<'a>(line: &'a str, secrets: &[String]) -> Cow<'a, str> {
+    let mut line = Cow::Borrowed(line);
+
+    for (pattern, replacement) in REDACTIONS.iter() {
+        if pattern.is_match(&line) {
+            line = Cow::Owned(pattern.replace_all(&line, *replacement).into_owned());
+        }
+    }
+
+    for secret in secrets.iter().filter(|secret| !secret.is_empty()) {
+        if line.contains(secret.as_str()) {
+            line = Cow::Owned(line.replace(secret.as_str(), "[REDACTED]"));
+        }
+    }
+
+    line
+}
+
+/// Wraps the log writers so every line is redacted before it leaves the process. The formatter writes
+/// each event in one call, so patterns never straddle writes.
+struct Redacting<M> {
+    inner: M,
+    secrets: Vec<String>,
+}
+
+impl<M> Redacting<M> {
+    fn new(inner: M, secrets: Vec<String>) -> Self {
+        Self { inner, secrets }
+    }
+}
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for Redacting<M> {
+    type Writer = RedactingWriter<'a, M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter {
+            inner: self.inner.make_writer(),
+            secrets: &self.secrets,
+        }
+    }
+
+    fn make_writer_for(&'a self, meta: &tracing::Metadata<'_>) -> Self::Writer {
+        RedactingWriter {
+            inner: self.inner.make_writer_for(meta),
+            secrets: &self.secrets,
+        }
+    }
+}
+
+struct RedactingWriter<'a, W> {
+    inner: W,
+    secrets: &'a [String],
+}
+
+impl<W: Write> Write for RedactingWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let line = String::from_utf8_lossy(buf);
+        self.inner
+            .write_all(redact(&line, self.secrets).as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scrubs_credentials_and_emails() {
+        let secrets = vec!["my-paystack-secret".to_string()];
+
+        let redacted = redact(
+            r#"{"fields":{"message":"headers: {\"authorization\": \"Bearer eyJhbGciOi.abc\", \"cookie\": \"oauth_client=;t=eyJtoken\", \"x-service-token\": \"payments.1700000000.a1b2c3\"}","customer":"jane@example.com","key":"sk_live_abc123","secret":"my-paystack-secret"}}"#,
+            &secrets,
+        );
+
+        assert!(!redacted.contains("eyJhbGciOi"));
+        assert!(!redacted.contains("eyJtoken"));
+        assert!(!redacted.contains("a1b2c3"));
+        assert!(!redacted.contains("jane@example.com"));
+        assert!(!redacted.contains("sk_live_abc123"));
+        assert!(!redacted.contains("my-paystack-secret"));
+        assert!(redacted.contains("[REDACTED_EMAIL]"));
+
+        let untouched = "Executing GraphQL request: Some(\"getProducts\")";
+        assert_eq!(redact(untouched, &secrets), untouched);
+    }
+
+    #[test]
+    fn keeps_or_generates_request_ids() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(request_id_from_headers(&headers).len(), 36);
+
+        headers.insert(REQUEST_ID_HEADER, HeaderValue::from_static("abc-123"));
+        assert_eq!(request_id_from_headers(&headers), "abc-123");
+    }
+}
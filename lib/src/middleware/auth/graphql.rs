@@ -1,7 +1,10 @@
 use crate::{
+    config,
+    error::AppError,
     integration::grpc::clients::acl_service::{acl_client::AclClient, Empty},
+    observability::observe_grpc_call,
     utils::{
-        grpc::{create_grpc_client, AuthMetaData},
+        grpc::{create_grpc_client, AuthMetaData, GrpcChannel},
         models::AuthStatus,
     },
 };
@@ -10,12 +13,12 @@ use hyper::{
     header::{AUTHORIZATION, COOKIE},
     HeaderMap,
 };
-use std::io::{Error, ErrorKind};
-use tonic::transport::Channel;
+use std::io::ErrorKind;
+use tonic::Code;
 
 /// False middleware for checking authentication from ACL service for GraphQL requests.
 /// I used this anti-pattern because the middleware in async-graphql just doesn't work. The headers are not properly parsed.
-pub async fn check_auth_from_acl(headers: &HeaderMap) -> Result<AuthStatus, Error> {
+pub async fn check_auth_from_acl(headers: &HeaderMap) -> Result<AuthStatus, AppError> {
     let auth_header = headers.get(AUTHORIZATION);
     let cookie_header = headers.get(COOKIE);
 
@@ -27,18 +30,22 @@ pub async fn check_auth_from_acl(headers: &HeaderMap) -> Result<AuthStatus, Erro
         constructed_grpc_request: Some(&mut request),
     };
 
-    let mut acl_grpc_client = create_grpc_client::<Empty, AclClient<Channel>>(
-        "http://[::1]:50051",
+    let mut acl_grpc_client = create_grpc_client::<Empty, AclClient<GrpcChannel>>(
+        &config::get().endpoints.acl,
         true,
         Some(auth_metadata),
     )
     .await
-    .map_err(|e| {
-        tracing::error!("Failed to connect to ACL service: {}", e);
-        Error::new(ErrorKind::Other, "Failed to connect to ACL service")
+    .map_err(|e| match e.kind() {
+        // The client sent an authorization or cookie header that can't be forwarded
+        ErrorKind::InvalidData => AppError::Unauthorized("Unauthorized".into()),
+        _ => {
+            tracing::error!("Failed to connect to ACL service: {}", e);
+            AppError::Upstream("Failed to connect to ACL service".into())
+        }
     })?;
 
-    let response = acl_grpc_client.check_auth(request).await;
+    let response = observe_grpc_call("acl", "CheckAuth", acl_grpc_client.check_auth(request)).await;
 
     match response {
         Ok(response) => {
@@ -48,6 +55,26 @@ pub async fn check_auth_from_acl(headers: &HeaderMap) -> Result<AuthStatus, Erro
                 is_auth: true,
             })
         }
-        Err(_e) => return Err(Error::new(ErrorKind::PermissionDenied, "Unauthorized")),
+        // ACL being down or slow isn't the caller's fault
+        Err(status) if matches!(status.code(), Code::Unavailable | Code::DeadlineExceeded) => {
+            Err(status.into())
+        }
+        Err(_e) => Err(AppError::Unauthorized("Unauthorized".into())),
+    }
+}
+
+/// Check that the current user is authenticated and is one of the admins listed in the configuration
+/// (ADMIN_USER_IDS, comma separated user IDs).
+pub async fn check_admin_from_acl(headers: &HeaderMap) -> Result<AuthStatus, AppError> {
+    let auth_status = check_auth_from_acl(headers).await?;
+
+    if config::get()
+        .admin_user_ids
+        .iter()
+        .any(|user_id| user_id == &auth_status.sub)
+    {
+        Ok(auth_status)
+    } else {
+        Err(AppError::Forbidden("Not an admin".into()))
     }
 }
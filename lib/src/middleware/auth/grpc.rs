@@ -1,18 +1,47 @@
-use std::time::Instant;
+use std::time::{Instant, SystemTime};
 
-use hyper::header::{AUTHORIZATION, COOKIE};
+use hyper::header::{HeaderValue, AUTHORIZATION, COOKIE};
 use tonic::body::BoxBody;
 use tonic::codegen::http::{Request, Response};
-use tonic::transport::Channel;
 use tonic::Status;
 use tonic_middleware::{Middleware, ServiceBound};
+use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 
+use crate::config;
+use crate::error::AppError;
 use crate::integration::grpc::clients::acl_service::{acl_client::AclClient, Empty};
-use crate::utils::grpc::{create_grpc_client, AuthMetaData};
+use crate::logging::{request_id_from_headers, with_request_id};
+use crate::middleware::auth::service::{verify_service_token, Principal, SERVICE_TOKEN_METADATA};
+use crate::observability::observe_grpc_call;
+use crate::telemetry::extract_context;
+use crate::utils::grpc::{create_grpc_client, AuthMetaData, GrpcChannel};
 
+/// The methods only other services may call, users get `PERMISSION_DENIED` even when signed in
+const INTERNAL_METHODS: &[&str] = &[
+    "/orders.OrdersService/UpdateOrder",
+    "/orders.OrdersService/GetAllArtifactsForOrder",
+    "/files.FilesService/PurchaseFile",
+    "/shared.SharedService/PostNotification",
+    "/shared.SharedService/HasAcceptedLatestTerms",
+];
+
+/// Authenticates the callers of the gRPC methods: services by their service token, users through
+/// the ACL service. The `Principal` is added to the request extensions, and the user ID as a
+/// `String` for users.
 #[derive(Default, Clone)]
 pub struct AuthMiddleware;
 
+/// The service that signed the token for this method
+fn verify_service(token: &HeaderValue, method: &str) -> Result<String, AppError> {
+    let secret = config::get().service_auth.token_secret.clone();
+    let token = token
+        .to_str()
+        .map_err(|_| AppError::Unauthorized("Invalid service token".into()))?;
+
+    verify_service_token(token, method, &secret, SystemTime::now())
+}
+
 #[async_trait::async_trait]
 impl<S> Middleware<S> for AuthMiddleware
 where
@@ -29,34 +58,74 @@ where
         tracing::debug!("Starting middleware");
         // Call the service. You can also intercept request from middleware.
 
-        let auth_header = req.headers().get(AUTHORIZATION);
-        let cookie_header = req.headers().get(COOKIE);
-
-        let mut request = tonic::Request::new(Empty {});
-
-        let auth_metadata: AuthMetaData<Empty> = AuthMetaData {
-            auth_header,
-            cookie_header,
-            constructed_grpc_request: Some(&mut request),
-        };
-
-        let mut acl_grpc_client = create_grpc_client::<Empty, AclClient<Channel>>(
-            "http://[::1]:50051",
-            true,
-            Some(auth_metadata),
-        )
-        .await
-        .map_err(|e| {
-            tracing::error!("Failed to connect to ACL service: {}", e);
-            Status::unavailable("Failed to connect to ACL service")
-        })?;
-
-        let response = acl_grpc_client.check_auth(request).await?;
-
-        let current_user = response.into_inner().sub;
-        // Insert current user to the req extensions(response.sub)
-        req.extensions_mut().insert(current_user);
-        let result = service.call(req).await?;
+        // Health probes come from orchestrators and other services' readiness checks, not users
+        if req.uri().path().starts_with("/grpc.health.v1.Health/") {
+            return service.call(req).await;
+        }
+
+        // Continue the caller's trace and request id, the ACL check and the handler run in this span
+        let request_id = request_id_from_headers(req.headers());
+        let span = tracing::info_span!(
+            "grpc_request",
+            method = %req.uri().path(),
+            request_id = %request_id,
+        );
+        span.set_parent(extract_context(req.headers()));
+
+        let method = req.uri().path().to_string();
+        let result = async {
+            let principal = match req.headers().get(SERVICE_TOKEN_METADATA) {
+                // Other services are trusted on their signature, without asking ACL
+                Some(token) => {
+                    Principal::Service(verify_service(token, &method).map_err(Status::from)?)
+                }
+                None => {
+                    let auth_header = req.headers().get(AUTHORIZATION);
+                    let cookie_header = req.headers().get(COOKIE);
+
+                    let mut request = tonic::Request::new(Empty {});
+
+                    let auth_metadata: AuthMetaData<Empty> = AuthMetaData {
+                        auth_header,
+                        cookie_header,
+                        constructed_grpc_request: Some(&mut request),
+                    };
+
+                    let mut acl_grpc_client = create_grpc_client::<Empty, AclClient<GrpcChannel>>(
+                        &config::get().endpoints.acl,
+                        true,
+                        Some(auth_metadata),
+                    )
+                    .await
+                    .map_err(|e| {
+                        tracing::error!("Failed to connect to ACL service: {}", e);
+                        Status::unavailable("Failed to connect to ACL service")
+                    })?;
+
+                    let response =
+                        observe_grpc_call("acl", "CheckAuth", acl_grpc_client.check_auth(request))
+                            .await?;
+
+                    let current_user = response.into_inner().sub;
+                    // Insert current user to the req extensions(response.sub)
+                    req.extensions_mut().insert(current_user.clone());
+                    Principal::User(current_user)
+                }
+            };
+
+            if INTERNAL_METHODS.contains(&method.as_str())
+                && !matches!(principal, Principal::Service(_))
+            {
+                tracing::warn!("{:?} called the internal method {}", principal, method);
+                return Err(Status::permission_denied("Only services may call this method").into());
+            }
+
+            req.extensions_mut().insert(principal);
+            service.call(req).await
+        }
+        .instrument(span);
+
+        let result = with_request_id(request_id, result).await?;
 
         let elapsed_time = start_time.elapsed();
         tracing::info!("Request processed in {:?}", elapsed_time);
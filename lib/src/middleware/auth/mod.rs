@@ -1,3 +1,4 @@
 pub mod graphql;
 pub mod grpc;
 pub mod rest;
+pub mod service;
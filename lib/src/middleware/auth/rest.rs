@@ -1,10 +1,13 @@
 use crate::{
+    config,
+    error::AppError,
     integration::grpc::clients::acl_service::{acl_client::AclClient, Empty},
-    utils::grpc::{create_grpc_client, AuthMetaData},
+    observability::observe_grpc_call,
+    utils::grpc::{create_grpc_client, AuthMetaData, GrpcChannel},
 };
 use axum::{extract::Request, http::StatusCode, middleware::Next, response::Response};
 use hyper::header::{AUTHORIZATION, COOKIE};
-use tonic::transport::Channel;
+use tonic::Code;
 
 pub async fn handle_auth_with_refresh(
     mut req: Request,
@@ -20,8 +23,8 @@ pub async fn handle_auth_with_refresh(
         cookie_header,
         constructed_grpc_request: Some(&mut request),
     };
-    let mut acl_grpc_client = create_grpc_client::<Empty, AclClient<Channel>>(
-        "http://[::1]:50051",
+    let mut acl_grpc_client = create_grpc_client::<Empty, AclClient<GrpcChannel>>(
+        &config::get().endpoints.acl,
         true,
         Some(auth_metadata),
     )
@@ -31,7 +34,7 @@ pub async fn handle_auth_with_refresh(
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
 
-    let response = acl_grpc_client.check_auth(request).await;
+    let response = observe_grpc_call("acl", "CheckAuth", acl_grpc_client.check_auth(request)).await;
 
     match response {
         Ok(response) => {
@@ -40,6 +43,10 @@ pub async fn handle_auth_with_refresh(
             req.extensions_mut().insert(current_user);
             Ok(next.run(req).await)
         }
-        Err(_e) => return Err(StatusCode::UNAUTHORIZED),
+        // ACL being down or slow isn't the caller's fault
+        Err(status) if matches!(status.code(), Code::Unavailable | Code::DeadlineExceeded) => {
+            Err(AppError::from(status).http_status())
+        }
+        Err(_e) => Err(StatusCode::UNAUTHORIZED),
     }
 }
@@ -0,0 +1,164 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use tonic::{service::Interceptor, GrpcMethod, Request, Status};
+
+use crate::error::AppError;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The metadata carrying the signed identity of the calling service
+pub const SERVICE_TOKEN_METADATA: &str = "x-service-token";
+
+/// Tokens are signed for every call, so they only need to outlive the call
+const SERVICE_TOKEN_TTL: Duration = Duration::from_secs(300);
+
+/// Who is calling a gRPC method, added to the request extensions by `AuthMiddleware`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Principal {
+    /// An end user, authenticated by the ACL service
+    User(String),
+    /// Another service, authenticated by its service token
+    Service(String),
+}
+
+/// The audience is signed but not sent, the receiver checks the token against its own
+fn signature(secret: &str, payload: &str, audience: &str) -> HmacSha256 {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC can take key of any size");
+    mac.update(payload.as_bytes());
+    mac.update(b"\n");
+    mac.update(audience.as_bytes());
+    mac
+}
+
+/// A `<service>.<expiry>.<signature>` token, the expiry in seconds since the epoch and the
+/// signature an HMAC-SHA256 with the secret shared by the services. The token is only valid for
+/// its audience, the gRPC method path (e.g. `/orders.OrdersService/UpdateOrder`) or
+/// `events_audience`, so a receiver can't replay it against another method.
+pub fn sign_service_token(service: &str, audience: &str, secret: &str, now: SystemTime) -> String {
+    let expires_at =
+        (now.duration_since(UNIX_EPOCH).unwrap_or_default() + SERVICE_TOKEN_TTL).as_secs();
+    let payload = format!("{}.{}", service, expires_at);
+    let signature = hex::encode(
+        signature(secret, &payload, audience)
+            .finalize()
+            .into_bytes(),
+    );
+
+    format!("{}.{}", payload, signature)
+}
+
+/// The name of the service that signed the token, if the signature for the audience is valid and
+/// it hasn't expired
+pub fn verify_service_token(
+    token: &str,
+    audience: &str,
+    secret: &str,
+    now: SystemTime,
+) -> Result<String, AppError> {
+    let invalid = || AppError::Unauthorized("Invalid service token".into());
+
+    let (payload, token_signature) = token.rsplit_once('.').ok_or_else(invalid)?;
+    let (service, expires_at) = payload.split_once('.').ok_or_else(invalid)?;
+    let expires_at: u64 = expires_at.parse().map_err(|_| invalid())?;
+    let token_signature = hex::decode(token_signature).map_err(|_| invalid())?;
+
+    signature(secret, payload, audience)
+        .verify_slice(&token_signature)
+        .map_err(|_| invalid())?;
+
+    if UNIX_EPOCH + Duration::from_secs(expires_at) < now {
+        return Err(AppError::Unauthorized("Expired service token".into()));
+    }
+
+    Ok(service.to_string())
+}
+
+/// The audience of a delivered event, the token only delivers that one event
+pub fn events_audience(event_id: &str) -> String {
+    format!("/events/{}", event_id)
+}
+
+/// Signs the outgoing calls of a service with its identity, for the method called
+#[derive(Clone)]
+pub struct ServiceIdentity {
+    service: String,
+    secret: String,
+}
+
+impl ServiceIdentity {
+    pub fn new(service: &str, secret: String) -> Self {
+        Self {
+            service: service.to_string(),
+            secret,
+        }
+    }
+}
+
+impl Interceptor for ServiceIdentity {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        // Set by the generated clients, the token is bound to the method
+        let method = request
+            .extensions()
+            .get::<GrpcMethod<'static>>()
+            .map(|method| format!("/{}/{}", method.service(), method.method()));
+
+        if let Some(method) = method {
+            let token = sign_service_token(&self.service, &method, &self.secret, SystemTime::now())
+                .parse()
+                .map_err(|_| Status::internal("Invalid service token"))?;
+            request.metadata_mut().insert(SERVICE_TOKEN_METADATA, token);
+        }
+
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UPDATE_ORDER: &str = "/orders.OrdersService/UpdateOrder";
+
+    #[test]
+    fn verifies_signed_service_tokens() {
+        let now = SystemTime::now();
+        let token = sign_service_token("payments", UPDATE_ORDER, "secret", now);
+
+        assert_eq!(
+            verify_service_token(&token, UPDATE_ORDER, "secret", now),
+            Ok("payments".to_string())
+        );
+        assert_eq!(
+            verify_service_token(&token, UPDATE_ORDER, "other-secret", now),
+            Err(AppError::Unauthorized("Invalid service token".into()))
+        );
+
+        let forged = token.replacen("payments", "orders", 1);
+        assert!(verify_service_token(&forged, UPDATE_ORDER, "secret", now).is_err());
+
+        let later = now + SERVICE_TOKEN_TTL + Duration::from_secs(1);
+        assert_eq!(
+            verify_service_token(&token, UPDATE_ORDER, "secret", later),
+            Err(AppError::Unauthorized("Expired service token".into()))
+        );
+    }
+
+    #[test]
+    fn tokens_are_only_valid_for_their_audience() {
+        let now = SystemTime::now();
+        let token = sign_service_token("shared", &events_audience("event-1"), "secret", now);
+
+        assert_eq!(
+            verify_service_token(&token, &events_audience("event-1"), "secret", now),
+            Ok("shared".to_string())
+        );
+        assert_eq!(
+            verify_service_token(&token, UPDATE_ORDER, "secret", now),
+            Err(AppError::Unauthorized("Invalid service token".into()))
+        );
+        assert!(verify_service_token(&token, &events_audience("event-2"), "secret", now).is_err());
+    }
+}
@@ -0,0 +1,723 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use async_graphql::parser::{
+    parse_query,
+    types::{DocumentOperations, ExecutableDocument, OperationDefinition, Selection, SelectionSet},
+};
+use axum::{
+    body::{to_bytes, Body},
+    extract::{MatchedPath, Request, State},
+    http::{
+        header::{CONTENT_TYPE, RETRY_AFTER},
+        HeaderValue,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use hyper::{
+    header::{AUTHORIZATION, COOKIE},
+    HeaderMap,
+};
+use serde::Deserialize;
+use surrealdb::{engine::any::Any, Surreal};
+
+use crate::{
+    config::{AppConfig, RateLimit, RateLimitBackend},
+    error::AppError,
+    middleware::auth::graphql::check_auth_from_acl,
+    schema::{query_hash, server_error},
+};
+
+/// GraphQL requests are buffered to find their operations, bigger ones are rejected
+const MAX_BODY_SIZE: usize = 2 * 1024 * 1024;
+/// The most buckets the memory store keeps. Past this, the ones that refilled are dropped, then the
+/// least recently used.
+const MAX_MEMORY_BUCKETS: usize = 10_000;
+/// How often the SurrealDB store deletes the rows of the buckets that refilled
+const SURREAL_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Decision {
+    Allowed,
+    Limited { retry_after: Duration },
+}
+
+/// Where the token buckets are kept, by "<operation>:<client>" key
+#[async_trait::async_trait]
+pub trait RateLimitStore: Send + Sync {
+    /// Take a token from the bucket, refilled since it was last used
+    async fn take(&self, key: &str, limit: &RateLimit) -> Result<Decision, AppError>;
+}
+
+/// The decision for a bucket with `tokens` left after the refill
+fn decide(tokens: f64, limit: &RateLimit) -> Decision {
+    if tokens >= 1.0 {
+        Decision::Allowed
+    } else {
+        Decision::Limited {
+            retry_after: Duration::from_secs_f64((1.0 - tokens) / limit.refill_rate()),
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+    full_at: Instant,
+}
+
+/// Per instance buckets, the default
+#[derive(Default)]
+pub struct MemoryStore {
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl MemoryStore {
+    fn take_at(&self, key: &str, limit: &RateLimit, now: Instant) -> Decision {
+        let mut buckets = self.buckets.lock().unwrap();
+
+        // A full bucket is the same as no bucket
+        if buckets.len() >= MAX_MEMORY_BUCKETS && !buckets.contains_key(key) {
+            buckets.retain(|_, bucket| bucket.full_at > now);
+        }
+
+        // Forgetting a draining bucket refills it, that's the price of a bounded store
+        if buckets.len() >= MAX_MEMORY_BUCKETS && !buckets.contains_key(key) {
+            let least_recently_used = buckets
+                .iter()
+                .min_by_key(|(_, bucket)| bucket.updated_at)
+                .map(|(key, _)| key.clone());
+
+            if let Some(least_recently_used) = least_recently_used {
+                buckets.remove(&least_recently_used);
+            }
+        }
+
+        let capacity = limit.capacity as f64;
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: capacity,
+            updated_at: now,
+            full_at: now,
+        });
+
+        let elapsed = now.saturating_duration_since(bucket.updated_at);
+        let tokens = (bucket.tokens + elapsed.as_secs_f64() * limit.refill_rate()).min(capacity);
+        let decision = decide(tokens, limit);
+
+        bucket.tokens = match decision {
+            Decision::Allowed => tokens - 1.0,
+            Decision::Limited { .. } => tokens,
+        };
+        bucket.updated_at = now;
+        bucket.full_at =
+            now + Duration::from_secs_f64((capacity - bucket.tokens) / limit.refill_rate());
+
+        decision
+    }
+}
+
+#[async_trait::async_trait]
+impl RateLimitStore for MemoryStore {
+    async fn take(&self, key: &str, limit: &RateLimit) -> Result<Decision, AppError> {
+        Ok(self.take_at(key, limit, Instant::now()))
+    }
+}
+
+/// Buckets in the `rate_limit` table, shared by the instances of a service. Each take is a single
+/// UPSERT, so concurrent requests can't spend the same token. The rows expire once their bucket
+/// refilled and are deleted by the next take after `SURREAL_SWEEP_INTERVAL`.
+pub struct SurrealStore {
+    db: Arc<Surreal<Any>>,
+    /// When the expired rows were last deleted, in milliseconds since the epoch
+    swept_at: AtomicU64,
+}
+
+impl SurrealStore {
+    pub fn new(db: Arc<Surreal<Any>>) -> Self {
+        Self {
+            db,
+            swept_at: AtomicU64::new(0),
+        }
+    }
+
+    async fn take_at(&self, key: &str, limit: &RateLimit, now: u64) -> Result<Decision, AppError> {
+        let swept_at = self.swept_at.load(Ordering::Relaxed);
+        let sweep_due = now.saturating_sub(swept_at) >= SURREAL_SWEEP_INTERVAL.as_millis() as u64;
+
+        // Only one of the concurrent takes sweeps
+        if sweep_due
+            && self
+                .swept_at
+                .compare_exchange(swept_at, now, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            self.db
+                .query("DELETE rate_limit WHERE expires_at <= $now")
+                .bind(("now", now))
+                .await
+                .map_err(AppError::database)?
+                .check()
+                .map_err(AppError::database)?;
+        }
+
+        let mut take_query = self
+            .db
+            .query(
+                "
+                UPSERT type::thing('rate_limit', $key) SET
+                    available = IF updated_at = NONE THEN $capacity
+                        ELSE math::min([$capacity, tokens + math::max([0, $now - updated_at]) * $refill_per_ms])
+                        END,
+                    tokens = IF available >= 1 THEN available - 1 ELSE available END,
+                    updated_at = $now,
+                    expires_at = $now + math::ceil(($capacity - tokens) / $refill_per_ms)
+                RETURN VALUE available
+                ",
+            )
+            .bind(("key", key.to_string()))
+            .bind(("capacity", limit.capacity as f64))
+            .bind(("refill_per_ms", limit.refill_rate() / 1000.0))
+            .bind(("now", now))
+            .await
+            .map_err(AppError::database)?;
+
+        let available: Option<f64> = take_query.take(0).map_err(AppError::deserialization)?;
+
+        available
+            .map(|tokens| decide(tokens, limit))
+            .ok_or_else(|| AppError::Internal("Failed to update the rate limit".into()))
+    }
+}
+
+#[async_trait::async_trait]
+impl RateLimitStore for SurrealStore {
+    async fn take(&self, key: &str, limit: &RateLimit) -> Result<Decision, AppError> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_millis() as u64)
+            .unwrap_or_default();
+
+        self.take_at(key, limit, now).await
+    }
+}
+
+/// Token buckets per operation and client, with the limits of the config's `rate_limit` section.
+/// Shared by the `rate_limit_graphql` and `rate_limit_rest` middlewares.
+pub struct RateLimiter {
+    limits: HashMap<String, RateLimit>,
+    store: Box<dyn RateLimitStore>,
+    allow_list: Option<Arc<HashMap<String, String>>>,
+    /// The root fields of the persisted queries seen, by hash, to limit the requests that only
+    /// send the hash
+    persisted_queries: Mutex<HashMap<String, Vec<String>>>,
+    persisted_query_cache_size: usize,
+}
+
+impl RateLimiter {
+    pub fn new(config: &AppConfig, db: Arc<Surreal<Any>>) -> Arc<Self> {
+        let store: Box<dyn RateLimitStore> = match config.rate_limit.backend {
+            RateLimitBackend::Memory => Box::new(MemoryStore::default()),
+            RateLimitBackend::SurrealDb => Box::new(SurrealStore::new(db)),
+        };
+
+        Arc::new(Self {
+            limits: config.rate_limit.limits.clone(),
+            store,
+            allow_list: config.graphql.allow_list.clone(),
+            persisted_queries: Mutex::new(HashMap::new()),
+            persisted_query_cache_size: config.graphql.persisted_query_cache_size,
+        })
+    }
+
+    /// Take a token for every limited operation of the request. The client is only resolved, which
+    /// may cost a call to the ACL service, when one of them is limited.
+    pub async fn check(&self, headers: &HeaderMap, operations: &[String]) -> Decision {
+        let limited: Vec<(&String, &RateLimit)> = operations
+            .iter()
+            .filter_map(|operation| self.limits.get(operation).map(|limit| (operation, limit)))
+            .collect();
+
+        if limited.is_empty() {
+            return Decision::Allowed;
+        }
+
+        let clients = client_keys(headers).await;
+
+        for (operation, limit) in limited {
+            for client in &clients {
+                match self
+                    .store
+                    .take(&format!("{}:{}", operation, client), limit)
+                    .await
+                {
+                    Ok(Decision::Allowed) => {}
+                    Ok(decision) => {
+                        tracing::warn!("Rate limited {} for {}", operation, client);
+                        return decision;
+                    }
+                    // Fail open, the endpoints shouldn't go down with the store
+                    Err(e) => tracing::error!("Rate limit store failed: {:?}", e),
+                }
+            }
+        }
+
+        Decision::Allowed
+    }
+
+    /// The root fields a GraphQL request runs, e.g. ["createOrder"]. `None` when it only sends the
+    /// hash of an automatic persisted query that wasn't seen yet.
+    fn requested_fields(&self, request: &GraphqlRequestBody) -> Option<Vec<String>> {
+        let operation_name = request.operation_name.as_deref();
+        let hash = request
+            .extensions
+            .get("persistedQuery")
+            .and_then(|persisted_query| persisted_query.get("sha256Hash"))
+            .and_then(|hash| hash.as_str());
+
+        if !request.query.is_empty() {
+            let fields = root_fields(&request.query, operation_name);
+
+            if let Some(hash) = hash.filter(|hash| query_hash(&request.query) == *hash) {
+                let mut persisted_queries = self.persisted_queries.lock().unwrap();
+                if persisted_queries.len() >= self.persisted_query_cache_size {
+                    // Forgotten queries cost the client one round trip to send them again
+                    persisted_queries.clear();
+                }
+                persisted_queries.insert(hash.to_string(), fields.clone());
+            }
+
+            return Some(fields);
+        }
+
+        let hash = hash?;
+
+        match &self.allow_list {
+            Some(allow_list) => Some(
+                allow_list
+                    .get(hash)
+                    .map(|query| root_fields(query, operation_name))
+                    // Not allow-listed, the schema rejects it
+                    .unwrap_or_default(),
+            ),
+            None => self.persisted_queries.lock().unwrap().get(hash).cloned(),
+        }
+    }
+}
+
+/// Who a request is counted against: the user when authenticated, else the client IP and the cart
+/// session, if any. The session cookie is chosen by the client, so a new one each request doesn't
+/// get around the IP's bucket. `X-Real-IP` is set by nginx, which every public request goes through.
+async fn client_keys(headers: &HeaderMap) -> Vec<String> {
+    if headers.contains_key(AUTHORIZATION) {
+        if let Ok(auth_status) = check_auth_from_acl(headers).await {
+            return vec![format!("user:{}", auth_status.sub)];
+        }
+    }
+
+    let ip = headers
+        .get("x-real-ip")
+        .and_then(|ip| ip.to_str().ok())
+        .unwrap_or("unknown");
+    let mut keys = vec![format!("ip:{}", ip)];
+
+    let session_id = headers
+        .get(COOKIE)
+        .and_then(|cookie| cookie.to_str().ok())
+        .and_then(|cookie| cookie.split("; ").find(|&c| c.starts_with("session_id=")))
+        .map(|cookie| cookie.trim_start_matches("session_id="));
+
+    if let Some(session_id) = session_id {
+        keys.push(format!("session:{}", session_id));
+    }
+
+    keys
+}
+
+#[derive(Deserialize)]
+struct GraphqlRequestBody {
+    #[serde(default)]
+    query: String,
+    #[serde(rename = "operationName")]
+    operation_name: Option<String>,
+    #[serde(default)]
+    extensions: HashMap<String, serde_json::Value>,
+}
+
+/// The root fields selected by the operation, or every operation when none is named. Invalid
+/// queries have none, the schema rejects them anyway.
+fn root_fields(query: &str, operation_name: Option<&str>) -> Vec<String> {
+    let Ok(document) = parse_query(query) else {
+        return vec![];
+    };
+
+    let operations: Vec<&OperationDefinition> = match &document.operations {
+        DocumentOperations::Single(operation) => vec![&operation.node],
+        DocumentOperations::Multiple(operations) => match operation_name {
+            Some(name) => operations
+                .get(name)
+                .map(|operation| vec![&operation.node])
+                .unwrap_or_default(),
+            None => operations
+                .values()
+                .map(|operation| &operation.node)
+                .collect(),
+        },
+    };
+
+    let mut fields = vec![];
+    for operation in operations {
+        collect_fields(&document, &operation.selection_set.node, &mut fields, 0);
+    }
+
+    fields
+}
+
+fn collect_fields(
+    document: &ExecutableDocument,
+    selection_set: &SelectionSet,
+    fields: &mut Vec<String>,
+    depth: usize,
+) {
+    // Fragment cycles are invalid, this only stops them before the validation does
+    if depth > 10 {
+        return;
+    }
+
+    for selection in &selection_set.items {
+        match &selection.node {
+            Selection::Field(field) => fields.push(field.node.name.node.to_string()),
+            Selection::InlineFragment(fragment) => collect_fields(
+                document,
+                &fragment.node.selection_set.node,
+                fields,
+                depth + 1,
+            ),
+            Selection::FragmentSpread(spread) => {
+                if let Some(fragment) = document.fragments.get(&spread.node.fragment_name.node) {
+                    collect_fields(
+                        document,
+                        &fragment.node.selection_set.node,
+                        fields,
+                        depth + 1,
+                    )
+                }
+            }
+        }
+    }
+}
+
+/// A 429 with `Retry-After`. GraphQL clients get a GraphQL response with the `RATE_LIMITED` code and
+/// a `retryAfter` extension in seconds.
+fn rate_limited(retry_after: Duration, graphql: bool) -> Response {
+    let retry_after_secs = retry_after.as_secs_f64().ceil() as u64;
+    let error = AppError::RateLimited("Too many requests, try again later".into());
+
+    let mut response = if graphql {
+        let status = error.http_status();
+        let mut server_error = server_error(error);
+        server_error
+            .extensions
+            .get_or_insert_with(Default::default)
+            .set("retryAfter", retry_after_secs);
+
+        (
+            status,
+            Json(async_graphql::Response::from_errors(vec![server_error])),
+        )
+            .into_response()
+    } else {
+        error.into_response()
+    };
+
+    response
+        .headers_mut()
+        .insert(RETRY_AFTER, HeaderValue::from(retry_after_secs));
+
+    response
+}
+
+/// Limits the GraphQL endpoint by root field, e.g. `createOrder`. The limited fields are configured
+/// in the `rate_limit` section.
+pub async fn rate_limit_graphql(
+    State(limiter): State<Arc<RateLimiter>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let is_multipart = request
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("multipart/"));
+
+    // No endpoint takes uploads, and the operations of a multipart request aren't inspected
+    if is_multipart {
+        return AppError::Validation("Multipart requests are not supported".into()).into_response();
+    }
+
+    let (parts, body) = request.into_parts();
+    let Ok(bytes) = to_bytes(body, MAX_BODY_SIZE).await else {
+        return AppError::Validation("The request is too large".into()).into_response();
+    };
+
+    let fields = match serde_json::from_slice::<GraphqlRequestBody>(&bytes) {
+        Ok(graphql_request) => match limiter.requested_fields(&graphql_request) {
+            Some(fields) => fields,
+            // The automatic persisted query protocol, the client sends the query with its hash again
+            None => {
+                let mut error = server_error(AppError::NotFound("PersistedQueryNotFound".into()));
+                error
+                    .extensions
+                    .get_or_insert_with(Default::default)
+                    .set("code", "PERSISTED_QUERY_NOT_FOUND");
+
+                return Json(async_graphql::Response::from_errors(vec![error])).into_response();
+            }
+        },
+        // Not a GraphQL request, the handler rejects it
+        Err(_) => vec![],
+    };
+
+    match limiter.check(&parts.headers, &fields).await {
+        Decision::Allowed => {
+            next.run(Request::from_parts(parts, Body::from(bytes)))
+                .await
+        }
+        Decision::Limited { retry_after } => rate_limited(retry_after, true),
+    }
+}
+
+/// Limits a REST endpoint by its route, e.g. `/paystack/webhook`
+pub async fn rate_limit_rest(
+    State(limiter): State<Arc<RateLimiter>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+
+    match limiter.check(request.headers(), &[route]).await {
+        Decision::Allowed => next.run(request).await,
+        Decision::Limited { retry_after } => rate_limited(retry_after, false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{database::memory::connect_in_memory, test_support::FakeServices};
+    use axum::http::StatusCode;
+
+    const LIMIT: RateLimit = RateLimit {
+        capacity: 2,
+        period: Duration::from_secs(10),
+    };
+
+    #[test]
+    fn refills_the_memory_buckets_over_time() {
+        let store = MemoryStore::default();
+        let now = Instant::now();
+
+        assert_eq!(
+            store.take_at("createOrder:ip:1", &LIMIT, now),
+            Decision::Allowed
+        );
+        assert_eq!(
+            store.take_at("createOrder:ip:1", &LIMIT, now),
+            Decision::Allowed
+        );
+        assert!(matches!(
+            store.take_at("createOrder:ip:1", &LIMIT, now),
+            Decision::Limited { retry_after } if retry_after.as_secs_f64().round() == 5.0
+        ));
+        // Other clients have their own bucket
+        assert_eq!(
+            store.take_at("createOrder:ip:2", &LIMIT, now),
+            Decision::Allowed
+        );
+
+        let later = now + Duration::from_secs(6);
+        assert_eq!(
+            store.take_at("createOrder:ip:1", &LIMIT, later),
+            Decision::Allowed
+        );
+    }
+
+    #[test]
+    fn keeps_at_most_max_memory_buckets() {
+        let store = MemoryStore::default();
+        let now = Instant::now();
+
+        // Draining buckets, none of them can be dropped for having refilled
+        for client in 0..MAX_MEMORY_BUCKETS {
+            store.take_at(&format!("createOrder:ip:{}", client), &LIMIT, now);
+        }
+        let later = now + Duration::from_secs(1);
+        store.take_at("createOrder:ip:new", &LIMIT, later);
+
+        let buckets = store.buckets.lock().unwrap();
+        assert_eq!(buckets.len(), MAX_MEMORY_BUCKETS);
+        assert!(buckets.contains_key("createOrder:ip:new"));
+    }
+
+    #[tokio::test]
+    async fn shares_the_buckets_through_surrealdb() {
+        let db = connect_in_memory(&[]).await.unwrap();
+        let store = SurrealStore::new(db);
+
+        assert_eq!(
+            store.take("createOrder:ip:1", &LIMIT).await,
+            Ok(Decision::Allowed)
+        );
+        assert_eq!(
+            store.take("createOrder:ip:1", &LIMIT).await,
+            Ok(Decision::Allowed)
+        );
+        assert!(matches!(
+            store.take("createOrder:ip:1", &LIMIT).await,
+            Ok(Decision::Limited { .. })
+        ));
+        assert_eq!(
+            store.take("createOrder:ip:2", &LIMIT).await,
+            Ok(Decision::Allowed)
+        );
+    }
+
+    #[tokio::test]
+    async fn deletes_the_refilled_surrealdb_buckets() {
+        let db = connect_in_memory(&[]).await.unwrap();
+        let store = SurrealStore::new(db.clone());
+        let rows = || async {
+            let ids: Vec<surrealdb::sql::Thing> = db
+                .query("SELECT VALUE id FROM rate_limit")
+                .await
+                .unwrap()
+                .take(0)
+                .unwrap();
+            ids.len()
+        };
+
+        store
+            .take_at("createOrder:ip:1", &LIMIT, 1_000)
+            .await
+            .unwrap();
+        store
+            .take_at("createOrder:ip:2", &LIMIT, 1_000)
+            .await
+            .unwrap();
+        assert_eq!(rows().await, 2);
+
+        // Refilled after 5s, but only deleted once the sweep is due
+        store
+            .take_at("createOrder:ip:2", &LIMIT, 7_000)
+            .await
+            .unwrap();
+        assert_eq!(rows().await, 2);
+
+        let sweep = 1_000 + SURREAL_SWEEP_INTERVAL.as_millis() as u64;
+        store
+            .take_at("createOrder:ip:3", &LIMIT, sweep)
+            .await
+            .unwrap();
+        assert_eq!(rows().await, 1);
+    }
+
+    #[tokio::test]
+    async fn counts_unauthenticated_authorization_headers_by_ip() {
+        let fake_services = FakeServices::start().await.unwrap();
+        fake_services.acl.reject_auth();
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-real-ip", HeaderValue::from_static("10.0.0.1"));
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer made-up"));
+        assert_eq!(client_keys(&headers).await, vec!["ip:10.0.0.1"]);
+
+        // Not forwarded to the ACL service at all
+        headers.insert(
+            AUTHORIZATION,
+            HeaderValue::from_bytes(b"Bearer \xff").unwrap(),
+        );
+        assert_eq!(client_keys(&headers).await, vec!["ip:10.0.0.1"]);
+        assert_eq!(fake_services.acl.check_auth.call_count(), 1);
+        assert_eq!(fake_services.acl.check_auth.calls()[0].cookie, None);
+    }
+
+    #[test]
+    fn finds_the_root_fields_of_the_operation() {
+        let query = "
+            mutation Checkout { a: createOrder b: createOrder ...Comment }
+            mutation Rate { rateProduct(rating: 5) }
+            fragment Comment on Mutation { ... on Mutation { postComment(content: \"Nice\") { id } } }
+        ";
+
+        assert_eq!(
+            root_fields(query, Some("Checkout")),
+            vec!["createOrder", "createOrder", "postComment"]
+        );
+        assert_eq!(root_fields(query, Some("Rate")), vec!["rateProduct"]);
+        assert!(root_fields("mutation {", None).is_empty());
+    }
+
+    #[tokio::test]
+    async fn limits_clients_by_ip_and_asks_for_unknown_persisted_queries() {
+        let db = connect_in_memory(&[]).await.unwrap();
+        let mut config = AppConfig::local("widgets");
+        config.rate_limit.limits = HashMap::from([("createOrder".to_string(), LIMIT)]);
+        let limiter = RateLimiter::new(&config, db);
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-real-ip", HeaderValue::from_static("10.0.0.1"));
+        let operations = vec!["createOrder".to_string(), "getProducts".to_string()];
+
+        assert_eq!(
+            limiter.check(&headers, &operations).await,
+            Decision::Allowed
+        );
+        assert_eq!(
+            limiter.check(&headers, &operations).await,
+            Decision::Allowed
+        );
+        let Decision::Limited { retry_after } = limiter.check(&headers, &operations).await else {
+            panic!("The third order wasn't limited");
+        };
+
+        let response = rate_limited(retry_after, true);
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[RETRY_AFTER], "5");
+
+        // A new session cookie doesn't reset the IP's bucket
+        headers.insert(COOKIE, HeaderValue::from_static("session_id=made-up"));
+        assert!(matches!(
+            limiter.check(&headers, &operations).await,
+            Decision::Limited { .. }
+        ));
+
+        let hash = query_hash("mutation { createOrder }");
+        let by_hash: GraphqlRequestBody = serde_json::from_value(serde_json::json!({
+            "extensions": { "persistedQuery": { "version": 1, "sha256Hash": hash } }
+        }))
+        .unwrap();
+        assert_eq!(limiter.requested_fields(&by_hash), None);
+
+        let registration: GraphqlRequestBody = serde_json::from_value(serde_json::json!({
+            "query": "mutation { createOrder }",
+            "extensions": { "persistedQuery": { "version": 1, "sha256Hash": hash } }
+        }))
+        .unwrap();
+        limiter.requested_fields(&registration);
+        assert_eq!(
+            limiter.requested_fields(&by_hash),
+            Some(vec!["createOrder".to_string()])
+        );
+    }
+}
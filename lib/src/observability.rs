@@ -0,0 +1,479 @@
+use std::{
+    future::Future,
+    sync::{Arc, LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+use async_graphql::{
+    extensions::{
+        Extension, ExtensionContext, ExtensionFactory, NextExecute, NextParseQuery, NextRequest,
+    },
+    parser::types::{DocumentOperations, ExecutableDocument, OperationDefinition, Selection},
+    Response as GraphqlResponse, ServerResult, Variables,
+};
+use axum::{
+    extract::{MatchedPath, Request as HttpRequest},
+    http::{header::CONTENT_TYPE, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response as HttpResponse},
+    routing::get,
+    Router,
+};
+use prometheus::{
+    CounterVec, Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, Opts, Registry,
+    TextEncoder,
+};
+use tonic::{
+    body::BoxBody,
+    codegen::http::{Request, Response},
+    Code, Status,
+};
+use tonic_middleware::{Middleware, ServiceBound};
+
+use crate::utils::models::OrderStatus;
+
+/// Every metric the services export. Prometheus tells the services apart by their scrape target.
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+    graphql_requests: IntCounterVec,
+    graphql_request_duration: HistogramVec,
+    grpc_requests: IntCounterVec,
+    grpc_request_duration: HistogramVec,
+    grpc_client_requests: IntCounterVec,
+    grpc_client_request_duration: HistogramVec,
+    carts_created: IntCounter,
+    orders_by_status: IntCounterVec,
+    webhooks: IntCounterVec,
+    events: IntCounterVec,
+    revenue: CounterVec,
+}
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+
+        let counter = |name: &str, help: &str, labels: &[&str]| {
+            let counter = IntCounterVec::new(Opts::new(name, help), labels).unwrap();
+            registry.register(Box::new(counter.clone())).unwrap();
+            counter
+        };
+
+        let histogram = |name: &str, help: &str, labels: &[&str]| {
+            let histogram = HistogramVec::new(HistogramOpts::new(name, help), labels).unwrap();
+            registry.register(Box::new(histogram.clone())).unwrap();
+            histogram
+        };
+
+        let http_requests = counter(
+            "http_requests_total",
+            "REST requests by route, method and status",
+            &["route", "method", "status"],
+        );
+        let http_request_duration = histogram(
+            "http_request_duration_seconds",
+            "REST request latency by route and method",
+            &["route", "method"],
+        );
+        let graphql_requests = counter(
+            "graphql_requests_total",
+            "GraphQL requests by root field and outcome",
+            &["root_field", "outcome"],
+        );
+        let graphql_request_duration = histogram(
+            "graphql_request_duration_seconds",
+            "GraphQL request latency by root field",
+            &["root_field"],
+        );
+        let grpc_requests = counter(
+            "grpc_server_requests_total",
+            "Served gRPC requests by method and status code",
+            &["method", "code"],
+        );
+        let grpc_request_duration = histogram(
+            "grpc_server_request_duration_seconds",
+            "Served gRPC request latency by method",
+            &["method"],
+        );
+        let grpc_client_requests = counter(
+            "grpc_client_requests_total",
+            "Outbound gRPC calls by target service, method and status code",
+            &["target", "method", "code"],
+        );
+        let grpc_client_request_duration = histogram(
+            "grpc_client_request_duration_seconds",
+            "Outbound gRPC call latency by target service and method",
+            &["target", "method"],
+        );
+        let orders_by_status = counter(
+            "orders_total",
+            "Orders created or moved to a status, by status",
+            &["status"],
+        );
+        let webhooks = counter(
+            "webhooks_total",
+            "Payment provider webhooks by provider and outcome (received, failed)",
+            &["provider", "outcome"],
+        );
+        let events = counter(
+            "domain_events_total",
+            "Domain events by type and outcome (delivered, failed, received)",
+            &["event", "outcome"],
+        );
+
+        let carts_created = IntCounter::new("carts_created_total", "Carts created").unwrap();
+        registry.register(Box::new(carts_created.clone())).unwrap();
+
+        let revenue = CounterVec::new(
+            Opts::new(
+                "revenue_total",
+                "Revenue from successful charges in the currency's main unit, by currency",
+            ),
+            &["currency"],
+        )
+        .unwrap();
+        registry.register(Box::new(revenue.clone())).unwrap();
+
+        Self {
+            registry,
+            http_requests,
+            http_request_duration,
+            graphql_requests,
+            graphql_request_duration,
+            grpc_requests,
+            grpc_request_duration,
+            grpc_client_requests,
+            grpc_client_request_duration,
+            carts_created,
+            orders_by_status,
+            webhooks,
+            events,
+            revenue,
+        }
+    }
+
+    /// The metrics in the Prometheus text format
+    pub fn encode(&self) -> String {
+        let mut buffer = vec![];
+
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            tracing::error!("Failed to encode metrics: {}", e);
+        }
+
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+fn metrics_router() -> Router {
+    Router::new().route("/metrics", get(metrics_handler))
+}
+
+/// Serve `/metrics` on its own port, so it stays off the public app and the gateway.
+/// Only Prometheus should be able to reach the port.
+pub async fn serve_metrics(port: u16) {
+    let listener = match tokio::net::TcpListener::bind(format!("0.0.0.0:{}", port)).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            tracing::error!("Failed to bind the metrics port {}: {}", port, e);
+            return;
+        }
+    };
+
+    if let Err(e) = axum::serve(listener, metrics_router()).await {
+        tracing::error!("Metrics server failed: {}", e);
+    }
+}
+
+async fn metrics_handler() -> impl IntoResponse {
+    (
+        StatusCode::OK,
+        [(CONTENT_TYPE, TextEncoder::new().format_type().to_string())],
+        metrics().encode(),
+    )
+}
+
+/// Axum middleware recording every REST request, use with `axum::middleware::from_fn`.
+/// Requests are labelled with the matched route so path parameters don't explode the label set.
+pub async fn track_http(request: HttpRequest, next: Next) -> HttpResponse {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let method = request.method().to_string();
+    let start = Instant::now();
+
+    let response = next.run(request).await;
+
+    let metrics = metrics();
+    metrics
+        .http_request_duration
+        .with_label_values(&[&route, &method])
+        .observe(start.elapsed().as_secs_f64());
+    metrics
+        .http_requests
+        .with_label_values(&[&route, &method, response.status().as_str()])
+        .inc();
+
+    response
+}
+
+/// Records every executed GraphQL request, see `schema::configure_schema`. Requests are labelled
+/// with the first root field of their operation rather than the operation name the client picked,
+/// so the label set is bounded by the schema. Requests that fail before execution (unparsable,
+/// invalid or rejected) are labelled "other".
+pub(crate) struct GraphqlMetrics;
+
+impl ExtensionFactory for GraphqlMetrics {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(GraphqlMetricsExtension::default())
+    }
+}
+
+#[derive(Default)]
+struct GraphqlMetricsExtension {
+    document: Mutex<Option<ExecutableDocument>>,
+    root_field: Mutex<Option<String>>,
+}
+
+#[async_trait::async_trait]
+impl Extension for GraphqlMetricsExtension {
+    async fn request(&self, ctx: &ExtensionContext<'_>, next: NextRequest<'_>) -> GraphqlResponse {
+        let start = Instant::now();
+
+        let response = next.run(ctx).await;
+
+        let root_field = self.root_field.lock().unwrap().take();
+        record_graphql(
+            root_field.as_deref().unwrap_or("other"),
+            start.elapsed(),
+            response.is_err(),
+        );
+
+        response
+    }
+
+    async fn parse_query(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        query: &str,
+        variables: &Variables,
+        next: NextParseQuery<'_>,
+    ) -> ServerResult<ExecutableDocument> {
+        let document = next.run(ctx, query, variables).await?;
+        *self.document.lock().unwrap() = Some(document.clone());
+
+        Ok(document)
+    }
+
+    /// Only runs once the document is valid, so the root field is one of the schema's
+    async fn execute(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        operation_name: Option<&str>,
+        next: NextExecute<'_>,
+    ) -> GraphqlResponse {
+        if let Some(document) = self.document.lock().unwrap().take() {
+            *self.root_field.lock().unwrap() = root_field(&document, operation_name);
+        }
+
+        next.run(ctx, operation_name).await
+    }
+}
+
+fn root_field(document: &ExecutableDocument, operation_name: Option<&str>) -> Option<String> {
+    let operation: &OperationDefinition = match (&document.operations, operation_name) {
+        (DocumentOperations::Single(operation), _) => &operation.node,
+        (DocumentOperations::Multiple(operations), Some(name)) => &operations.get(name)?.node,
+        (DocumentOperations::Multiple(operations), None) => &operations.values().next()?.node,
+    };
+
+    match &operation.selection_set.node.items.first()?.node {
+        Selection::Field(field) => Some(field.node.name.node.to_string()),
+        _ => None,
+    }
+}
+
+fn record_graphql(root_field: &str, duration: Duration, has_errors: bool) {
+    let outcome = match has_errors {
+        true => "error",
+        false => "ok",
+    };
+
+    let metrics = metrics();
+    metrics
+        .graphql_request_duration
+        .with_label_values(&[root_field])
+        .observe(duration.as_secs_f64());
+    metrics
+        .graphql_requests
+        .with_label_values(&[root_field, outcome])
+        .inc();
+}
+
+/// Records every served gRPC request. Add it before `AuthMiddleware` so rejected requests are counted too.
+#[derive(Default, Clone)]
+pub struct MetricsMiddleware;
+
+#[async_trait::async_trait]
+impl<S> Middleware<S> for MetricsMiddleware
+where
+    S: ServiceBound,
+    S::Future: Send,
+    S::Error: From<tonic::Status> + Send + 'static,
+{
+    async fn call(
+        &self,
+        req: Request<BoxBody>,
+        mut service: S,
+    ) -> Result<Response<BoxBody>, S::Error> {
+        let method = req.uri().path().to_string();
+        let start = Instant::now();
+
+        let result = service.call(req).await;
+
+        // Errors are sent as trailers-only responses, so the status is in the headers.
+        // Successful responses carry it in the trailers instead.
+        let code = match &result {
+            Ok(response) => response
+                .headers()
+                .get("grpc-status")
+                .map(|status| Code::from_bytes(status.as_bytes()))
+                .unwrap_or(Code::Ok),
+            Err(_) => Code::Unknown,
+        };
+
+        let metrics = metrics();
+        metrics
+            .grpc_request_duration
+            .with_label_values(&[&method])
+            .observe(start.elapsed().as_secs_f64());
+        metrics
+            .grpc_requests
+            .with_label_values(&[&method, &format!("{:?}", code)])
+            .inc();
+
+        result
+    }
+}
+
+/// Time an outbound gRPC call and record its status code, e.g.
+/// `observe_grpc_call("products", "GetProductPrice", client.get_product_price(request)).await`
+pub async fn observe_grpc_call<T, F>(
+    target: &str,
+    method: &str,
+    call: F,
+) -> Result<tonic::Response<T>, Status>
+where
+    F: Future<Output = Result<tonic::Response<T>, Status>>,
+{
+    let start = Instant::now();
+
+    let result = call.await;
+
+    let code = match &result {
+        Ok(_) => Code::Ok,
+        Err(status) => status.code(),
+    };
+
+    let metrics = metrics();
+    metrics
+        .grpc_client_request_duration
+        .with_label_values(&[target, method])
+        .observe(start.elapsed().as_secs_f64());
+    metrics
+        .grpc_client_requests
+        .with_label_values(&[target, method, &format!("{:?}", code)])
+        .inc();
+
+    result
+}
+
+pub fn record_cart_created() {
+    metrics().carts_created.inc();
+}
+
+/// Record an order being created (Pending) or moved to another status
+pub fn record_order_status(status: &OrderStatus) {
+    metrics()
+        .orders_by_status
+        .with_label_values(&[&format!("{:?}", status)])
+        .inc();
+}
+
+/// Record a webhook from a payment provider. `outcome` is "received" for every delivery and "failed"
+/// for the ones that could not be processed.
+pub fn record_webhook(provider: &str, outcome: &str) {
+    metrics()
+        .webhooks
+        .with_label_values(&[provider, outcome])
+        .inc();
+}
+
+/// Record a domain event being delivered to the subscribers or failing to be, or being received from
+/// another service
+pub fn record_domain_event(event: &str, outcome: &str) {
+    metrics().events.with_label_values(&[event, outcome]).inc();
+}
+
+/// Record revenue from a successful charge, in the currency's main unit (e.g. KES, not cents)
+pub fn record_revenue(currency: &str, amount: f64) {
+    metrics()
+        .revenue
+        .with_label_values(&[&currency.to_uppercase()])
+        .inc_by(amount);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_graphql::{EmptyMutation, EmptySubscription, Object, Schema};
+
+    struct Query;
+
+    #[Object]
+    impl Query {
+        async fn get_products(&self) -> bool {
+            true
+        }
+    }
+
+    #[tokio::test]
+    async fn exports_recorded_metrics() {
+        let schema = Schema::build(Query, EmptyMutation, EmptySubscription)
+            .extension(GraphqlMetrics)
+            .finish();
+        schema
+            .execute("query clientChosenName { getProducts }")
+            .await;
+        schema.execute("{ unknownField }").await;
+        record_revenue("kes", 150.0);
+        // A method the other tests don't call, the registry is shared
+        observe_grpc_call("acl", "GetUserEmail", async {
+            Err::<tonic::Response<()>, _>(Status::unauthenticated("No token"))
+        })
+        .await
+        .unwrap_err();
+
+        let exported = metrics().encode();
+
+        assert!(
+            exported.contains(r#"graphql_requests_total{outcome="ok",root_field="getProducts"} 1"#)
+        );
+        assert!(
+            exported.contains(r#"graphql_requests_total{outcome="error",root_field="other"} 1"#)
+        );
+        assert!(!exported.contains("clientChosenName"));
+        assert!(exported.contains(r#"revenue_total{currency="KES"} 150"#));
+        assert!(exported.contains(
+            r#"grpc_client_requests_total{code="Unauthenticated",method="GetUserEmail",target="acl"} 1"#
+        ));
+    }
+}
@@ -0,0 +1,265 @@
+use std::{collections::HashMap, fs, sync::Arc, time::Duration};
+
+use async_graphql::{
+    extensions::{
+        apollo_persisted_queries::{ApolloPersistedQueries, LruCacheStorage},
+        Extension, ExtensionContext, ExtensionFactory, NextExecute, NextPrepareRequest,
+    },
+    ObjectType, Request, Response, SchemaBuilder, ServerError, ServerResult, SubscriptionType,
+    Value,
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::{config::AppConfig, error::AppError, observability::GraphqlMetrics};
+
+/// Apply the limits every public GraphQL endpoint shares: the complexity budget, the operation
+/// timeout and persisted queries. Automatic persisted queries are cached unless an allow-list is
+/// configured, then only the listed operations run. Production also disables introspection and
+/// limits the query depth. The requests' metrics are recorded too, see `observability`.
+pub fn configure_schema<Query, Mutation, Subscription>(
+    builder: SchemaBuilder<Query, Mutation, Subscription>,
+    config: &AppConfig,
+) -> SchemaBuilder<Query, Mutation, Subscription>
+where
+    Query: ObjectType + 'static,
+    Mutation: ObjectType + 'static,
+    Subscription: SubscriptionType + 'static,
+{
+    let graphql = &config.graphql;
+
+    let timeout = OperationTimeout {
+        timeout: graphql.operation_timeout,
+    };
+    let mut builder = builder
+        .limit_complexity(graphql.max_complexity)
+        .extension(GraphqlMetrics)
+        .extension(timeout);
+
+    builder = match &graphql.allow_list {
+        Some(operations) => builder.extension(AllowList {
+            operations: operations.clone(),
+        }),
+        None => builder.extension(ApolloPersistedQueries::new(LruCacheStorage::new(
+            graphql.persisted_query_cache_size,
+        ))),
+    };
+
+    if config.is_prod() {
+        builder = builder
+            .disable_introspection()
+            .limit_depth(graphql.max_depth);
+    }
+
+    builder
+}
+
+#[derive(Deserialize)]
+struct PersistedQueryManifest {
+    operations: Vec<PersistedOperation>,
+}
+
+#[derive(Deserialize)]
+struct PersistedOperation {
+    id: String,
+    body: String,
+}
+
+/// Load an Apollo persisted query manifest (`{ "operations": [{ "id": ..., "body": ... }] }`) into
+/// a hash to query map. The IDs have to be the SHA-256 of the bodies, clients send the same hash.
+pub fn load_allow_list(path: &str) -> Result<HashMap<String, String>, String> {
+    let manifest = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let manifest: PersistedQueryManifest =
+        serde_json::from_str(&manifest).map_err(|e| e.to_string())?;
+
+    manifest
+        .operations
+        .into_iter()
+        .map(|operation| {
+            if query_hash(&operation.body) == operation.id {
+                Ok((operation.id, operation.body))
+            } else {
+                Err(format!(
+                    "operation {} is not the SHA-256 of its body",
+                    operation.id
+                ))
+            }
+        })
+        .collect()
+}
+
+pub(crate) fn query_hash(query: &str) -> String {
+    hex::encode(Sha256::digest(query.as_bytes()))
+}
+
+/// A request error with the `code` and `status` extensions of `AppError`
+pub(crate) fn server_error(error: AppError) -> ServerError {
+    let error: async_graphql::Error = error.into();
+    let mut server_error = ServerError::new(error.message, None);
+    server_error.extensions = error.extensions;
+
+    server_error
+}
+
+/// Only runs the operations of the allow-list. Clients either send the hash in the
+/// `persistedQuery` extension, like automatic persisted queries, or the exact query.
+/// The gateway is a client too: its `_service` and `_entities` queries aren't exempt, so the
+/// manifest has to list the subgraph queries of its query plans.
+#[derive(Clone)]
+struct AllowList {
+    operations: Arc<HashMap<String, String>>,
+}
+
+impl ExtensionFactory for AllowList {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(self.clone())
+    }
+}
+
+#[async_trait::async_trait]
+impl Extension for AllowList {
+    async fn prepare_request(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        mut request: Request,
+        next: NextPrepareRequest<'_>,
+    ) -> ServerResult<Request> {
+        let sent_hash = match request.extensions.get("persistedQuery") {
+            Some(Value::Object(persisted_query)) => match persisted_query.get("sha256Hash") {
+                Some(Value::String(hash)) => Some(hash.clone()),
+                _ => None,
+            },
+            _ => None,
+        };
+        let hash = sent_hash.unwrap_or_else(|| query_hash(&request.query));
+
+        match self.operations.get(&hash) {
+            Some(query) if request.query.is_empty() || request.query == *query => {
+                request.query = query.clone();
+                next.run(ctx, request).await
+            }
+            _ => {
+                tracing::warn!("Rejected an operation that is not allow-listed: {}", hash);
+                Err(server_error(AppError::Forbidden(
+                    "Operation is not allow-listed".into(),
+                )))
+            }
+        }
+    }
+}
+
+/// Fails the operations that run longer than the timeout. Subscriptions aren't executed through
+/// this hook, so they stay open.
+#[derive(Clone)]
+struct OperationTimeout {
+    timeout: Duration,
+}
+
+impl ExtensionFactory for OperationTimeout {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(self.clone())
+    }
+}
+
+#[async_trait::async_trait]
+impl Extension for OperationTimeout {
+    async fn execute(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        operation_name: Option<&str>,
+        next: NextExecute<'_>,
+    ) -> Response {
+        match tokio::time::timeout(self.timeout, next.run(ctx, operation_name)).await {
+            Ok(response) => response,
+            Err(_) => {
+                tracing::warn!("{:?} timed out after {:?}", operation_name, self.timeout);
+                Response::from_errors(vec![server_error(AppError::Timeout(
+                    "Operation timed out".into(),
+                ))])
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_graphql::{EmptyMutation, EmptySubscription, Object, Schema};
+
+    struct Query;
+
+    #[Object]
+    impl Query {
+        async fn cheap(&self) -> bool {
+            true
+        }
+
+        #[graphql(complexity = 50)]
+        async fn expensive(&self) -> bool {
+            true
+        }
+
+        async fn slow(&self) -> bool {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            true
+        }
+    }
+
+    fn schema(config: &AppConfig) -> Schema<Query, EmptyMutation, EmptySubscription> {
+        configure_schema(
+            Schema::build(Query, EmptyMutation, EmptySubscription),
+            config,
+        )
+        .finish()
+    }
+
+    fn error_code(response: &Response) -> Option<Value> {
+        response.errors[0]
+            .extensions
+            .as_ref()
+            .and_then(|extensions| extensions.get("code").cloned())
+    }
+
+    #[tokio::test]
+    async fn enforces_the_complexity_budget_and_the_timeout() {
+        let mut config = AppConfig::local("widgets");
+        config.graphql.max_complexity = 40;
+        config.graphql.operation_timeout = Duration::from_millis(20);
+        let schema = schema(&config);
+
+        assert!(schema.execute("{ cheap }").await.errors.is_empty());
+
+        let response = schema.execute("{ expensive }").await;
+        assert!(response.errors[0].message.contains("complex"));
+
+        let response = schema.execute("{ slow }").await;
+        assert_eq!(error_code(&response), Some("TIMEOUT".into()));
+    }
+
+    #[tokio::test]
+    async fn only_runs_allow_listed_operations() {
+        let mut config = AppConfig::local("widgets");
+        config.graphql.allow_list = Some(Arc::new(HashMap::from([(
+            query_hash("{ cheap }"),
+            "{ cheap }".to_string(),
+        )])));
+        let schema = schema(&config);
+
+        assert!(schema.execute("{ cheap }").await.errors.is_empty());
+
+        let mut by_hash = Request::new("");
+        by_hash.extensions.insert(
+            "persistedQuery".to_string(),
+            Value::from_json(serde_json::json!({
+                "version": 1,
+                "sha256Hash": query_hash("{ cheap }"),
+            }))
+            .unwrap(),
+        );
+        let response = schema.execute(by_hash).await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+
+        let response = schema.execute("{ expensive }").await;
+        assert_eq!(error_code(&response), Some("FORBIDDEN".into()));
+    }
+}
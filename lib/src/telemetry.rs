@@ -0,0 +1,201 @@
+use axum::{
+    extract::{MatchedPath, Request as HttpRequest},
+    middleware::Next,
+    response::Response as HttpResponse,
+};
+use hyper::{
+    header::{HeaderName, HeaderValue},
+    HeaderMap,
+};
+use opentelemetry::{
+    global,
+    propagation::{Extractor, Injector},
+    trace::TracerProvider as _,
+    Context, KeyValue,
+};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{
+    propagation::TraceContextPropagator,
+    runtime,
+    trace::{Sampler, Tracer, TracerProvider},
+    Resource,
+};
+use tonic::metadata::{MetadataKey, MetadataMap, MetadataValue};
+use tracing::{Instrument, Span, Subscriber};
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::{config::AppConfig, logging::request_id_from_headers};
+
+/// The layer exporting spans to the OTLP collector, if one is configured. Also installs the W3C trace
+/// context propagator, so a trace started by any service is continued by the others.
+pub fn otel_layer<S>(config: &AppConfig) -> Option<OpenTelemetryLayer<S, Tracer>>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let endpoint = config.telemetry.otlp_endpoint.as_ref()?;
+
+    match build_tracer_provider(config, endpoint) {
+        Ok(provider) => {
+            let tracer = provider.tracer(config.service.clone());
+            global::set_tracer_provider(provider);
+            Some(tracing_opentelemetry::layer().with_tracer(tracer))
+        }
+        Err(e) => {
+            eprintln!("Failed to set up trace export to {}: {}", endpoint, e);
+            None
+        }
+    }
+}
+
+fn build_tracer_provider(
+    config: &AppConfig,
+    endpoint: &str,
+) -> Result<TracerProvider, opentelemetry::trace::TraceError> {
+    let exporter = SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(endpoint)
+        .build()?;
+
+    Ok(TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+            config.telemetry.sample_ratio,
+        ))))
+        .with_resource(Resource::new(vec![
+            KeyValue::new("service.name", config.service.clone()),
+            KeyValue::new("deployment.environment", config.environment.clone()),
+        ]))
+        .build())
+}
+
+/// Flush the spans that haven't been exported yet. Call it before the service exits.
+pub fn shutdown_tracing() {
+    global::shutdown_tracer_provider();
+}
+
+/// Axum middleware that runs each request in a span continuing the caller's trace, if any.
+/// Use with `axum::middleware::from_fn`.
+pub async fn trace_http(request: HttpRequest, next: Next) -> HttpResponse {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+
+    let span = tracing::info_span!(
+        "http_request",
+        method = %request.method(),
+        route = %route,
+        request_id = %request_id_from_headers(request.headers()),
+        status = tracing::field::Empty,
+    );
+    span.set_parent(extract_context(request.headers()));
+
+    let response = next.run(request).instrument(span.clone()).await;
+    span.record("status", response.status().as_u16());
+
+    response
+}
+
+/// The trace context sent by the caller in the `traceparent`/`tracestate` headers. gRPC metadata is
+/// sent as HTTP/2 headers, so this works for both REST and gRPC requests.
+pub fn extract_context(headers: &HeaderMap) -> Context {
+    global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(headers)))
+}
+
+/// Add the current span's trace context to an outgoing gRPC request
+pub fn inject_context(metadata: &mut MetadataMap) {
+    let context = Span::current().context();
+
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut MetadataInjector(metadata))
+    });
+}
+
+/// Add the current span's trace context to the headers of an outgoing HTTP request
+pub fn inject_http_context(headers: &mut HeaderMap) {
+    let context = Span::current().context();
+
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut HeaderInjector(headers))
+    });
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
+
+struct MetadataInjector<'a>(&'a mut MetadataMap);
+
+impl Injector for MetadataInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(key), Ok(value)) = (
+            MetadataKey::from_bytes(key.as_bytes()),
+            MetadataValue::try_from(value.as_str()),
+        ) {
+            self.0.insert(key, value);
+        }
+    }
+}
+
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(key), Ok(value)) = (
+            HeaderName::from_bytes(key.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            self.0.insert(key, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{
+        SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState,
+    };
+
+    #[test]
+    fn propagates_the_trace_context_through_grpc_metadata() {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+
+        let span_context = SpanContext::new(
+            TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap(),
+            SpanId::from_hex("00f067aa0ba902b7").unwrap(),
+            TraceFlags::SAMPLED,
+            true,
+            TraceState::default(),
+        );
+        let context = Context::new().with_remote_span_context(span_context);
+
+        let mut metadata = MetadataMap::new();
+        global::get_text_map_propagator(|propagator| {
+            propagator.inject_context(&context, &mut MetadataInjector(&mut metadata))
+        });
+
+        assert_eq!(
+            metadata.get("traceparent").unwrap(),
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+        );
+
+        let extracted = extract_context(&metadata.into_headers());
+        assert_eq!(
+            extracted.span().span_context().trace_id(),
+            context.span().span_context().trace_id()
+        );
+    }
+}
@@ -0,0 +1,167 @@
+use std::sync::Arc;
+
+use tonic::{Request, Response, Status};
+
+use super::recorder::FakeMethod;
+use crate::integration::grpc::clients::{
+    acl_service::{
+        acl_server::Acl, AuthDetails, AuthStatus, Empty, GetUserEmailRequest, GetUserEmailResponse,
+    },
+    email_service::{email_service_server::EmailService, Email, EmailResponse},
+    files_service::{
+        files_service_server::FilesService, FileId, FileName, PurchaseFileDetails,
+        PurchaseFileResponse,
+    },
+};
+
+/// The user the fake ACL service authenticates by default
+pub const FAKE_USER_ID: &str = "test-user";
+/// The token the fake ACL service hands out to services signing in
+pub const FAKE_SERVICE_TOKEN: &str = "test-service-token";
+
+/// In-process stand-in for the ACL service
+#[derive(Clone)]
+pub struct FakeAcl {
+    pub check_auth: Arc<FakeMethod<Empty, AuthStatus>>,
+    pub sign_in_as_service: Arc<FakeMethod<Empty, AuthDetails>>,
+    pub get_user_email: Arc<FakeMethod<GetUserEmailRequest, GetUserEmailResponse>>,
+}
+
+impl Default for FakeAcl {
+    fn default() -> Self {
+        Self {
+            check_auth: FakeMethod::new(AuthStatus {
+                is_auth: true,
+                sub: FAKE_USER_ID.to_string(),
+            }),
+            sign_in_as_service: FakeMethod::new(AuthDetails {
+                token: FAKE_SERVICE_TOKEN.to_string(),
+            }),
+            get_user_email: FakeMethod::new(GetUserEmailResponse {
+                email: format!("{}@example.com", FAKE_USER_ID),
+            }),
+        }
+    }
+}
+
+impl FakeAcl {
+    /// Authenticate every following request as the user
+    pub fn authenticate_as(&self, user_id: &str) {
+        self.check_auth.respond_with(AuthStatus {
+            is_auth: true,
+            sub: user_id.to_string(),
+        });
+    }
+
+    /// Reject every following request as unauthenticated
+    pub fn reject_auth(&self) {
+        self.check_auth
+            .fail_with(Status::unauthenticated("Not Authorized!"));
+    }
+}
+
+#[tonic::async_trait]
+impl Acl for FakeAcl {
+    async fn check_auth(&self, request: Request<Empty>) -> Result<Response<AuthStatus>, Status> {
+        self.check_auth.handle(request)
+    }
+
+    async fn sign_in_as_service(
+        &self,
+        request: Request<Empty>,
+    ) -> Result<Response<AuthDetails>, Status> {
+        self.sign_in_as_service.handle(request)
+    }
+
+    async fn get_user_email(
+        &self,
+        request: Request<GetUserEmailRequest>,
+    ) -> Result<Response<GetUserEmailResponse>, Status> {
+        self.get_user_email.handle(request)
+    }
+}
+
+/// In-process stand-in for the email service
+#[derive(Clone)]
+pub struct FakeEmail {
+    pub send_email: Arc<FakeMethod<Email, EmailResponse>>,
+}
+
+impl Default for FakeEmail {
+    fn default() -> Self {
+        Self {
+            send_email: FakeMethod::new(EmailResponse {
+                message: "Email sent".to_string(),
+            }),
+        }
+    }
+}
+
+impl FakeEmail {
+    /// The emails sent so far, oldest first
+    pub fn sent(&self) -> Vec<Email> {
+        self.send_email
+            .calls()
+            .into_iter()
+            .map(|call| call.message)
+            .collect()
+    }
+}
+
+#[tonic::async_trait]
+impl EmailService for FakeEmail {
+    async fn send_email(&self, request: Request<Email>) -> Result<Response<EmailResponse>, Status> {
+        self.send_email.handle(request)
+    }
+}
+
+/// In-process stand-in for the files service
+#[derive(Clone)]
+pub struct FakeFiles {
+    pub get_file_id: Arc<FakeMethod<FileName, FileId>>,
+    pub get_file_name: Arc<FakeMethod<FileId, FileName>>,
+    pub purchase_file: Arc<FakeMethod<PurchaseFileDetails, PurchaseFileResponse>>,
+}
+
+impl Default for FakeFiles {
+    fn default() -> Self {
+        Self {
+            get_file_id: FakeMethod::new(FileId {
+                file_id: "test-file-id".to_string(),
+            }),
+            get_file_name: FakeMethod::new(FileName {
+                file_name: "test-file-name".to_string(),
+            }),
+            purchase_file: FakeMethod::new(PurchaseFileResponse { success: true }),
+        }
+    }
+}
+
+impl FakeFiles {
+    /// The files purchased so far as (buyer_id, file_id), oldest first
+    pub fn purchases(&self) -> Vec<(String, String)> {
+        self.purchase_file
+            .calls()
+            .into_iter()
+            .map(|call| (call.message.buyer_id, call.message.file_id))
+            .collect()
+    }
+}
+
+#[tonic::async_trait]
+impl FilesService for FakeFiles {
+    async fn get_file_id(&self, request: Request<FileName>) -> Result<Response<FileId>, Status> {
+        self.get_file_id.handle(request)
+    }
+
+    async fn get_file_name(&self, request: Request<FileId>) -> Result<Response<FileName>, Status> {
+        self.get_file_name.handle(request)
+    }
+
+    async fn purchase_file(
+        &self,
+        request: Request<PurchaseFileDetails>,
+    ) -> Result<Response<PurchaseFileResponse>, Status> {
+        self.purchase_file.handle(request)
+    }
+}
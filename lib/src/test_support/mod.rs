@@ -0,0 +1,235 @@
+//! Test support for running the services offline. The fakes bind free ports and the harness installs
+//! a configuration pointing the services at them, so the code under test runs unchanged against them.
+
+pub mod fakes;
+pub mod recorder;
+
+use std::{
+    future::Future,
+    io::{Error, ErrorKind},
+    net::SocketAddr,
+    sync::Arc,
+};
+
+use hyper::{
+    header::{AUTHORIZATION, COOKIE},
+    HeaderMap,
+};
+use tokio::{
+    net::TcpListener,
+    sync::{oneshot, Mutex, MutexGuard},
+};
+use tonic::transport::{server::TcpIncoming, Server};
+
+use crate::{
+    config::{self, AppConfig},
+    integration::grpc::clients::{
+        acl_service::acl_server::AclServer,
+        email_service::email_service_server::EmailServiceServer,
+        files_service::files_service_server::FilesServiceServer,
+    },
+};
+use fakes::{FakeAcl, FakeEmail, FakeFiles};
+
+/// The configuration is process wide, so only one harness may run at a time in a test binary
+static CONFIG_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// The fake ACL, email and files services, served in-process on free ports. Everything is shut down
+/// when the harness is dropped.
+pub struct FakeServices {
+    pub acl: FakeAcl,
+    pub email: FakeEmail,
+    pub files: FakeFiles,
+    config: Arc<AppConfig>,
+    shutdown_senders: Vec<oneshot::Sender<()>>,
+    _config_guard: MutexGuard<'static, ()>,
+}
+
+impl FakeServices {
+    /// Start the fakes with their default responses and `AppConfig::local`. Waits for any other harness
+    /// in the binary to finish.
+    pub async fn start() -> Result<Self, Error> {
+        Self::start_with_config(AppConfig::local("test")).await
+    }
+
+    /// Start the fakes and install the configuration, its endpoints pointed at them
+    pub async fn start_with_config(config: AppConfig) -> Result<Self, Error> {
+        let config_guard = CONFIG_LOCK.lock().await;
+
+        let mut fake_services = Self {
+            acl: FakeAcl::default(),
+            email: FakeEmail::default(),
+            files: FakeFiles::default(),
+            config: Arc::new(config),
+            shutdown_senders: vec![],
+            _config_guard: config_guard,
+        };
+
+        let acl_incoming = fake_services.serve_endpoint("acl").await?;
+        let email_incoming = fake_services.serve_endpoint("email").await?;
+        let files_incoming = fake_services.serve_endpoint("files").await?;
+
+        tokio::spawn(
+            Server::builder()
+                .add_service(AclServer::new(fake_services.acl.clone()))
+                .serve_with_incoming_shutdown(acl_incoming, fake_services.shutdown_signal()),
+        );
+        tokio::spawn(
+            Server::builder()
+                .add_service(EmailServiceServer::new(fake_services.email.clone()))
+                .serve_with_incoming_shutdown(email_incoming, fake_services.shutdown_signal()),
+        );
+        tokio::spawn(
+            Server::builder()
+                .add_service(FilesServiceServer::new(fake_services.files.clone()))
+                .serve_with_incoming_shutdown(files_incoming, fake_services.shutdown_signal()),
+        );
+
+        Ok(fake_services)
+    }
+
+    /// The installed configuration, e.g. to hand to the handlers under test
+    pub fn config(&self) -> Arc<AppConfig> {
+        self.config.clone()
+    }
+
+    /// Bind a free port for a downstream service by endpoint name, e.g. "orders", and point the
+    /// configuration at it. Serve a service's own gRPC server or another fake on the returned listener.
+    pub async fn serve_endpoint(&mut self, name: &str) -> Result<TcpIncoming, Error> {
+        let listener =
+            TcpListener::bind(SocketAddr::from((std::net::Ipv6Addr::LOCALHOST, 0))).await?;
+        let address = listener.local_addr()?;
+
+        let mut config = (*self.config).clone();
+        let endpoint = match name {
+            "acl" => &mut config.endpoints.acl,
+            "email" => &mut config.endpoints.email,
+            "files" => &mut config.endpoints.files,
+            "products" => &mut config.endpoints.products,
+            "orders" => &mut config.endpoints.orders,
+            "payments" => &mut config.endpoints.payments,
+            "shared" => &mut config.endpoints.shared,
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("Unknown endpoint: {}", name),
+                ))
+            }
+        };
+        *endpoint = format!("http://{}", address);
+
+        self.config = Arc::new(config);
+        config::install(self.config.clone());
+
+        TcpIncoming::from_listener(listener, true, None)
+            .map_err(|e| Error::other(format!("Failed to serve {}: {}", address, e)))
+    }
+
+    /// A signal that resolves when the harness is dropped. Pass it to the `serve_with_incoming_shutdown`
+    /// of a service's own gRPC server so it stops together with the fakes.
+    pub fn shutdown_signal(&mut self) -> impl Future<Output = ()> + Send + 'static {
+        let (sender, receiver) = oneshot::channel();
+        self.shutdown_senders.push(sender);
+
+        async move {
+            let _ = receiver.await;
+        }
+    }
+}
+
+impl Drop for FakeServices {
+    fn drop(&mut self) {
+        for sender in self.shutdown_senders.drain(..) {
+            let _ = sender.send(());
+        }
+    }
+}
+
+/// The headers of a browser request signed in with the token, as the GraphQL resolvers receive them
+pub fn auth_headers(token: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(AUTHORIZATION, format!("Bearer {}", token).parse().unwrap());
+    headers.insert(
+        COOKIE,
+        format!("oauth_client=;t={}", token).parse().unwrap(),
+    );
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        integration::grpc::clients::{
+            acl_service::{acl_client::AclClient, Empty},
+            files_service::{files_service_client::FilesServiceClient, PurchaseFileDetails},
+        },
+        utils::grpc::{create_grpc_client, GrpcChannel},
+    };
+    use tonic::Status;
+
+    #[tokio::test]
+    async fn fakes_record_calls_and_return_scripted_responses() {
+        let fake_services = FakeServices::start().await.unwrap();
+        fake_services.acl.authenticate_as("user-1");
+        fake_services
+            .files
+            .purchase_file
+            .enqueue(Err(Status::not_found("File not found")));
+
+        let mut acl_client = create_grpc_client::<Empty, AclClient<GrpcChannel>>(
+            &fake_services.config().endpoints.acl,
+            false,
+            None,
+        )
+        .await
+        .unwrap();
+        let auth_status = acl_client
+            .check_auth(tonic::Request::new(Empty {}))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(auth_status.sub, "user-1");
+
+        let mut files_client = create_grpc_client::<
+            PurchaseFileDetails,
+            FilesServiceClient<GrpcChannel>,
+        >(&fake_services.config().endpoints.files, false, None)
+        .await
+        .unwrap();
+        let purchase = PurchaseFileDetails {
+            file_id: "file-1".to_string(),
+            buyer_id: "user-1".to_string(),
+        };
+        assert!(files_client.purchase_file(purchase.clone()).await.is_err());
+        assert!(
+            files_client
+                .purchase_file(purchase)
+                .await
+                .unwrap()
+                .into_inner()
+                .success
+        );
+
+        assert_eq!(fake_services.acl.check_auth.call_count(), 1);
+        assert_eq!(
+            fake_services.files.purchases(),
+            vec![
+                ("user-1".to_string(), "file-1".to_string()),
+                ("user-1".to_string(), "file-1".to_string())
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn harnesses_install_their_endpoints() {
+        drop(FakeServices::start().await.unwrap());
+
+        let fake_services = FakeServices::start().await.unwrap();
+        assert_eq!(
+            config::get().endpoints.acl,
+            fake_services.config().endpoints.acl
+        );
+        assert_eq!(fake_services.email.sent().len(), 0);
+    }
+}
@@ -0,0 +1,89 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use tonic::{Request, Response, Status};
+
+use crate::middleware::auth::service::SERVICE_TOKEN_METADATA;
+
+/// A call received by a fake service
+#[derive(Clone, Debug)]
+pub struct RecordedCall<T> {
+    pub message: T,
+    /// The forwarded authorization metadata, if any
+    pub authorization: Option<String>,
+    /// The forwarded cookie metadata, if any
+    pub cookie: Option<String>,
+    /// The calling service's signed identity, if any
+    pub service_token: Option<String>,
+}
+
+/// Records the calls made to a single RPC and answers them with scripted responses. Queued responses
+/// are used first, in order, after which every call gets the default response.
+pub struct FakeMethod<Req, Res> {
+    calls: Mutex<Vec<RecordedCall<Req>>>,
+    queued: Mutex<VecDeque<Result<Res, Status>>>,
+    default: Mutex<Result<Res, Status>>,
+}
+
+impl<Req: Clone, Res: Clone> FakeMethod<Req, Res> {
+    pub fn new(default: Res) -> Arc<Self> {
+        Arc::new(Self {
+            calls: Mutex::new(vec![]),
+            queued: Mutex::new(VecDeque::new()),
+            default: Mutex::new(Ok(default)),
+        })
+    }
+
+    /// Answer every following call with the response
+    pub fn respond_with(&self, response: Res) {
+        *self.default.lock().unwrap() = Ok(response);
+    }
+
+    /// Fail every following call with the status
+    pub fn fail_with(&self, status: Status) {
+        *self.default.lock().unwrap() = Err(status);
+    }
+
+    /// Answer the next call with the result, before falling back to the default response
+    pub fn enqueue(&self, result: Result<Res, Status>) {
+        self.queued.lock().unwrap().push_back(result);
+    }
+
+    pub fn calls(&self) -> Vec<RecordedCall<Req>> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    pub fn call_count(&self) -> usize {
+        self.calls.lock().unwrap().len()
+    }
+
+    /// Record the call and answer it. Lets tests fake other services with the same scripting.
+    // Returns what the tonic service methods return
+    #[allow(clippy::result_large_err)]
+    pub fn handle(&self, request: Request<Req>) -> Result<Response<Res>, Status> {
+        let metadata_value = |key: &str| {
+            request
+                .metadata()
+                .get(key)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string())
+        };
+
+        let call = RecordedCall {
+            authorization: metadata_value("authorization"),
+            cookie: metadata_value("cookie"),
+            service_token: metadata_value(SERVICE_TOKEN_METADATA),
+            message: request.into_inner(),
+        };
+
+        self.calls.lock().unwrap().push(call);
+
+        let queued = self.queued.lock().unwrap().pop_front();
+
+        queued
+            .unwrap_or_else(|| self.default.lock().unwrap().clone())
+            .map(Response::new)
+    }
+}
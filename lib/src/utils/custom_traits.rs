@@ -1,23 +1,30 @@
 use std::sync::Arc;
 
 use axum::Extension;
-use surrealdb::{engine::remote::ws::Client as SurrealClient, Surreal};
+use surrealdb::{engine::any::Any, Surreal};
 
-/// A trait to get the Surreal<Client> for generic functions that use the Surreal Client
+/// A trait to get the Surreal<Any> for generic functions that use the Surreal Client
 pub trait AsSurrealClient {
-    fn as_client(&self) -> &Surreal<SurrealClient>;
+    fn as_client(&self) -> &Surreal<Any>;
 }
 
-// Implement for Arc<Surreal<Client>>
-impl AsSurrealClient for Arc<Surreal<SurrealClient>> {
-    fn as_client(&self) -> &Surreal<SurrealClient> {
+// Implement for Arc<Surreal<Any>>
+impl AsSurrealClient for Arc<Surreal<Any>> {
+    fn as_client(&self) -> &Surreal<Any> {
         self.as_ref()
     }
 }
 
-// Implement for Extension<Arc<Surreal<Client>>>
-impl AsSurrealClient for Extension<Arc<Surreal<SurrealClient>>> {
-    fn as_client(&self) -> &Surreal<SurrealClient> {
+// Implement for Extension<Arc<Surreal<Any>>>
+impl AsSurrealClient for Extension<Arc<Surreal<Any>>> {
+    fn as_client(&self) -> &Surreal<Any> {
         self.0.as_ref()
     }
 }
+
+// Implement for Surreal<Any>
+impl AsSurrealClient for Surreal<Any> {
+    fn as_client(&self) -> &Surreal<Any> {
+        self
+    }
+}
@@ -1,85 +1,388 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, time::Duration};
 
-use gql_client::Client;
-use serde::{Deserialize, Serialize};
+use hyper::{
+    header::{HeaderName, HeaderValue, AUTHORIZATION, COOKIE},
+    HeaderMap,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-#[derive(Debug, Deserialize)]
-pub enum GraphQLResponse<T> {
-    Data(T),
-    Error(String),
+use crate::{
+    error::AppError,
+    logging::{current_request_id, REQUEST_ID_HEADER},
+    telemetry::inject_http_context,
+};
+
+/// A client for a GraphQL API, e.g. another service's. Requests time out, transient failures are
+/// retried with exponential backoff, and the current trace context and request id are forwarded.
+///
+/// ```ignore
+/// let response: SignInResponse = GraphQLClient::new(&endpoint)
+///     .forward_auth(headers)
+///     .query_with_vars(SIGN_IN, UserLoginsVar { raw_user_details })
+///     .await?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct GraphQLClient {
+    http: reqwest::Client,
+    endpoint: String,
+    headers: HeaderMap,
+    timeout: Duration,
+    max_retries: u32,
+    retry_backoff: Duration,
+}
+
+/// What went wrong with a GraphQL request. Converts into an `AppError`, keeping the codes the
+/// caller can act on.
+#[derive(Debug)]
+pub enum GraphQLError {
+    /// The server couldn't be reached, so the request wasn't sent
+    Connect(String),
+    Timeout,
+    /// The request failed after it was sent, e.g. the connection was reset
+    Transport(String),
+    /// A status without a GraphQL response, e.g. a 502 from the proxy
+    Http(u16),
+    /// The `errors` of the response
+    Response(Vec<GraphQLResponseError>),
+    /// No data, or data that doesn't match the expected type
+    InvalidResponse(String),
+}
+
+/// An error of the response's `errors` array
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct GraphQLResponseError {
+    pub message: String,
+    /// The path of the field that failed, e.g. `["getUser", "email"]`
+    #[serde(default)]
+    pub path: Vec<PathSegment>,
+    #[serde(default)]
+    pub extensions: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum PathSegment {
+    Field(String),
+    Index(usize),
+}
+
+#[derive(Serialize)]
+struct GraphQLRequestBody<'a, V> {
+    query: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    variables: Option<V>,
+}
+
+#[derive(Deserialize)]
+struct GraphQLResponseBody {
+    data: Option<serde_json::Value>,
+    #[serde(default)]
+    errors: Vec<GraphQLResponseError>,
+}
+
+impl GraphQLClient {
+    pub fn new(endpoint: &str) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            endpoint: endpoint.to_string(),
+            headers: HeaderMap::new(),
+            timeout: Duration::from_secs(10),
+            max_retries: 2,
+            retry_backoff: Duration::from_millis(200),
+        }
+    }
+
+    /// The timeout of each attempt, 10 seconds by default
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Retry up to `max_retries` times, waiting `backoff` then twice as long every time. 2 retries
+    /// from 200ms by default.
+    pub fn retries(mut self, max_retries: u32, backoff: Duration) -> Self {
+        self.max_retries = max_retries;
+        self.retry_backoff = backoff;
+        self
+    }
+
+    /// Send the caller's `Authorization` and `Cookie` headers, to act as the user
+    pub fn forward_auth(mut self, headers: &HeaderMap) -> Self {
+        for name in [AUTHORIZATION, COOKIE] {
+            if let Some(value) = headers.get(&name) {
+                self.headers.insert(name, value.clone());
+            }
+        }
+        self
+    }
+
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    pub async fn query<R: DeserializeOwned>(&self, query: &str) -> Result<R, GraphQLError> {
+        self.send::<R, ()>(query, None).await
+    }
+
+    pub async fn query_with_vars<R: DeserializeOwned, V: Serialize>(
+        &self,
+        query: &str,
+        variables: V,
+    ) -> Result<R, GraphQLError> {
+        self.send(query, Some(variables)).await
+    }
+
+    async fn send<R: DeserializeOwned, V: Serialize>(
+        &self,
+        query: &str,
+        variables: Option<V>,
+    ) -> Result<R, GraphQLError> {
+        let body = GraphQLRequestBody { query, variables };
+        let is_mutation = query.trim_start().starts_with("mutation");
+        let mut attempt = 0;
+
+        loop {
+            match self.send_once(&body).await {
+                Err(error) if attempt < self.max_retries && error.is_retryable(is_mutation) => {
+                    let backoff = self.retry_backoff * 2u32.pow(attempt);
+                    attempt += 1;
+                    tracing::warn!(
+                        "GraphQL request to {} failed, retrying in {:?}: {:?}",
+                        self.endpoint,
+                        backoff,
+                        error
+                    );
+                    tokio::time::sleep(backoff).await;
+                }
+                result => {
+                    return result.and_then(|data| {
+                        serde_json::from_value(data)
+                            .map_err(|e| GraphQLError::InvalidResponse(e.to_string()))
+                    })
+                }
+            }
+        }
+    }
+
+    async fn send_once<V: Serialize>(
+        &self,
+        body: &GraphQLRequestBody<'_, V>,
+    ) -> Result<serde_json::Value, GraphQLError> {
+        let mut headers = self.headers.clone();
+        inject_http_context(&mut headers);
+        if let Some(request_id) = current_request_id().and_then(|id| id.parse().ok()) {
+            headers.insert(REQUEST_ID_HEADER, request_id);
+        }
+
+        let response = self
+            .http
+            .post(&self.endpoint)
+            .headers(headers)
+            .timeout(self.timeout)
+            .json(body)
+            .send()
+            .await
+            .map_err(GraphQLError::from)?;
+
+        let status = response.status();
+        let text = response.text().await.map_err(GraphQLError::from)?;
+
+        // Errors can come with any status, e.g. a 429 from the rate limiter
+        match serde_json::from_str::<GraphQLResponseBody>(&text) {
+            Ok(response) if !response.errors.is_empty() => {
+                Err(GraphQLError::Response(response.errors))
+            }
+            _ if !status.is_success() => Err(GraphQLError::Http(status.as_u16())),
+            Ok(GraphQLResponseBody {
+                data: Some(data), ..
+            }) if !data.is_null() => Ok(data),
+            Ok(_) => Err(GraphQLError::InvalidResponse(
+                "The response has no data".into(),
+            )),
+            Err(e) => Err(GraphQLError::InvalidResponse(e.to_string())),
+        }
+    }
 }
 
-impl<T> GraphQLResponse<T> {
-    pub fn get_data(&self) -> Option<&T> {
+impl GraphQLError {
+    /// Mutations are only retried when they weren't sent, they could run twice otherwise
+    fn is_retryable(&self, is_mutation: bool) -> bool {
         match self {
-            GraphQLResponse::Data(data) => Some(data),
-            _ => None,
+            GraphQLError::Connect(_) => true,
+            GraphQLError::Timeout | GraphQLError::Transport(_) => !is_mutation,
+            GraphQLError::Http(status) => !is_mutation && matches!(status, 502..=504),
+            GraphQLError::Response(_) | GraphQLError::InvalidResponse(_) => false,
         }
     }
 }
 
-pub async fn perform_query_without_vars<R: for<'de> Deserialize<'de>>(
-    headers: Option<HashMap<String, String>>,
-    endpoint: &str,
-    query: &str,
-) -> GraphQLResponse<R> {
-    // let endpoint = "http://localhost:3001";
-
-    // create query
-    //     let query = r#"
-    //        query Query {
-    //            getUsers {
-    //                id
-    //                email
-    //                fullName
-    //                age
-    //            }
-    //        }
-    //    "#;
-
-    let client = match headers {
-        Some(headers) => Client::new_with_headers(endpoint, headers),
-        None => Client::new(endpoint)
-    };
+impl From<reqwest::Error> for GraphQLError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            GraphQLError::Timeout
+        } else if e.is_connect() {
+            GraphQLError::Connect(e.to_string())
+        } else {
+            GraphQLError::Transport(e.to_string())
+        }
+    }
+}
 
-    let response = client.query::<R>(query).await;
+impl GraphQLResponseError {
+    /// The error as an `AppError` if its `code` (`AppError`) or `status` (`ExtendedError`)
+    /// extension is one the caller can act on
+    fn as_app_error(&self) -> Option<AppError> {
+        let extension = |name: &str| self.extensions.get(name).and_then(|value| value.as_str());
+        let message = self.message.clone();
 
-    match response {
-        Ok(data) => GraphQLResponse::Data(data.unwrap()),
-        Err(err) => GraphQLResponse::Error(format!("{:?}", err)),
+        match (extension("code"), extension("status")) {
+            (Some("NOT_FOUND"), _) | (_, Some("404")) => Some(AppError::NotFound(message)),
+            (Some("UNAUTHORIZED"), _) | (_, Some("401")) => Some(AppError::Unauthorized(message)),
+            (Some("FORBIDDEN"), _) | (_, Some("403")) => Some(AppError::Forbidden(message)),
+            (Some("VALIDATION_FAILED"), _) | (_, Some("400")) => {
+                Some(AppError::Validation(message))
+            }
+            (Some("CONFLICT"), _) | (_, Some("409")) => Some(AppError::Conflict(message)),
+            (Some("RATE_LIMITED"), _) | (_, Some("429")) => Some(AppError::RateLimited(message)),
+            _ => None,
+        }
     }
 }
 
+/// A failed call to another service. Like `From<Status>`, the codes the caller can act on are kept.
+impl From<GraphQLError> for AppError {
+    fn from(error: GraphQLError) -> Self {
+        let actionable = match &error {
+            GraphQLError::Response(errors) => errors.first().and_then(|e| e.as_app_error()),
+            GraphQLError::Timeout => Some(AppError::Timeout("Upstream request timed out".into())),
+            _ => None,
+        };
+
+        actionable.unwrap_or_else(|| {
+            tracing::error!("GraphQL request failed: {:?}", error);
+            AppError::Upstream("Upstream service failed".into())
+        })
+    }
+}
 
-pub async fn perform_mutation_or_query_with_vars<R: for<'de> Deserialize<'de> + Serialize, T: for<'de> Deserialize<'de> + Serialize>(
-    headers: Option<HashMap<String, String>>,
-    endpoint: &str,
-    query: &str,
-    vars: T,
-) -> GraphQLResponse<R> {
-    // let endpoint = "http://localhost:3001";
-
-    // create query
-    // let query = r#"
-    //     mutation Mutation($user: UserInput!) {
-    //         signUp(user: $user) {
-    //             id
-    //             email
-    //             fullName
-    //             age
-    //         }
-    //     }
-    // "#;
-
-    let client = match headers {
-        Some(headers) => Client::new_with_headers(endpoint, headers),
-        None => Client::new(endpoint)
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
     };
 
-    let response = client.query_with_vars::<R, T>(query, vars).await;
+    use super::*;
+    use crate::utils::models::{GetUserResponse, GetUserVar};
+    use axum::{extract::State, http::StatusCode, routing::post, Json, Router};
+
+    /// A server failing with a 503 first, then answering `getUserEmail` for authenticated callers
+    async fn serve_flaky_api() -> (String, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let app = Router::new()
+            .route(
+                "/",
+                post(
+                    |State(calls): State<Arc<AtomicUsize>>,
+                     headers: HeaderMap,
+                     Json(body): Json<serde_json::Value>| async move {
+                        if calls.fetch_add(1, Ordering::SeqCst) == 0 {
+                            return (StatusCode::SERVICE_UNAVAILABLE, Json(serde_json::json!({})));
+                        }
+
+                        let response = if headers.get(AUTHORIZATION).is_none() {
+                            serde_json::json!({
+                                "data": null,
+                                "errors": [{
+                                    "message": "Unauthorized",
+                                    "path": ["getUserEmail"],
+                                    "extensions": { "code": "UNAUTHORIZED", "status": "401" }
+                                }]
+                            })
+                        } else {
+                            serde_json::json!({
+                                "data": { "getUserEmail": format!("{}@example.com", body["variables"]["id"].as_str().unwrap()) }
+                            })
+                        };
+
+                        (StatusCode::OK, Json(response))
+                    },
+                ),
+            )
+            .with_state(calls.clone());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        (format!("http://{}", address), calls)
+    }
+
+    const GET_USER_EMAIL: &str = "query GetUserEmail($id: String!) { getUserEmail(id: $id) }";
+
+    #[tokio::test]
+    async fn retries_transient_failures_and_forwards_auth() {
+        let (endpoint, calls) = serve_flaky_api().await;
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer token"));
+
+        let response: GetUserResponse = GraphQLClient::new(&endpoint)
+            .retries(2, Duration::from_millis(1))
+            .forward_auth(&headers)
+            .query_with_vars(
+                GET_USER_EMAIL,
+                GetUserVar {
+                    id: "user-1".into(),
+                },
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.get_user_email, "user-1@example.com");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn parses_the_errors_of_the_response() {
+        let (endpoint, _calls) = serve_flaky_api().await;
+
+        let error = GraphQLClient::new(&endpoint)
+            .retries(2, Duration::from_millis(1))
+            .query_with_vars::<GetUserResponse, _>(
+                GET_USER_EMAIL,
+                GetUserVar {
+                    id: "user-1".into(),
+                },
+            )
+            .await
+            .unwrap_err();
+
+        let GraphQLError::Response(errors) = &error else {
+            panic!("Unexpected error: {:?}", error);
+        };
+        assert_eq!(
+            errors[0].path,
+            vec![PathSegment::Field("getUserEmail".into())]
+        );
+        assert_eq!(
+            AppError::from(error),
+            AppError::Unauthorized("Unauthorized".into())
+        );
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_sent_mutations() {
+        let (endpoint, calls) = serve_flaky_api().await;
+
+        let error = GraphQLClient::new(&endpoint)
+            .retries(2, Duration::from_millis(1))
+            .query::<serde_json::Value>("mutation { signOut }")
+            .await
+            .unwrap_err();
 
-    match response {
-        Ok(data) => GraphQLResponse::Data(data.unwrap()),
-        Err(err) => GraphQLResponse::Error(format!("{:?}", err)),
+        assert!(matches!(error, GraphQLError::Http(503)));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
     }
 }
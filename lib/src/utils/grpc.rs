@@ -2,10 +2,14 @@ use async_trait::async_trait;
 use hyper::header::HeaderValue;
 use std::io::{Error as StdError, ErrorKind};
 use tonic::{
-    metadata::MetadataValue,
-    transport::{Channel, Endpoint, Error},
+    metadata::{errors::InvalidMetadataValue, MetadataValue},
+    service::interceptor::InterceptedService,
+    transport::{
+        Certificate, Channel, ClientTlsConfig, Endpoint, Error, Identity, Server, ServerTlsConfig,
+    },
     Request,
 };
+use tower::Layer;
 
 use crate::integration::grpc::clients::{
     acl_service::acl_client::AclClient, email_service::email_service_client::EmailServiceClient,
@@ -13,6 +17,14 @@ use crate::integration::grpc::clients::{
     orders_service::orders_service_client::OrdersServiceClient,
     payments_service::payments_service_client::PaymentsServiceClient,
     products_service::products_service_client::ProductsServiceClient,
+    shared_service::shared_service_client::SharedServiceClient,
+};
+use crate::{
+    config::{self, AppConfig},
+    logging::current_request_id,
+    middleware::auth::service::ServiceIdentity,
+    telemetry::inject_context,
+    utils::resilience::{ResilienceLayer, Resilient},
 };
 
 // Define the trait for gRPC clients
@@ -21,130 +33,172 @@ pub trait GrpcClient: Sized {
     async fn connect<'a>(endpoint: &'a str) -> Result<Self, Error>;
 }
 
+/// The channel of every client: the service identity, then deadlines, retries and a circuit breaker
+/// per downstream service (see `utils::resilience`) over a lazily connected tonic channel
+pub type GrpcChannel = Resilient<InterceptedService<Channel, ServiceIdentity>>;
+
+/// Calls to a service whose circuit breaker is open fail fast, so the channel connects on the
+/// first call instead of here
+fn resilient_channel(service: &'static str, endpoint: &str) -> Result<GrpcChannel, Error> {
+    let config = config::get();
+    let mut endpoint = Endpoint::from_shared(endpoint.to_string())?
+        .connect_timeout(config.grpc_client.connect_timeout);
+
+    if let Some(tls) = &config.service_auth.tls {
+        let tls_config = ClientTlsConfig::new()
+            .identity(Identity::from_pem(&tls.cert, &tls.key))
+            .ca_certificate(Certificate::from_pem(&tls.ca));
+        endpoint = endpoint.tls_config(tls_config)?;
+    }
+
+    let identity = ServiceIdentity::new(&config.service, config.service_auth.token_secret.clone());
+    let channel = InterceptedService::new(endpoint.connect_lazy(), identity);
+
+    Ok(ResilienceLayer::new(service, &config.grpc_client).layer(channel))
+}
+
+/// A gRPC server builder with the service's mutual TLS configuration, if any: only the clients
+/// presenting a certificate signed by the CA can connect
+pub fn grpc_server(config: &AppConfig) -> Result<Server, Error> {
+    let server = Server::builder();
+
+    match &config.service_auth.tls {
+        Some(tls) => {
+            let tls_config = ServerTlsConfig::new()
+                .identity(Identity::from_pem(&tls.cert, &tls.key))
+                .client_ca_root(Certificate::from_pem(&tls.ca));
+            server.tls_config(tls_config)
+        }
+        None => Ok(server),
+    }
+}
+
 pub struct AuthMetaData<'a, T> {
     pub auth_header: Option<&'a HeaderValue>,
     pub cookie_header: Option<&'a HeaderValue>,
     pub constructed_grpc_request: Option<&'a mut Request<T>>,
 }
 
-// Implement the trait for AclClient<Channel>
+// Implement the trait for AclClient<GrpcChannel>
 #[async_trait]
-impl GrpcClient for AclClient<Channel> {
+impl GrpcClient for AclClient<GrpcChannel> {
     async fn connect<'a>(endpoint: &'a str) -> Result<Self, Error> {
-        let channel = Endpoint::from_shared(endpoint.to_string())?
-            .connect()
-            .await?;
+        let channel = resilient_channel("acl", endpoint)?;
         Ok(AclClient::new(channel))
     }
 }
 
-// Implement the trait for EmailServiceClient<Channel>
+// Implement the trait for EmailServiceClient<GrpcChannel>
 #[async_trait]
-impl GrpcClient for EmailServiceClient<Channel> {
+impl GrpcClient for EmailServiceClient<GrpcChannel> {
     async fn connect<'a>(endpoint: &'a str) -> Result<Self, Error> {
-        let channel = Endpoint::from_shared(endpoint.to_string())?
-            .connect()
-            .await?;
+        let channel = resilient_channel("email", endpoint)?;
         Ok(EmailServiceClient::new(channel))
     }
 }
 
-// Implement the trait for FilesServiceClient<Channel>
+// Implement the trait for FilesServiceClient<GrpcChannel>
 #[async_trait]
-impl GrpcClient for FilesServiceClient<Channel> {
+impl GrpcClient for FilesServiceClient<GrpcChannel> {
     async fn connect<'a>(endpoint: &'a str) -> Result<Self, Error> {
-        let channel = Endpoint::from_shared(endpoint.to_string())?
-            .connect()
-            .await?;
+        let channel = resilient_channel("files", endpoint)?;
         Ok(FilesServiceClient::new(channel))
     }
 }
 
-// Implement the trait for PaymentsServiceClient<Channel>
+// Implement the trait for PaymentsServiceClient<GrpcChannel>
 #[async_trait]
-impl GrpcClient for PaymentsServiceClient<Channel> {
+impl GrpcClient for PaymentsServiceClient<GrpcChannel> {
     async fn connect<'a>(endpoint: &'a str) -> Result<Self, Error> {
-        let channel = Endpoint::from_shared(endpoint.to_string())?
-            .connect()
-            .await?;
+        let channel = resilient_channel("payments", endpoint)?;
         Ok(PaymentsServiceClient::new(channel))
     }
 }
 
-// Implement the trait for OrdersServiceClient<Channel>
+// Implement the trait for OrdersServiceClient<GrpcChannel>
 #[async_trait]
-impl GrpcClient for OrdersServiceClient<Channel> {
+impl GrpcClient for OrdersServiceClient<GrpcChannel> {
     async fn connect<'a>(endpoint: &'a str) -> Result<Self, Error> {
-        let channel = Endpoint::from_shared(endpoint.to_string())?
-            .connect()
-            .await?;
+        let channel = resilient_channel("orders", endpoint)?;
         Ok(OrdersServiceClient::new(channel))
     }
 }
 
-// Implement the trait for ProductsServiceClient<Channel>
+// Implement the trait for ProductsServiceClient<GrpcChannel>
 #[async_trait]
-impl GrpcClient for ProductsServiceClient<Channel> {
+impl GrpcClient for ProductsServiceClient<GrpcChannel> {
     async fn connect<'a>(endpoint: &'a str) -> Result<Self, Error> {
-        let channel = Endpoint::from_shared(endpoint.to_string())?
-            .connect()
-            .await?;
+        let channel = resilient_channel("products", endpoint)?;
         Ok(ProductsServiceClient::new(channel))
     }
 }
 
-// Generic function to create gRPC clients
-pub async fn create_grpc_client<'a, R, T: GrpcClient>(
+// Implement the trait for SharedServiceClient<GrpcChannel>
+#[async_trait]
+impl GrpcClient for SharedServiceClient<GrpcChannel> {
+    async fn connect<'a>(endpoint: &'a str) -> Result<Self, Error> {
+        let channel = resilient_channel("shared", endpoint)?;
+        Ok(SharedServiceClient::new(channel))
+    }
+}
+
+// Generic function to create gRPC clients. The current trace context and request id are added to the constructed request.
+pub async fn create_grpc_client<R, T: GrpcClient>(
     endpoint: &str,
     is_authenticated: bool,
-    auth_metadata: Option<AuthMetaData<'_, R>>,
+    mut auth_metadata: Option<AuthMetaData<'_, R>>,
 ) -> Result<T, StdError> {
-    if is_authenticated {
-        add_auth_headers_to_request::<R>(auth_metadata.unwrap()).await?;
+    if let Some(request) = auth_metadata
+        .as_mut()
+        .and_then(|auth_metadata| auth_metadata.constructed_grpc_request.as_mut())
+    {
+        inject_context(request.metadata_mut());
+
+        if let Some(request_id) = current_request_id().and_then(|id| id.parse().ok()) {
+            request.metadata_mut().insert("x-request-id", request_id);
+        }
+    }
+
+    if let Some(auth_metadata) = auth_metadata.filter(|_| is_authenticated) {
+        add_auth_headers_to_request::<R>(auth_metadata).await?;
     }
     T::connect(endpoint)
         .await
-        .map_err(|_e| StdError::new(ErrorKind::InvalidData, "Invalid header"))
+        .map_err(|e| StdError::new(ErrorKind::NotConnected, e.to_string()))
 }
 
+/// Forwards the user's authorization and cookie headers, the ones the client didn't send are skipped.
+/// A header that isn't valid metadata is an `InvalidData` error.
 async fn add_auth_headers_to_request<R>(
-    mut auth_metadata: AuthMetaData<'_, R>,
+    auth_metadata: AuthMetaData<'_, R>,
 ) -> Result<(), StdError> {
-    let token: MetadataValue<_> = auth_metadata
-        .auth_header
-        .unwrap()
-        .to_str()
-        .unwrap()
-        .parse()
-        .map_err(|e| {
-            tracing::error!("Failed to parse auth header: {}", e);
-            StdError::new(ErrorKind::InvalidData, "Invalid header")
-        })?;
-
-    auth_metadata
-        .constructed_grpc_request
-        .as_mut()
-        .unwrap()
-        .metadata_mut()
-        .insert("authorization", token);
-
-    let cookie: MetadataValue<_> = auth_metadata
-        .cookie_header
-        .unwrap()
-        .to_str()
-        .unwrap()
-        .parse()
-        .map_err(|e| {
-            tracing::error!("Failed to parse cookie header: {}", e);
-            StdError::new(ErrorKind::InvalidData, "Invalid header")
-        })?;
-
-    auth_metadata
-        .constructed_grpc_request
-        .as_mut()
-        .unwrap()
-        .metadata_mut()
-        .insert("cookie", cookie);
+    let Some(request) = auth_metadata.constructed_grpc_request else {
+        return Ok(());
+    };
+
+    for (key, header) in [
+        ("authorization", auth_metadata.auth_header),
+        ("cookie", auth_metadata.cookie_header),
+    ] {
+        let Some(header) = header else {
+            continue;
+        };
+
+        let value: MetadataValue<_> = header
+            .to_str()
+            .map_err(|e| e.to_string())
+            .and_then(|value| {
+                value
+                    .parse()
+                    .map_err(|e: InvalidMetadataValue| e.to_string())
+            })
+            .map_err(|e| {
+                tracing::error!("Failed to parse {} header: {}", key, e);
+                StdError::new(ErrorKind::InvalidData, "Invalid header")
+            })?;
+
+        request.metadata_mut().insert(key, value);
+    }
 
     Ok(())
 }
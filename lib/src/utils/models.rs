@@ -23,6 +23,21 @@ pub struct Product {
     pub product_id: String,
 }
 
+/// Federation reference to a product of the products service. The gateway resolves the rest of its
+/// fields from there.
+#[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
+#[graphql(name = "Product", unresolvable)]
+pub struct ProductReference {
+    pub id: String,
+}
+
+/// Federation reference to a user of the ACL service, keyed by the user ID in the auth token
+#[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
+#[graphql(name = "User", unresolvable)]
+pub struct UserReference {
+    pub id: String,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
 pub struct UploadedFile {
     #[graphql(skip)]
@@ -73,7 +88,9 @@ pub struct UserPaymentDetails {
     pub amount: u64,
     // pub currency: Option<String>,
     pub reference: String,
-    // pub metadata: Option<PaymentDetailsMetaData>,
+    /// Sent back by Paystack with the charge's webhook
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<PaymentDetailsMetaData>,
 }
 
 #[derive(Debug, Deserialize, Serialize, SimpleObject)]
@@ -117,9 +134,12 @@ pub struct InitializePaymentGraphQLResponseData {
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, SimpleObject, InputObject)]
+#[graphql(input_name = "PaymentDetailsMetaDataInput")]
 pub struct PaymentDetailsMetaData {
     #[serde(rename = "cartId")]
     pub cart_id: Option<String>,
+    /// The buyer's language tag, e.g. "fr-FR", for the emails sent once the charge succeeds
+    pub locale: Option<String>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, Enum, Copy, Eq, PartialEq)]
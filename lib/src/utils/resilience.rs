@@ -0,0 +1,512 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, LazyLock, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use http_body_util::{BodyExt, Full};
+use rand::Rng;
+use tonic::{
+    body::{boxed, BoxBody},
+    codegen::{http, BoxFuture, StdError},
+    Code, Status,
+};
+use tower::{Layer, Service, ServiceExt};
+
+use crate::config::GrpcClientConfig;
+
+/// The read-only RPCs, sending them again after a failed attempt is harmless
+const IDEMPOTENT_METHODS: &[&str] = &[
+    "/acl.Acl/CheckAuth",
+    "/acl.Acl/GetUserEmail",
+    "/files.FilesService/GetFileId",
+    "/files.FilesService/GetFileName",
+    "/orders.OrdersService/GetAllArtifactsForOrder",
+    "/products.ProductsService/GetProductPrice",
+    "/products.ProductsService/GetProductArtifact",
+    "/products.ProductsService/GetLicensePriceFactor",
+    "/products.ProductsService/GetProductOwner",
+    "/shared.SharedService/HasAcceptedLatestTerms",
+];
+
+/// The error of a call that was not sent because the circuit breaker of the downstream service is
+/// open. It's the source of the `Unavailable` status the client returns, which converts into
+/// `AppError::Unavailable`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CircuitOpen {
+    pub service: &'static str,
+}
+
+impl fmt::Display for CircuitOpen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The {} service is unavailable", self.service)
+    }
+}
+
+impl std::error::Error for CircuitOpen {}
+
+impl CircuitOpen {
+    fn into_status(self) -> Status {
+        let mut status = Status::unavailable(self.to_string());
+        status.set_source(Arc::new(self));
+        status
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BreakerState {
+    Closed {
+        failures: u32,
+    },
+    /// Failing fast until `until`, then the next call is let through as a probe
+    Open {
+        until: Instant,
+    },
+    /// A probe is in flight, the other calls keep failing fast
+    HalfOpen,
+}
+
+/// Counts the consecutive failures of the calls to one downstream service. Once there are too many
+/// the calls fail fast for a while, then a single probe decides whether the service is back.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    service: &'static str,
+    failure_threshold: u32,
+    open_duration: Duration,
+    state: Mutex<BreakerState>,
+}
+
+/// Lets one call through. Dropping it without calling `succeeded` counts as a failure, so attempts
+/// that time out or are cancelled are failures too.
+struct Permit {
+    breaker: Arc<CircuitBreaker>,
+    succeeded: bool,
+}
+
+impl Permit {
+    fn succeeded(mut self) {
+        self.succeeded = true;
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.breaker.record_at(self.succeeded, Instant::now());
+    }
+}
+
+impl CircuitBreaker {
+    pub fn new(service: &'static str, failure_threshold: u32, open_duration: Duration) -> Self {
+        Self {
+            service,
+            failure_threshold,
+            open_duration,
+            state: Mutex::new(BreakerState::Closed { failures: 0 }),
+        }
+    }
+
+    fn acquire(self: &Arc<Self>) -> Result<Permit, CircuitOpen> {
+        self.acquire_at(Instant::now())
+    }
+
+    fn acquire_at(self: &Arc<Self>, now: Instant) -> Result<Permit, CircuitOpen> {
+        let mut state = self.state.lock().unwrap();
+
+        match *state {
+            BreakerState::Closed { .. } => {}
+            BreakerState::Open { until } if now >= until => {
+                tracing::info!("Probing the {} service", self.service);
+                *state = BreakerState::HalfOpen;
+            }
+            BreakerState::Open { .. } | BreakerState::HalfOpen => {
+                return Err(CircuitOpen {
+                    service: self.service,
+                })
+            }
+        }
+
+        Ok(Permit {
+            breaker: self.clone(),
+            succeeded: false,
+        })
+    }
+
+    fn record_at(&self, succeeded: bool, now: Instant) {
+        let mut state = self.state.lock().unwrap();
+
+        *state = match (*state, succeeded) {
+            (BreakerState::Closed { failures: 0 }, true) => return,
+            (BreakerState::HalfOpen, true) => {
+                tracing::info!("The {} service recovered", self.service);
+                BreakerState::Closed { failures: 0 }
+            }
+            (_, true) => BreakerState::Closed { failures: 0 },
+            (BreakerState::Closed { failures }, false) if failures + 1 < self.failure_threshold => {
+                BreakerState::Closed {
+                    failures: failures + 1,
+                }
+            }
+            (BreakerState::Open { until }, false) => BreakerState::Open { until },
+            (_, false) => {
+                tracing::warn!(
+                    "The {} service is failing, failing its calls fast for {:?}",
+                    self.service,
+                    self.open_duration
+                );
+                BreakerState::Open {
+                    until: now + self.open_duration,
+                }
+            }
+        };
+    }
+}
+
+/// One circuit breaker per downstream service, shared by all its clients
+static BREAKERS: LazyLock<Mutex<HashMap<&'static str, Arc<CircuitBreaker>>>> =
+    LazyLock::new(Default::default);
+
+fn circuit_breaker(service: &'static str, config: &GrpcClientConfig) -> Arc<CircuitBreaker> {
+    BREAKERS
+        .lock()
+        .unwrap()
+        .entry(service)
+        .or_insert_with(|| {
+            Arc::new(CircuitBreaker::new(
+                service,
+                config.breaker_failures,
+                config.breaker_open,
+            ))
+        })
+        .clone()
+}
+
+/// Adds the resilience policy of `GrpcClientConfig` to a channel to a downstream service: a deadline
+/// per call, jittered retries of the idempotent calls that failed with a transient error and the
+/// circuit breaker of the service.
+#[derive(Clone)]
+pub struct ResilienceLayer {
+    breaker: Arc<CircuitBreaker>,
+    config: GrpcClientConfig,
+}
+
+impl ResilienceLayer {
+    /// `service` names the downstream service, e.g. "products", its breaker is shared
+    pub fn new(service: &'static str, config: &GrpcClientConfig) -> Self {
+        Self {
+            breaker: circuit_breaker(service, config),
+            config: config.clone(),
+        }
+    }
+}
+
+impl<S> Layer<S> for ResilienceLayer {
+    type Service = Resilient<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Resilient {
+            inner,
+            breaker: self.breaker.clone(),
+            config: self.config.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Resilient<S> {
+    inner: S,
+    breaker: Arc<CircuitBreaker>,
+    config: GrpcClientConfig,
+}
+
+/// The outcome of an attempt. It failed when the service could not be reached or answered
+/// `Unavailable`, the transient failures worth a retry.
+enum Attempt {
+    Succeeded(http::Response<BoxBody>),
+    Failed(Result<http::Response<BoxBody>, StdError>),
+}
+
+impl<S> Resilient<S>
+where
+    S: Service<http::Request<BoxBody>, Response = http::Response<BoxBody>> + Clone + Send,
+    S::Error: Into<StdError>,
+    S::Future: Send,
+{
+    async fn attempt(&mut self, request: http::Request<BoxBody>) -> Result<Attempt, StdError> {
+        let permit = self.breaker.acquire().map_err(CircuitOpen::into_status)?;
+
+        let response = match self.inner.ready().await.map_err(Into::into) {
+            Ok(inner) => inner.call(request).await.map_err(Into::into),
+            Err(e) => Err(e),
+        };
+
+        // Trailers-only responses, such as the errors, have their status in the headers
+        let is_unavailable = response.as_ref().is_ok_and(|response| {
+            response
+                .headers()
+                .get("grpc-status")
+                .is_some_and(|status| Code::from_bytes(status.as_bytes()) == Code::Unavailable)
+        });
+
+        match response {
+            Ok(response) if !is_unavailable => {
+                permit.succeeded();
+                Ok(Attempt::Succeeded(response))
+            }
+            response => Ok(Attempt::Failed(response)),
+        }
+    }
+
+    async fn call_with_retries(
+        mut self,
+        request: http::Request<BoxBody>,
+    ) -> Result<http::Response<BoxBody>, StdError> {
+        let max_retries = if IDEMPOTENT_METHODS.contains(&request.uri().path()) {
+            self.config.max_retries
+        } else {
+            0
+        };
+
+        if max_retries == 0 {
+            return match self.attempt(request).await? {
+                Attempt::Succeeded(response) => Ok(response),
+                Attempt::Failed(response) => response,
+            };
+        }
+
+        // The idempotent calls are unary with small messages, buffered to be sent again
+        let (parts, body) = request.into_parts();
+        let body = body.collect().await?.to_bytes();
+
+        let mut backoff = self.config.retry_backoff;
+        let mut retries = 0;
+        loop {
+            let request = http::Request::from_parts(parts.clone(), boxed(Full::new(body.clone())));
+
+            match self.attempt(request).await? {
+                Attempt::Succeeded(response) => return Ok(response),
+                Attempt::Failed(response) if retries == max_retries => return response,
+                Attempt::Failed(_) => {
+                    retries += 1;
+                    tracing::warn!(
+                        "{} failed, retry {} of {}",
+                        parts.uri.path(),
+                        retries,
+                        max_retries
+                    );
+
+                    let jitter = rand::thread_rng().gen_range(0.5..=1.0);
+                    tokio::time::sleep(backoff.mul_f64(jitter)).await;
+                    backoff *= 2;
+                }
+            }
+        }
+    }
+}
+
+impl<S> Service<http::Request<BoxBody>> for Resilient<S>
+where
+    S: Service<http::Request<BoxBody>, Response = http::Response<BoxBody>> + Clone + Send + 'static,
+    S::Error: Into<StdError>,
+    S::Future: Send,
+{
+    type Response = http::Response<BoxBody>;
+    type Error = StdError;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    // The inner service is readied for every attempt, the generated clients would turn a readiness
+    // error into an `Unknown` status
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, mut request: http::Request<BoxBody>) -> Self::Future {
+        let timeout = self.config.timeout;
+        let service = self.breaker.service;
+        let this = self.clone();
+
+        // Tell the server how long the client waits, so it can give up too
+        if let Ok(grpc_timeout) = format!("{}m", timeout.as_millis()).parse() {
+            request
+                .headers_mut()
+                .entry("grpc-timeout")
+                .or_insert(grpc_timeout);
+        }
+
+        Box::pin(async move {
+            match tokio::time::timeout(timeout, this.call_with_retries(request)).await {
+                Ok(response) => response,
+                Err(_) => {
+                    tracing::warn!(
+                        "The {} service did not answer within {:?}",
+                        service,
+                        timeout
+                    );
+                    Err(Box::new(Status::deadline_exceeded(format!(
+                        "The {} service did not answer in time",
+                        service
+                    ))) as StdError)
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::AppError;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use tonic::body::empty_body;
+
+    fn config() -> GrpcClientConfig {
+        GrpcClientConfig {
+            timeout: Duration::from_millis(200),
+            retry_backoff: Duration::from_millis(1),
+            breaker_failures: 3,
+            ..Default::default()
+        }
+    }
+
+    fn request(path: &str) -> http::Request<BoxBody> {
+        http::Request::post(format!("http://widgets{}", path))
+            .body(boxed(Full::new("message".into())))
+            .unwrap()
+    }
+
+    /// Answers `Unavailable` to the first `failures` calls, then OK
+    fn flaky_service(
+        failures: u32,
+        calls: Arc<AtomicU32>,
+    ) -> Resilient<
+        impl Service<
+                http::Request<BoxBody>,
+                Response = http::Response<BoxBody>,
+                Error = StdError,
+                Future = impl Send,
+            > + Clone
+            + Send
+            + 'static,
+    > {
+        let inner = tower::service_fn(move |request: http::Request<BoxBody>| {
+            let calls = calls.clone();
+            async move {
+                let body = request.into_body().collect().await.unwrap().to_bytes();
+                assert_eq!(body, "message");
+
+                let status = if calls.fetch_add(1, Ordering::SeqCst) < failures {
+                    Code::Unavailable
+                } else {
+                    Code::Ok
+                };
+                Ok::<_, StdError>(
+                    http::Response::builder()
+                        .header("grpc-status", (status as i32).to_string())
+                        .body(empty_body())
+                        .unwrap(),
+                )
+            }
+        });
+
+        Resilient {
+            inner,
+            breaker: Arc::new(CircuitBreaker::new("products", 3, Duration::from_secs(30))),
+            config: config(),
+        }
+    }
+
+    fn grpc_status(response: &http::Response<BoxBody>) -> &str {
+        response.headers()["grpc-status"].to_str().unwrap()
+    }
+
+    #[test]
+    fn opens_after_consecutive_failures_and_probes_once() {
+        let breaker = Arc::new(CircuitBreaker::new("widgets", 2, Duration::from_secs(30)));
+        let now = Instant::now();
+
+        breaker.acquire_at(now).unwrap();
+        breaker.acquire_at(now).unwrap().succeeded();
+        breaker.acquire_at(now).unwrap();
+        assert!(
+            breaker.acquire_at(now).is_ok(),
+            "a success resets the count"
+        );
+        assert_eq!(
+            breaker.acquire_at(now).err(),
+            Some(CircuitOpen { service: "widgets" })
+        );
+
+        let later = Instant::now() + Duration::from_secs(31);
+        let probe = breaker.acquire_at(later).unwrap();
+        assert!(
+            breaker.acquire_at(later).is_err(),
+            "only one probe at a time"
+        );
+        drop(probe);
+        assert!(breaker.acquire().is_err(), "a failed probe reopens");
+
+        let much_later = Instant::now() + Duration::from_secs(31);
+        breaker.acquire_at(much_later).unwrap().succeeded();
+        assert!(breaker.acquire_at(much_later).is_ok());
+    }
+
+    #[tokio::test]
+    async fn retries_idempotent_calls_then_fails_fast() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let mut service = flaky_service(2, calls.clone());
+
+        let response = service
+            .call(request("/products.ProductsService/GetProductPrice"))
+            .await
+            .unwrap();
+        assert_eq!(grpc_status(&response), "0");
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        // Not idempotent, so sent once
+        calls.store(0, Ordering::SeqCst);
+        let response = service
+            .call(request(
+                "/payments.PaymentsService/InitiatePaymentIntegration",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(grpc_status(&response), "14");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // The third consecutive failure opens the breaker, the last retry isn't sent
+        calls.store(0, Ordering::SeqCst);
+        let error = service
+            .call(request("/products.ProductsService/GetProductPrice"))
+            .await
+            .unwrap_err();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        let status = Status::from_error(error);
+        assert_eq!(status.code(), Code::Unavailable);
+        assert_eq!(
+            AppError::from(status),
+            AppError::Unavailable("The products service is unavailable".into())
+        );
+    }
+
+    #[tokio::test]
+    async fn enforces_the_deadline() {
+        let mut service = Resilient {
+            inner: tower::service_fn(|_request: http::Request<BoxBody>| async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                Ok::<_, StdError>(http::Response::new(empty_body()))
+            }),
+            breaker: Arc::new(CircuitBreaker::new("acl", 3, Duration::from_secs(30))),
+            config: config(),
+        };
+
+        let error = service
+            .call(request("/acl.Acl/CheckAuth"))
+            .await
+            .unwrap_err();
+        let status = Status::from_error(error);
+        assert_eq!(status.code(), Code::DeadlineExceeded);
+        assert_eq!(AppError::from(status).code(), "TIMEOUT");
+    }
+}
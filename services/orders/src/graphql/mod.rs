@@ -1,2 +1,20 @@
 pub mod resolvers;
 pub mod schemas;
+
+use axum::http::{header::AUTHORIZATION, HeaderMap, HeaderValue};
+
+/// The headers of a WebSocket connection. Browsers can't set headers on the upgrade request, so the
+/// `Authorization` value of the `connection_init` payload is added when there's one.
+pub fn connection_init_headers(mut headers: HeaderMap, payload: &serde_json::Value) -> HeaderMap {
+    let authorization = payload
+        .get("Authorization")
+        .or_else(|| payload.get("authorization"))
+        .and_then(|value| value.as_str())
+        .and_then(|value| HeaderValue::from_str(value).ok());
+
+    if let Some(authorization) = authorization {
+        headers.insert(AUTHORIZATION, authorization);
+    }
+
+    headers
+}
@@ -1,2 +1,3 @@
 pub mod mutation;
 pub mod query;
+pub mod subscription;
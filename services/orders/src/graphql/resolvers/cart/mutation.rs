@@ -5,6 +5,8 @@ use async_graphql::{Context, Error, Object, Result};
 use axum::{http::HeaderMap, Extension};
 use hyper::header::{AUTHORIZATION, COOKIE, SET_COOKIE};
 use lib::{
+    config,
+    error::AppError,
     integration::{
         foreign_key::add_foreign_key_if_not_exists,
         grpc::clients::products_service::{
@@ -13,14 +15,14 @@ use lib::{
         },
     },
     middleware::auth::graphql::check_auth_from_acl,
+    observability::{observe_grpc_call, record_cart_created},
     utils::{
         custom_error::ExtendedError,
-        grpc::{create_grpc_client, AuthMetaData},
+        grpc::{create_grpc_client, AuthMetaData, GrpcChannel},
         models::{ForeignKey, License, Product, User},
     },
 };
-use surrealdb::{engine::remote::ws::Client, Surreal};
-use tonic::transport::Channel;
+use surrealdb::{engine::any::Any, Surreal};
 use uuid::Uuid;
 
 struct UpdateCartArgs {
@@ -28,9 +30,10 @@ struct UpdateCartArgs {
     pub internal_product_id: String,
     pub cart_operation: CartOperation,
     pub product_price: u64,
-    pub db_ctx: Extension<Arc<Surreal<Client>>>,
+    pub db_ctx: Extension<Arc<Surreal<Any>>>,
     pub license_id: String,
     pub artifact: String,
+    pub license_price_factor: u64,
 }
 
 #[derive(Debug)]
@@ -38,7 +41,7 @@ struct NewCartArgs {
     pub internal_product_id: String,
     pub product_price: u64,
     pub internal_user_id: Option<String>,
-    pub db_ctx: Extension<Arc<Surreal<Client>>>,
+    pub db_ctx: Extension<Arc<Surreal<Any>>>,
     pub session_id: String,
     pub license_id: String,
     pub artifact: String,
@@ -58,7 +61,7 @@ impl CartMutation {
         cart_operation: CartOperation,
         external_license_id: String,
     ) -> Result<Cart> {
-        let db = ctx.data::<Extension<Arc<Surreal<Client>>>>().unwrap();
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
 
         if let Some(headers) = ctx.data_opt::<HeaderMap>() {
             let session_id = set_session_cookie(&mut headers.clone(), ctx);
@@ -76,12 +79,12 @@ impl CartMutation {
             };
 
             let product_fk = add_foreign_key_if_not_exists::<
-                Extension<Arc<Surreal<Client>>>,
+                Extension<Arc<Surreal<Any>>>,
                 Product,
             >(db, product_fk_body)
             .await;
             let license_fk = add_foreign_key_if_not_exists::<
-                Extension<Arc<Surreal<Client>>>,
+                Extension<Arc<Surreal<Any>>>,
                 License,
             >(db, license_fk_body)
             .await;
@@ -117,9 +120,9 @@ impl CartMutation {
 
             let mut products_grpc_client = create_grpc_client::<
                 ProductId,
-                ProductsServiceClient<Channel>,
+                ProductsServiceClient<GrpcChannel>,
             >(
-                "http://[::1]:50054", true, Some(auth_metadata)
+                &config::get().endpoints.products, true, Some(auth_metadata)
             )
             .await
             .map_err(|e| {
@@ -131,11 +134,15 @@ impl CartMutation {
                 .build()
             })?;
 
-            let product_price = products_grpc_client
-                .get_product_price(get_product_price_request)
-                .await?
-                .into_inner()
-                .price;
+            let product_price = observe_grpc_call(
+                "products",
+                "GetProductPrice",
+                products_grpc_client.get_product_price(get_product_price_request),
+            )
+            .await
+            .map_err(AppError::from)?
+            .into_inner()
+            .price;
 
             let mut get_product_artifact_request =
                 tonic::Request::new(RetrieveProductArtifactArgs {
@@ -151,9 +158,9 @@ impl CartMutation {
 
             let mut products_grpc_client = create_grpc_client::<
                 RetrieveProductArtifactArgs,
-                ProductsServiceClient<Channel>,
+                ProductsServiceClient<GrpcChannel>,
             >(
-                "http://[::1]:50054", true, Some(auth_metadata)
+                &config::get().endpoints.products, true, Some(auth_metadata)
             )
             .await
             .map_err(|e| {
@@ -165,17 +172,22 @@ impl CartMutation {
                 .build()
             })?;
 
-            let product_artifact = products_grpc_client
-                .get_product_artifact(get_product_artifact_request)
-                .await?
-                .into_inner()
-                .artifact;
+            let product_artifact = observe_grpc_call(
+                "products",
+                "GetProductArtifact",
+                products_grpc_client.get_product_artifact(get_product_artifact_request),
+            )
+            .await
+            .map_err(AppError::from)?
+            .into_inner()
+            .artifact;
 
             tracing::debug!("product_artifact: {:?}", product_artifact);
 
             let mut get_license_price_factor_request =
                 tonic::Request::new(GetLicensePriceFactorArgs {
                     license_id: external_license_id.clone(),
+                    product_id: external_product_id.clone(),
                 });
 
             let auth_metadata: AuthMetaData<GetLicensePriceFactorArgs> = AuthMetaData {
@@ -186,9 +198,9 @@ impl CartMutation {
 
             let mut products_grpc_client = create_grpc_client::<
                 GetLicensePriceFactorArgs,
-                ProductsServiceClient<Channel>,
+                ProductsServiceClient<GrpcChannel>,
             >(
-                "http://[::1]:50054", true, Some(auth_metadata)
+                &config::get().endpoints.products, true, Some(auth_metadata)
             )
             .await
             .map_err(|e| {
@@ -200,11 +212,15 @@ impl CartMutation {
                 .build()
             })?;
 
-            let license_price_factor = products_grpc_client
-                .get_license_price_factor(get_license_price_factor_request)
-                .await?
-                .into_inner()
-                .price_factor;
+            let license_price_factor = observe_grpc_call(
+                "products",
+                "GetLicensePriceFactor",
+                products_grpc_client.get_license_price_factor(get_license_price_factor_request),
+            )
+            .await
+            .map_err(AppError::from)?
+            .into_inner()
+            .price_factor;
 
             match check_auth_from_acl(headers).await {
                 Ok(auth_status) => {
@@ -215,7 +231,7 @@ impl CartMutation {
                     };
 
                     let user_fk = add_foreign_key_if_not_exists::<
-                        Extension<Arc<Surreal<Client>>>,
+                        Extension<Arc<Surreal<Any>>>,
                         User,
                     >(db, user_fk_body)
                     .await;
@@ -231,7 +247,7 @@ impl CartMutation {
                     let _claimed_cart = claim_cart(db, &internal_user_id, &session_id).await;
 
                     let mut existing_cart_query = db
-                        .query("SELECT * FROM cart WHERE archived=false AND owner=type::thing($user_id) LIMIT 1")
+                        .query("SELECT * FROM cart WHERE archived=false AND upgrade_of IS NONE AND owner=type::thing($user_id) LIMIT 1")
                         .bind(("user_id", format!("user_id:{}", internal_user_id)))
                         .await
                         .map_err(|e| Error::new(e.to_string()))?;
@@ -248,6 +264,7 @@ impl CartMutation {
                                 db_ctx: db.clone(),
                                 license_id: internal_license_id,
                                 artifact: product_artifact,
+                                license_price_factor,
                             };
 
                             let updated_cart = update_existing_cart(update_args).await;
@@ -282,7 +299,7 @@ impl CartMutation {
                 }
                 Err(_e) => {
                     let mut existing_cart_query = db
-                        .query("SELECT * FROM cart WHERE archived=false AND session_id=$session_id LIMIT 1")
+                        .query("SELECT * FROM cart WHERE archived=false AND upgrade_of IS NONE AND session_id=$session_id LIMIT 1")
                         .bind(("session_id", session_id.clone()))
                         .await
                         .map_err(|e| Error::new(e.to_string()))?;
@@ -299,6 +316,7 @@ impl CartMutation {
                                 db_ctx: db.clone(),
                                 license_id: internal_license_id,
                                 artifact: product_artifact,
+                                license_price_factor,
                             };
 
                             let updated_cart = update_existing_cart(update_args).await;
@@ -362,7 +380,7 @@ async fn update_existing_cart(args: UpdateCartArgs) -> Result<Cart> {
                     LET $removed_amount = $prev_license_factor * $product_price;
                     UPDATE $cart SET total_amount -= $removed_amount RETURN AFTER;
 
-              		LET $updates_license = (UPDATE $found_product SET license = $license.id RETURN AFTER);
+              		LET $updates_license = (UPDATE $found_product SET license = $license.id, unit_price = $product_price, price_factor = $license_price_factor RETURN AFTER);
 
               		RETURN $updates_license[0].quantity;
 
@@ -375,7 +393,9 @@ async fn update_existing_cart(args: UpdateCartArgs) -> Result<Cart> {
              			license: $license.id,
              			out: $product,
              			quantity: 1,
-                        artifact: $artifact
+                        artifact: $artifact,
+                        unit_price: $product_price,
+                        price_factor: $license_price_factor
               		} RETURN AFTER);
 
               		RETURN $updates[0].quantity;
@@ -393,6 +413,7 @@ async fn update_existing_cart(args: UpdateCartArgs) -> Result<Cart> {
             .bind(("cart_id", format!("cart:{}", cart_id_raw)))
             .bind(("license_id", format!("license:{}", args.license_id)))
             .bind(("artifact", args.artifact))
+            .bind(("license_price_factor", args.license_price_factor))
             .await
             .map_err(|e| Error::new(e.to_string()))?;
 
@@ -457,7 +478,9 @@ async fn create_new_cart(args: NewCartArgs) -> Result<Cart> {
         RELATE $cart_id-> cart_product -> $product CONTENT {
             quantity: 1,
             license: $license,
-            artifact: $artifact
+            artifact: $artifact,
+            unit_price: $product_price,
+            price_factor: $license_price_factor
         };
         RETURN $new_cart;
         COMMIT TRANSACTION;
@@ -484,6 +507,7 @@ async fn create_new_cart(args: NewCartArgs) -> Result<Cart> {
         .map_err(|e| Error::new(e.to_string()))?;
 
     let response: Vec<Cart> = create_cart_transaction.take(0)?;
+    record_cart_created();
 
     Ok(response.first().unwrap().to_owned())
 }
@@ -513,9 +537,9 @@ pub fn set_session_cookie(headers: &mut HeaderMap, ctx: &Context<'_>) -> String
 
 /// Utility function to claim a cart that was instantiated anonymously
 pub async fn claim_cart(
-    db: &Extension<Arc<Surreal<Client>>>,
-    internal_user_id: &String,
-    session_id: &String,
+    db: &Extension<Arc<Surreal<Any>>>,
+    internal_user_id: &str,
+    session_id: &str,
 ) -> Result<Option<Cart>> {
     let mut existing_cart_query = db
         .query(
@@ -525,8 +549,8 @@ pub async fn claim_cart(
             RETURN $updates[0];
             "
         )
-        .bind(("session_id", session_id.clone()))
-        .bind(("internal_user_id", format!("user_id:{}", &internal_user_id)))
+        .bind(("session_id", session_id.to_string()))
+        .bind(("internal_user_id", format!("user_id:{}", internal_user_id)))
         .await
         .map_err(|e| Error::new(e.to_string()))?;
 
@@ -534,3 +558,41 @@ pub async fn claim_cart(
 
     Ok(existing_cart)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::migrations::MIGRATIONS;
+    use lib::database::memory::connect_in_memory;
+    use surrealdb::sql::Thing;
+
+    #[tokio::test]
+    async fn claiming_a_cart_sets_its_owner() {
+        let db = Extension(connect_in_memory(MIGRATIONS).await.unwrap());
+        db.query(
+            "
+            CREATE user_id:buyer SET user_id = 'buyer-1';
+            CREATE cart:anonymous SET session_id = 'session-1';
+            CREATE cart:other SET session_id = 'session-2';
+            ",
+        )
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+
+        claim_cart(&db, "buyer", "session-1")
+            .await
+            .unwrap();
+
+        let mut owners_query = db
+            .query("SELECT VALUE owner FROM cart:anonymous; SELECT VALUE owner FROM cart:other;")
+            .await
+            .unwrap();
+        let claimed: Vec<Option<Thing>> = owners_query.take(0).unwrap();
+        let untouched: Vec<Option<Thing>> = owners_query.take(1).unwrap();
+
+        assert_eq!(claimed, vec![Some(Thing::from(("user_id", "buyer")))]);
+        assert_eq!(untouched, vec![None]);
+    }
+}
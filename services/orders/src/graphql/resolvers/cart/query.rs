@@ -3,8 +3,11 @@ use std::sync::Arc;
 use async_graphql::{Context, Error, Object, Result};
 use axum::Extension;
 use hyper::HeaderMap;
-use lib::utils::custom_error::ExtendedError;
-use surrealdb::{engine::remote::ws::Client, Surreal};
+use lib::{
+    error::AppError, middleware::auth::graphql::check_auth_from_acl,
+    utils::custom_error::ExtendedError,
+};
+use surrealdb::{engine::any::Any, Surreal};
 
 use crate::graphql::{resolvers::cart::mutation::set_session_cookie, schemas::general::Cart};
 
@@ -18,7 +21,7 @@ impl CartQuery {
         ctx: &Context<'_>,
         cart_id: String,
     ) -> Result<Vec<String>> {
-        let db = ctx.data::<Extension<Arc<Surreal<Client>>>>().unwrap();
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
 
         let mut external_product_ids_query = db
         .query(
@@ -36,14 +39,14 @@ impl CartQuery {
     }
 
     async fn get_cart(&self, ctx: &Context<'_>) -> Result<Cart> {
-        let db = ctx.data::<Extension<Arc<Surreal<Client>>>>().unwrap();
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
 
         if let Some(headers) = ctx.data_opt::<HeaderMap>() {
             let session_id = set_session_cookie(&mut headers.clone(), ctx);
             let mut external_product_ids_query = db
                 .query(
                     "
-                SELECT * FROM ONLY cart WHERE session_id = $session_id AND archived=false LIMIT 1
+                SELECT * FROM ONLY cart WHERE session_id = $session_id AND archived=false AND upgrade_of IS NONE LIMIT 1
                 ",
                 )
                 .bind(("session_id", session_id))
@@ -67,14 +70,14 @@ impl CartQuery {
         ctx: &Context<'_>,
         external_product_id: String,
     ) -> Result<u64> {
-        let db = ctx.data::<Extension<Arc<Surreal<Client>>>>().unwrap();
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
 
         let mut product_total_sales_query = db
         .query(
             "
             BEGIN TRANSACTION;
             LET $internal_product = (SELECT VALUE id FROM product_id WHERE product_id=$external_product_id);
-            LET $total_sales = (SELECT VALUE count(<-cart_product<-(cart WHERE archived=true)) FROM ONLY $internal_product LIMIT 1);
+            LET $total_sales = (SELECT VALUE count(<-cart_product<-(cart WHERE archived=true AND upgrade_of IS NONE)) FROM ONLY $internal_product LIMIT 1);
             RETURN $total_sales;
             COMMIT TRANSACTION;
             "
@@ -90,4 +93,28 @@ impl CartQuery {
             None => Err(ExtendedError::new("Not found!", Some(404.to_string())).build()),
         }
     }
+
+    /// Federation entity resolver for the carts of the current user. Anonymous carts are only
+    /// available through `get_cart`.
+    #[graphql(entity)]
+    async fn find_cart_by_id(&self, ctx: &Context<'_>, id: String) -> Result<Cart> {
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
+
+        if let Some(headers) = ctx.data_opt::<HeaderMap>() {
+            let auth_status = check_auth_from_acl(headers).await?;
+
+            let mut cart_query = db
+                .query("SELECT * FROM type::thing($cart_id) WHERE owner.user_id = $user_id")
+                .bind(("cart_id", format!("cart:{}", id)))
+                .bind(("user_id", auth_status.sub))
+                .await
+                .map_err(AppError::database)?;
+
+            let cart: Option<Cart> = cart_query.take(0).map_err(AppError::deserialization)?;
+
+            Ok(cart.ok_or_else(|| AppError::NotFound("Cart Not Found".into()))?)
+        } else {
+            Err(ExtendedError::new("Invalid Request!", Some(400.to_string())).build())
+        }
+    }
 }
@@ -0,0 +1,238 @@
+use std::sync::Arc;
+
+use async_graphql::{Context, Result, Subscription};
+use axum::Extension;
+use futures_util::{future, Stream, StreamExt};
+use hyper::HeaderMap;
+use lib::{
+    error::AppError,
+    integration::foreign_key::add_foreign_key_if_not_exists,
+    middleware::auth::graphql::check_auth_from_acl,
+    utils::{
+        custom_error::ExtendedError,
+        models::{ForeignKey, User},
+    },
+};
+use surrealdb::{engine::any::Any, Action, Notification, Surreal};
+
+use crate::graphql::schemas::general::Cart;
+
+#[derive(Default)]
+pub struct CartSubscription;
+
+#[Subscription]
+impl CartSubscription {
+    /// Emits the current user's carts whenever one is created or changes, e.g. a product is added
+    /// or the cart is archived after checkout.
+    async fn cart_updated(&self, ctx: &Context<'_>) -> Result<impl Stream<Item = Cart>> {
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
+
+        if let Some(headers) = ctx.data_opt::<HeaderMap>() {
+            let auth_status = check_auth_from_acl(headers).await?;
+
+            let user_fk = ForeignKey {
+                table: "user_id".into(),
+                column: "user_id".into(),
+                foreign_key: auth_status.sub,
+            };
+
+            let owner =
+                add_foreign_key_if_not_exists::<Extension<Arc<Surreal<Any>>>, User>(db, user_fk)
+                    .await
+                    .and_then(|user| user.id)
+                    .ok_or_else(|| AppError::Internal("Failed to get the current user".into()))?;
+
+            // Only the user's carts are sent by the database. Live queries don't keep the bound
+            // parameters (SurrealDB 2.2), so the owner is inlined: a record ID from the database,
+            // escaped when displayed. The live query is killed when the client unsubscribes and
+            // the stream is dropped
+            let mut live_query = db
+                .query(format!("LIVE SELECT * FROM cart WHERE owner = {}", owner))
+                .await
+                .map_err(AppError::database)?;
+            let notifications = live_query
+                .stream::<Notification<Cart>>(0)
+                .map_err(AppError::database)?;
+
+            Ok(notifications.filter_map(|notification| {
+                let cart = match notification {
+                    Ok(notification) if !matches!(notification.action, Action::Delete) => {
+                        Some(notification.data)
+                    }
+                    Ok(_) => None,
+                    Err(e) => {
+                        tracing::error!("Cart live query failed: {}", e);
+                        None
+                    }
+                };
+
+                future::ready(cart)
+            }))
+        } else {
+            Err(ExtendedError::new("Invalid Request!", Some(400.to_string())).build())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        database::migrations::MIGRATIONS,
+        graphql::{connection_init_headers, resolvers::query::EmptyQuery},
+    };
+    use async_graphql::{EmptyMutation, Request, Schema};
+    use hyper::header::{AUTHORIZATION, COOKIE};
+    use lib::{database::memory::connect_in_memory, test_support::FakeServices};
+    use serde_json::json;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    fn user_headers() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, "Bearer user-token".parse().unwrap());
+        headers.insert(COOKIE, "session_id=session-1".parse().unwrap());
+        headers
+    }
+
+    #[tokio::test]
+    async fn users_only_get_their_own_carts() {
+        let fake_services = FakeServices::start().await.unwrap();
+        fake_services.acl.authenticate_as("buyer-1");
+        let db = connect_in_memory(MIGRATIONS).await.unwrap();
+        db.query("CREATE user_id:buyer SET user_id = 'buyer-1'")
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+
+        let schema = Schema::new(EmptyQuery, EmptyMutation, CartSubscription);
+        let mut carts = schema.execute_stream(
+            Request::new("subscription { cartUpdated { id totalAmount } }")
+                .data(Extension(db.clone()))
+                .data(user_headers()),
+        );
+        // Polling starts the live query, nothing changed yet
+        assert!(timeout(Duration::from_millis(500), carts.next())
+            .await
+            .is_err());
+
+        db.query(
+            "
+            CREATE user_id:other SET user_id = 'other-1';
+            CREATE cart:theirs SET owner = user_id:other, session_id = 'session-2', total_amount = 5;
+            CREATE cart:mine SET owner = user_id:buyer, session_id = 'session-1', total_amount = 10;
+            ",
+        )
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+
+        let response = timeout(Duration::from_secs(5), carts.next())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        assert_eq!(
+            response.data.into_json().unwrap(),
+            json!({ "cartUpdated": { "id": "mine", "totalAmount": 10 } })
+        );
+
+        // The other user's cart isn't sent, even when it changes again
+        db.query("UPDATE cart:theirs SET total_amount = 6")
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+        assert!(timeout(Duration::from_millis(500), carts.next())
+            .await
+            .is_err());
+    }
+
+    /// Subscribes with the headers and waits for the buyer's new cart
+    async fn receive_new_cart(headers: HeaderMap) {
+        let db = connect_in_memory(MIGRATIONS).await.unwrap();
+        db.query("CREATE user_id:buyer SET user_id = 'buyer-1'")
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+
+        let schema = Schema::new(EmptyQuery, EmptyMutation, CartSubscription);
+        let mut carts = schema.execute_stream(
+            Request::new("subscription { cartUpdated { id } }")
+                .data(Extension(db.clone()))
+                .data(headers),
+        );
+        assert!(timeout(Duration::from_millis(500), carts.next())
+            .await
+            .is_err());
+
+        db.query("CREATE cart:mine SET owner = user_id:buyer, session_id = 'session-1'")
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+
+        let response = timeout(Duration::from_secs(5), carts.next())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        assert_eq!(
+            response.data.into_json().unwrap(),
+            json!({ "cartUpdated": { "id": "mine" } })
+        );
+    }
+
+    #[tokio::test]
+    async fn cookie_only_clients_can_subscribe() {
+        let fake_services = FakeServices::start().await.unwrap();
+        fake_services.acl.authenticate_as("buyer-1");
+
+        let mut headers = HeaderMap::new();
+        headers.insert(COOKIE, "session_id=session-1".parse().unwrap());
+        receive_new_cart(headers).await;
+
+        let calls = fake_services.acl.check_auth.calls();
+        assert_eq!(calls[0].authorization, None);
+        assert_eq!(calls[0].cookie.as_deref(), Some("session_id=session-1"));
+    }
+
+    #[tokio::test]
+    async fn clients_can_authenticate_in_connection_init() {
+        let fake_services = FakeServices::start().await.unwrap();
+        fake_services.acl.authenticate_as("buyer-1");
+
+        let headers = connection_init_headers(
+            HeaderMap::new(),
+            &json!({ "Authorization": "Bearer user-token" }),
+        );
+        receive_new_cart(headers).await;
+
+        let calls = fake_services.acl.check_auth.calls();
+        assert_eq!(calls[0].authorization.as_deref(), Some("Bearer user-token"));
+        assert_eq!(calls[0].cookie, None);
+    }
+
+    #[tokio::test]
+    async fn anonymous_users_cant_subscribe() {
+        let fake_services = FakeServices::start().await.unwrap();
+        fake_services.acl.reject_auth();
+        let db = connect_in_memory(MIGRATIONS).await.unwrap();
+
+        let schema = Schema::new(EmptyQuery, EmptyMutation, CartSubscription);
+        let response = schema
+            .execute_stream(
+                Request::new("subscription { cartUpdated { id } }")
+                    .data(Extension(db))
+                    .data(user_headers()),
+            )
+            .next()
+            .await
+            .unwrap();
+
+        assert_eq!(response.errors[0].message, "Unauthorized");
+    }
+}
@@ -0,0 +1,392 @@
+use std::sync::Arc;
+
+use async_graphql::{Context, Object, Result};
+use axum::{http::HeaderMap, Extension};
+use hyper::header::{AUTHORIZATION, COOKIE};
+use lib::{
+    config,
+    error::AppError,
+    events::{
+        outbox::{OutboxEntry, RECORD_EVENT},
+        DomainEvent,
+    },
+    integration::{
+        foreign_key::add_foreign_key_if_not_exists,
+        grpc::clients::products_service::{
+            products_service_client::ProductsServiceClient, GetLicensePriceFactorArgs, ProductId,
+            RetrieveProductArtifactArgs,
+        },
+    },
+    middleware::auth::graphql::check_auth_from_acl,
+    observability::observe_grpc_call,
+    utils::{
+        custom_error::ExtendedError,
+        grpc::{create_grpc_client, AuthMetaData, GrpcChannel},
+        models::{ForeignKey, License, User},
+    },
+};
+use serde::Deserialize;
+use surrealdb::{
+    engine::any::Any,
+    sql::{Id, Thing},
+    Surreal,
+};
+
+use crate::graphql::{
+    resolvers::orders::mutation::{ensure_latest_terms_accepted, initiate_order_payment},
+    schemas::general::Order,
+};
+
+#[derive(Debug, Deserialize)]
+struct UpgradableCartProduct {
+    id: Thing,
+    out: Thing,
+    license_id: String,
+    unit_price: Option<u64>,
+    price_factor: Option<u64>,
+    upgraded_price_factor: Option<u64>,
+    pending_upgrade: Option<Thing>,
+}
+
+#[derive(Default)]
+pub struct LicenseMutation;
+
+#[Object]
+impl LicenseMutation {
+    /// Resolver method to upgrade the license of a purchased product. The buyer only pays the difference
+    /// between the price snapshot of the original purchase and the target license. Returns the payment link.
+    pub async fn upgrade_license(
+        &self,
+        ctx: &Context<'_>,
+        order_id: String,
+        product_id: String,
+        target_license_id: String,
+    ) -> Result<String> {
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
+
+        if let Some(headers) = ctx.data_opt::<HeaderMap>() {
+            let auth_status = check_auth_from_acl(headers).await?;
+
+            ensure_latest_terms_accepted(headers, &auth_status.sub).await?;
+
+            let user_fk = ForeignKey {
+                table: "user_id".into(),
+                column: "user_id".into(),
+                foreign_key: auth_status.sub.clone(),
+            };
+
+            let buyer =
+                add_foreign_key_if_not_exists::<Extension<Arc<Surreal<Any>>>, User>(db, user_fk)
+                    .await
+                    .ok_or_else(|| AppError::Internal("Failed to get the current user".into()))?;
+            let internal_user_id = buyer
+                .id
+                .as_ref()
+                .map(|t| t.id.to_raw())
+                .ok_or_else(|| AppError::Internal("Failed to get the current user".into()))?;
+
+            // Only products from the buyer's own paid orders can be upgraded. Confirmed upgrades of the
+            // same cart_product count towards the license the buyer already holds, and one awaiting payment
+            // blocks another so the buyer isn't charged twice.
+            let mut purchased_product_query = db
+                .query(
+                    "
+                BEGIN TRANSACTION;
+                LET $order = type::thing($order_id);
+                LET $user = type::thing($user_id);
+                LET $cart_product = (
+                    SELECT id, out, license.license_id AS license_id, unit_price, price_factor
+                    FROM ONLY cart_product
+                    WHERE out.product_id = $product_id
+                    AND <-cart<-(order WHERE id = $order AND in = $user AND status INSIDE ['Confirmed', 'Completed'])
+                    LIMIT 1
+                );
+                LET $upgraded_price_factor = math::max((
+                    SELECT VALUE price_factor FROM cart_product
+                    WHERE in.upgrade_of = $cart_product.id AND in.archived = true
+                ));
+                LET $pending_upgrade = (
+                    SELECT VALUE id FROM cart
+                    WHERE upgrade_of = $cart_product.id AND archived = false
+                    AND <-(order WHERE status = 'Pending')
+                    LIMIT 1
+                )[0];
+                RETURN IF $cart_product IS NONE {
+                    NONE
+                } ELSE {
+                    object::extend($cart_product, {
+                        upgraded_price_factor: $upgraded_price_factor,
+                        pending_upgrade: $pending_upgrade
+                    })
+                };
+                COMMIT TRANSACTION;
+                ",
+                )
+                .bind(("order_id", format!("order:{}", order_id)))
+                .bind(("user_id", format!("user_id:{}", internal_user_id)))
+                .bind(("product_id", product_id.clone()))
+                .await
+                .map_err(AppError::database)?;
+
+            let purchased_product: Option<UpgradableCartProduct> = purchased_product_query
+                .take(0)
+                .map_err(AppError::deserialization)?;
+
+            let purchased_product = match purchased_product {
+                Some(purchased_product) => purchased_product,
+                None => {
+                    return Err(ExtendedError::new(
+                        "Purchased product not found!",
+                        Some(404.to_string()),
+                    )
+                    .build())
+                }
+            };
+
+            if purchased_product.pending_upgrade.is_some() {
+                return Err(ExtendedError::new(
+                    "An upgrade of this product is already awaiting payment!",
+                    Some(409.to_string()),
+                )
+                .build());
+            }
+
+            let current_price_factor = match purchased_product.price_factor {
+                Some(price_factor) => price_factor,
+                None => {
+                    get_license_price_factor(
+                        headers,
+                        purchased_product.license_id.clone(),
+                        product_id.clone(),
+                    )
+                    .await?
+                }
+            }
+            .max(purchased_product.upgraded_price_factor.unwrap_or(0));
+
+            let target_price_factor =
+                get_license_price_factor(headers, target_license_id.clone(), product_id.clone())
+                    .await?;
+
+            if target_price_factor <= current_price_factor {
+                return Err(ExtendedError::new(
+                    "The target license is not an upgrade!",
+                    Some(400.to_string()),
+                )
+                .build());
+            }
+
+            let unit_price = match purchased_product.unit_price {
+                Some(unit_price) => unit_price,
+                None => {
+                    let mut get_product_price_request = tonic::Request::new(ProductId {
+                        product_id: product_id.clone(),
+                    });
+
+                    let auth_metadata: AuthMetaData<ProductId> = AuthMetaData {
+                        auth_header: headers.get(AUTHORIZATION),
+                        cookie_header: headers.get(COOKIE),
+                        constructed_grpc_request: Some(&mut get_product_price_request),
+                    };
+
+                    let mut products_grpc_client = create_grpc_client::<
+                        ProductId,
+                        ProductsServiceClient<GrpcChannel>,
+                    >(
+                        &config::get().endpoints.products, true, Some(auth_metadata)
+                    )
+                    .await
+                    .map_err(|e| {
+                        tracing::error!("Failed to connect to Products service: {}", e);
+                        ExtendedError::new(
+                            "Failed to connect to Products service",
+                            Some(500.to_string()),
+                        )
+                        .build()
+                    })?;
+
+                    observe_grpc_call(
+                        "products",
+                        "GetProductPrice",
+                        products_grpc_client.get_product_price(get_product_price_request),
+                    )
+                    .await?
+                    .into_inner()
+                    .price
+                }
+            };
+
+            let upgrade_amount = unit_price * (target_price_factor - current_price_factor);
+
+            let mut get_product_artifact_request =
+                tonic::Request::new(RetrieveProductArtifactArgs {
+                    product_id: product_id.clone(),
+                    license_id: target_license_id.clone(),
+                });
+
+            let auth_metadata: AuthMetaData<RetrieveProductArtifactArgs> = AuthMetaData {
+                auth_header: headers.get(AUTHORIZATION),
+                cookie_header: headers.get(COOKIE),
+                constructed_grpc_request: Some(&mut get_product_artifact_request),
+            };
+
+            let mut products_grpc_client = create_grpc_client::<
+                RetrieveProductArtifactArgs,
+                ProductsServiceClient<GrpcChannel>,
+            >(
+                &config::get().endpoints.products, true, Some(auth_metadata)
+            )
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to connect to Products service: {}", e);
+                ExtendedError::new(
+                    "Failed to connect to Products service",
+                    Some(500.to_string()),
+                )
+                .build()
+            })?;
+
+            let target_artifact = observe_grpc_call(
+                "products",
+                "GetProductArtifact",
+                products_grpc_client.get_product_artifact(get_product_artifact_request),
+            )
+            .await?
+            .into_inner()
+            .artifact;
+
+            let license_fk = ForeignKey {
+                table: "license_id".into(),
+                column: "license_id".into(),
+                foreign_key: target_license_id,
+            };
+
+            let license_id =
+                add_foreign_key_if_not_exists::<Extension<Arc<Surreal<Any>>>, License>(
+                    db, license_fk,
+                )
+                .await
+                .and_then(|license| license.id)
+                .ok_or_else(|| AppError::Internal("Failed to get the target license".into()))?;
+
+            let order_key = Id::rand().to_raw();
+            let order_created = OutboxEntry::new(
+                &config::get().service,
+                DomainEvent::OrderCreated {
+                    order_id: order_key.clone(),
+                    buyer_id: auth_status.sub.clone(),
+                    total_amount: upgrade_amount,
+                },
+            );
+
+            // The upgrade cart is paid and archived like any other cart, so the normal payment webhook
+            // grants the target artifact (GetAllArtifactsForOrder -> PurchaseFile) and issues its certificate
+            let mut upgrade_order_transaction = db
+                .query(format!(
+                    "
+                BEGIN TRANSACTION;
+                LET $user = type::thing($user_id);
+                LET $license = type::thing($license_id);
+                LET $order = type::thing('order', $order_key);
+                LET $upgrade_cart = (CREATE ONLY cart CONTENT {{
+                    owner: $user,
+                    total_amount: $upgrade_amount,
+                    session_id: '',
+                    upgrade_of: $cart_product
+                }});
+                RELATE ($upgrade_cart.id) -> cart_product -> $product CONTENT {{
+                    quantity: 1,
+                    license: $license,
+                    artifact: $artifact,
+                    unit_price: $unit_price,
+                    price_factor: $target_price_factor
+                }};
+                LET $new_order = (RELATE $user -> $order -> ($upgrade_cart.id) CONTENT {{
+                    status: 'Pending',
+                }} RETURN AFTER);
+                {}
+                RETURN $new_order;
+                COMMIT TRANSACTION;
+                ",
+                    RECORD_EVENT
+                ))
+                .bind(("user_id", format!("user_id:{}", internal_user_id)))
+                .bind((
+                    "license_id",
+                    format!("license_id:{}", license_id.id.to_raw()),
+                ))
+                .bind(("cart_product", purchased_product.id))
+                .bind(("product", purchased_product.out))
+                .bind(("artifact", target_artifact))
+                .bind(("unit_price", unit_price))
+                .bind(("target_price_factor", target_price_factor))
+                .bind(("upgrade_amount", upgrade_amount))
+                .bind(("order_key", order_key))
+                .bind(("event", order_created))
+                .await
+                .map_err(AppError::database)?;
+
+            let new_order: Vec<Order> = upgrade_order_transaction
+                .take(0)
+                .map_err(AppError::deserialization)?;
+            let new_order_id = new_order
+                .into_iter()
+                .next()
+                .and_then(|order| order.id)
+                .ok_or_else(|| AppError::Internal("Failed to create the upgrade order".into()))?;
+
+            initiate_order_payment(
+                headers,
+                buyer.user_id,
+                upgrade_amount,
+                new_order_id.id.to_raw(),
+            )
+            .await
+        } else {
+            Err(ExtendedError::new("Invalid Request!", Some(400.to_string())).build())
+        }
+    }
+}
+
+/// Utility function to get the price factor of a license from the products service
+async fn get_license_price_factor(
+    headers: &HeaderMap,
+    license_id: String,
+    product_id: String,
+) -> Result<u64> {
+    let mut get_license_price_factor_request = tonic::Request::new(GetLicensePriceFactorArgs {
+        license_id,
+        product_id,
+    });
+
+    let auth_metadata: AuthMetaData<GetLicensePriceFactorArgs> = AuthMetaData {
+        auth_header: headers.get(AUTHORIZATION),
+        cookie_header: headers.get(COOKIE),
+        constructed_grpc_request: Some(&mut get_license_price_factor_request),
+    };
+
+    let mut products_grpc_client = create_grpc_client::<
+        GetLicensePriceFactorArgs,
+        ProductsServiceClient<GrpcChannel>,
+    >(&config::get().endpoints.products, true, Some(auth_metadata))
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to connect to Products service: {}", e);
+        ExtendedError::new(
+            "Failed to connect to Products service",
+            Some(500.to_string()),
+        )
+        .build()
+    })?;
+
+    let price_factor = observe_grpc_call(
+        "products",
+        "GetLicensePriceFactor",
+        products_grpc_client.get_license_price_factor(get_license_price_factor_request),
+    )
+    .await?
+    .into_inner()
+    .price_factor;
+
+    Ok(price_factor)
+}
@@ -0,0 +1,76 @@
+use std::sync::Arc;
+
+use async_graphql::{Context, Object, Result};
+use axum::Extension;
+use hyper::HeaderMap;
+use lib::{middleware::auth::graphql::check_auth_from_acl, utils::custom_error::ExtendedError};
+use surrealdb::{engine::any::Any, Surreal};
+
+use crate::{
+    graphql::schemas::licenses::{LicenseCertificate, LicenseVerification},
+    utils::licenses::{
+        decode_license_key, get_license_certificate_by_key, get_license_certificates,
+        get_license_public_key,
+    },
+};
+
+#[derive(Default)]
+pub struct LicenseQuery;
+
+#[Object]
+impl LicenseQuery {
+    /// Resolver method to get the license certificates of the current user, optionally for a single order
+    async fn get_my_license_certificates(
+        &self,
+        ctx: &Context<'_>,
+        order_id: Option<String>,
+    ) -> Result<Vec<LicenseCertificate>> {
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
+
+        if let Some(headers) = ctx.data_opt::<HeaderMap>() {
+            let auth_status = check_auth_from_acl(headers).await?;
+
+            let certificates =
+                get_license_certificates(db, Some(auth_status.sub.as_str()), order_id.as_deref())
+                    .await?;
+
+            Ok(certificates)
+        } else {
+            Err(ExtendedError::new("Invalid Request!", Some(400.to_string())).build())
+        }
+    }
+
+    /// Public resolver method to verify a license key. A key is valid if its signature checks out and it was issued by us.
+    async fn verify_license(
+        &self,
+        ctx: &Context<'_>,
+        license_key: String,
+    ) -> Result<LicenseVerification> {
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
+
+        let payload = match decode_license_key(license_key.as_str()) {
+            Some(payload) => payload,
+            None => {
+                return Ok(LicenseVerification {
+                    valid: false,
+                    certificate: None,
+                })
+            }
+        };
+
+        let certificate = get_license_certificate_by_key(db, license_key.as_str()).await?;
+
+        // A validly signed key that we no longer know of, e.g. revoked, doesn't certify anything
+        Ok(LicenseVerification {
+            valid: certificate.is_some(),
+            certificate: certificate.map(|_| payload),
+        })
+    }
+
+    /// Public resolver method to get the base64 encoded Ed25519 public key for offline license verification
+    async fn get_license_public_key(&self) -> Result<String> {
+        let public_key = get_license_public_key()?;
+
+        Ok(public_key)
+    }
+}
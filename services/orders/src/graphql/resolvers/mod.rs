@@ -1,4 +1,6 @@
 pub mod mutation;
 pub mod query;
+pub mod subscription;
 pub mod orders;
 pub mod cart;
+pub mod licenses;
@@ -1,6 +1,9 @@
 use async_graphql::MergedObject;
 
-use super::{cart::mutation::CartMutation, orders::mutation::OrderMutation};
+use super::{
+    cart::mutation::CartMutation, licenses::mutation::LicenseMutation,
+    orders::mutation::OrderMutation,
+};
 
 #[derive(MergedObject, Default)]
-pub struct Mutation(CartMutation, OrderMutation);
+pub struct Mutation(CartMutation, OrderMutation, LicenseMutation);
@@ -9,8 +9,14 @@ use crate::{
 };
 use async_graphql::{Context, Error, Object, Result};
 use axum::{http::HeaderMap, Extension};
-use hyper::header::{AUTHORIZATION, COOKIE};
+use hyper::header::{ACCEPT_LANGUAGE, AUTHORIZATION, COOKIE};
 use lib::{
+    config,
+    email::Locale,
+    events::{
+        outbox::{OutboxEntry, RECORD_EVENT},
+        DomainEvent,
+    },
     integration::{
         foreign_key::add_foreign_key_if_not_exists,
         grpc::clients::{
@@ -18,18 +24,19 @@ use lib::{
             payments_service::{
                 payments_service_client::PaymentsServiceClient, UserPaymentDetails,
             },
+            shared_service::{shared_service_client::SharedServiceClient, UserId},
         },
         // payments::initiate_payment_integration,
     },
     middleware::auth::graphql::check_auth_from_acl,
+    observability::{observe_grpc_call, record_order_status},
     utils::{
         custom_error::ExtendedError,
-        grpc::{create_grpc_client, AuthMetaData},
+        grpc::{create_grpc_client, AuthMetaData, GrpcChannel},
         models::{ForeignKey, OrderStatus, User},
     },
 };
-use surrealdb::{engine::remote::ws::Client, Surreal};
-use tonic::transport::Channel;
+use surrealdb::{engine::any::Any, sql::Id, Surreal};
 
 #[derive(Default)]
 pub struct OrderMutation;
@@ -37,11 +44,14 @@ pub struct OrderMutation;
 #[Object]
 impl OrderMutation {
     pub async fn create_order(&self, ctx: &Context<'_>) -> Result<String> {
-        let db = ctx.data::<Extension<Arc<Surreal<Client>>>>().unwrap();
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
 
         if let Some(headers) = ctx.data_opt::<HeaderMap>() {
             let auth_status = check_auth_from_acl(headers).await?;
 
+            // The buyer must have accepted the latest license and refund policy before checkout
+            ensure_latest_terms_accepted(headers, &auth_status.sub).await?;
+
             let user_fk = ForeignKey {
                 table: "user_id".into(),
                 column: "user_id".into(),
@@ -51,7 +61,7 @@ impl OrderMutation {
             let session_id = set_session_cookie(&mut headers.clone(), ctx);
 
             let buyer_result =
-                add_foreign_key_if_not_exists::<Extension<Arc<Surreal<Client>>>, User>(db, user_fk)
+                add_foreign_key_if_not_exists::<Extension<Arc<Surreal<Any>>>, User>(db, user_fk)
                     .await;
             let buyer_result_clone = buyer_result.clone();
             let internal_user_id = buyer_result_clone
@@ -65,7 +75,7 @@ impl OrderMutation {
             let _claimed_cart = claim_cart(db, &internal_user_id, &session_id).await?;
 
             let mut existing_cart_query = db
-                .query("SELECT * FROM cart WHERE archived=false AND owner=type::thing($user_id) LIMIT 1")
+                .query("SELECT * FROM cart WHERE archived=false AND upgrade_of IS NONE AND owner=type::thing($user_id) LIMIT 1")
                 .bind(("user_id", format!("user_id:{}", internal_user_id)))
                 .await
                 .map_err(|e| Error::new(e.to_string()))?;
@@ -74,21 +84,36 @@ impl OrderMutation {
 
             match existing_cart {
                 Some(cart) => {
+                    let order_key = Id::rand().to_raw();
+                    let order_created = OutboxEntry::new(
+                        &config::get().service,
+                        DomainEvent::OrderCreated {
+                            order_id: order_key.clone(),
+                            buyer_id: auth_status.sub.clone(),
+                            total_amount: cart.total_amount,
+                        },
+                    );
+
                     let mut create_order_transaction = db
-                        .query(
+                        .query(format!(
                             "
                         BEGIN TRANSACTION;
                         LET $user = type::thing($user_id);
                         LET $cart = type::thing($cart_id);
-                        LET $new_order = (RELATE $user -> order -> $cart CONTENT {
+                        LET $order = type::thing('order', $order_key);
+                        LET $new_order = (RELATE $user -> $order -> $cart CONTENT {{
                             status: 'Pending',
-                        } RETURN AFTER);
+                        }} RETURN AFTER);
+                        {}
                         RETURN $new_order;
                         COMMIT TRANSACTION;
                         ",
-                        )
+                            RECORD_EVENT
+                        ))
                         // .bind(("comment_body", comment))
                         .bind(("user_id", format!("user_id:{}", internal_user_id)))
+                        .bind(("order_key", order_key))
+                        .bind(("event", order_created))
                         .bind((
                             "cart_id",
                             format!(
@@ -100,96 +125,20 @@ impl OrderMutation {
                         .map_err(|e| Error::new(e.to_string()))?;
 
                     let new_order: Vec<Order> = create_order_transaction.take(0)?;
+                    record_order_status(&OrderStatus::Pending);
 
-                    let auth_header = headers.get(AUTHORIZATION);
-                    let cookie_header = headers.get(COOKIE);
-
-                    let mut request = tonic::Request::new(GetUserEmailRequest {
-                        user_id: buyer_result.unwrap().user_id.clone(),
-                    });
-
-                    let auth_metadata: AuthMetaData<GetUserEmailRequest> = AuthMetaData {
-                        auth_header,
-                        cookie_header,
-                        constructed_grpc_request: Some(&mut request),
-                    };
-
-                    let mut acl_grpc_client = create_grpc_client::<
-                        GetUserEmailRequest,
-                        AclClient<Channel>,
-                    >(
-                        "http://[::1]:50051", true, Some(auth_metadata)
+                    initiate_order_payment(
+                        headers,
+                        buyer_result.unwrap().user_id,
+                        cart.total_amount,
+                        new_order[0]
+                            .id
+                            .as_ref()
+                            .map(|t| &t.id)
+                            .expect("id")
+                            .to_raw(),
                     )
                     .await
-                    .map_err(|e| {
-                        tracing::error!("Failed to connect to ACL service: {}", e);
-                        ExtendedError::new(
-                            "Failed to connect to ACL service",
-                            Some(500.to_string()),
-                        )
-                        .build()
-                    })?;
-
-                    let get_user_email_res = acl_grpc_client.get_user_email(request).await;
-
-                    match get_user_email_res {
-                        Ok(email) => {
-                            let payment_info = UserPaymentDetails {
-                                email: email.into_inner().email,
-                                amount: cart.total_amount as u64,
-                                reference: new_order[0]
-                                    .id
-                                    .as_ref()
-                                    .map(|t| &t.id)
-                                    .expect("id")
-                                    .to_raw(),
-                            };
-
-                            let mut request = tonic::Request::new(payment_info);
-
-                            let auth_metadata: AuthMetaData<UserPaymentDetails> = AuthMetaData {
-                                auth_header,
-                                cookie_header,
-                                constructed_grpc_request: Some(&mut request),
-                            };
-
-                            let mut payments_grpc_client =
-                                create_grpc_client::<
-                                    UserPaymentDetails,
-                                    PaymentsServiceClient<Channel>,
-                                >(
-                                    "http://[::1]:50056", true, Some(auth_metadata)
-                                )
-                                .await
-                                .map_err(|e| {
-                                    tracing::error!("Failed to connect to Payments service: {}", e);
-                                    ExtendedError::new(
-                                        "Failed to connect to Payments service",
-                                        Some(400.to_string()),
-                                    )
-                                    .build()
-                                })?;
-
-                            match payments_grpc_client
-                                .initiate_payment_integration(request)
-                                .await
-                            {
-                                Ok(payment_link) => Ok(payment_link.into_inner().authorization_url),
-                                Err(e) => Err(ExtendedError::new(
-                                    format!("Error getting payment link! {:?}", e),
-                                    Some(400.to_string()),
-                                )
-                                .build()),
-                            }
-                        }
-                        Err(e) => Err(ExtendedError::new(
-                            format!("User not found! {:?}", e),
-                            Some(400.to_string()),
-                        )
-                        .build()),
-                    }
-
-                    // Ok(response)
                 }
                 None => Err(ExtendedError::new("Cart is empty!", Some(400.to_string())).build()),
             }
@@ -204,7 +153,7 @@ impl OrderMutation {
         order_id: String,
         status: OrderStatus,
     ) -> Result<String> {
-        let db = ctx.data::<Extension<Arc<Surreal<Client>>>>().unwrap();
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
 
         if let Some(headers) = ctx.data_opt::<HeaderMap>() {
             let _auth_status = check_auth_from_acl(headers).await?;
@@ -216,3 +165,144 @@ impl OrderMutation {
         }
     }
 }
+
+/// Utility function to check that a buyer has accepted the latest version of the documents required at checkout
+pub async fn ensure_latest_terms_accepted(headers: &HeaderMap, external_user_id: &str) -> Result<()> {
+    let mut terms_request = tonic::Request::new(UserId {
+        user_id: external_user_id.to_string(),
+    });
+
+    let auth_metadata: AuthMetaData<UserId> = AuthMetaData {
+        auth_header: headers.get(AUTHORIZATION),
+        cookie_header: headers.get(COOKIE),
+        constructed_grpc_request: Some(&mut terms_request),
+    };
+
+    let mut shared_grpc_client = create_grpc_client::<UserId, SharedServiceClient<GrpcChannel>>(
+        &config::get().endpoints.shared,
+        true,
+        Some(auth_metadata),
+    )
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to connect to Shared service: {}", e);
+        ExtendedError::new("Failed to connect to Shared service", Some(500.to_string())).build()
+    })?;
+
+    let terms_accepted = observe_grpc_call(
+        "shared",
+        "HasAcceptedLatestTerms",
+        shared_grpc_client.has_accepted_latest_terms(terms_request),
+    )
+    .await?
+    .into_inner()
+    .accepted;
+
+    if !terms_accepted {
+        return Err(ExtendedError::new(
+            "Please accept the latest terms before checkout!",
+            Some(403.to_string()),
+        )
+        .build());
+    }
+
+    Ok(())
+}
+
+/// Utility function to initiate a Paystack payment for an order. Returns the payment link.
+pub async fn initiate_order_payment(
+    headers: &HeaderMap,
+    external_user_id: String,
+    amount: u64,
+    order_id: String,
+) -> Result<String> {
+    let auth_header = headers.get(AUTHORIZATION);
+    let cookie_header = headers.get(COOKIE);
+
+    let mut request = tonic::Request::new(GetUserEmailRequest {
+        user_id: external_user_id,
+    });
+
+    let auth_metadata: AuthMetaData<GetUserEmailRequest> = AuthMetaData {
+        auth_header,
+        cookie_header,
+        constructed_grpc_request: Some(&mut request),
+    };
+
+    let mut acl_grpc_client = create_grpc_client::<GetUserEmailRequest, AclClient<GrpcChannel>>(
+        &config::get().endpoints.acl,
+        true,
+        Some(auth_metadata),
+    )
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to connect to ACL service: {}", e);
+        ExtendedError::new("Failed to connect to ACL service", Some(500.to_string())).build()
+    })?;
+
+    let get_user_email_res = observe_grpc_call(
+        "acl",
+        "GetUserEmail",
+        acl_grpc_client.get_user_email(request),
+    )
+    .await;
+
+    match get_user_email_res {
+        Ok(email) => {
+            let payment_info = UserPaymentDetails {
+                email: email.into_inner().email,
+                amount,
+                reference: order_id,
+                locale: headers
+                    .get(ACCEPT_LANGUAGE)
+                    .and_then(|language| language.to_str().ok())
+                    .map(|language| Locale::from_accept_language(language).as_str().to_string())
+                    .unwrap_or_default(),
+            };
+
+            let mut request = tonic::Request::new(payment_info);
+
+            let auth_metadata: AuthMetaData<UserPaymentDetails> = AuthMetaData {
+                auth_header,
+                cookie_header,
+                constructed_grpc_request: Some(&mut request),
+            };
+
+            let mut payments_grpc_client =
+                create_grpc_client::<UserPaymentDetails, PaymentsServiceClient<GrpcChannel>>(
+                    &config::get().endpoints.payments,
+                    true,
+                    Some(auth_metadata),
+                )
+                .await
+                .map_err(|e| {
+                    tracing::error!("Failed to connect to Payments service: {}", e);
+                    ExtendedError::new(
+                        "Failed to connect to Payments service",
+                        Some(400.to_string()),
+                    )
+                    .build()
+                })?;
+
+            match observe_grpc_call(
+                "payments",
+                "InitiatePaymentIntegration",
+                payments_grpc_client.initiate_payment_integration(request),
+            )
+            .await
+            {
+                Ok(payment_link) => Ok(payment_link.into_inner().authorization_url),
+                Err(e) => Err(ExtendedError::new(
+                    format!("Error getting payment link! {:?}", e),
+                    Some(400.to_string()),
+                )
+                .build()),
+            }
+        }
+        Err(e) => Err(ExtendedError::new(
+            format!("User not found! {:?}", e),
+            Some(400.to_string()),
+        )
+        .build()),
+    }
+}
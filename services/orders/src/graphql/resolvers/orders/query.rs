@@ -4,15 +4,19 @@ use async_graphql::{Context, Error, Object, Result};
 use axum::Extension;
 use hyper::HeaderMap;
 use lib::{
+    error::AppError,
     middleware::auth::graphql::check_auth_from_acl,
     utils::{
         custom_error::ExtendedError,
         models::{ArtifactsPurchaseDetails, OrderStatus},
     },
 };
-use surrealdb::{engine::remote::ws::Client, Surreal};
+use surrealdb::{engine::any::Any, Surreal};
 
-use crate::{graphql::schemas::general::CartProduct, utils::orders::get_all_artifacts_for_order};
+use crate::{
+    graphql::schemas::general::{CartProduct, Order},
+    utils::orders::{get_all_artifacts_for_order, get_order_buyer},
+};
 
 #[derive(Default)]
 pub struct OrderQuery;
@@ -24,7 +28,7 @@ impl OrderQuery {
         ctx: &Context<'_>,
         cart_id: String,
     ) -> Result<Vec<CartProduct>> {
-        let db = ctx.data::<Extension<Arc<Surreal<Client>>>>().unwrap();
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
 
         let mut cart_products_query = db
         .query(
@@ -52,7 +56,7 @@ impl OrderQuery {
         ctx: &Context<'_>,
         order_id: String,
     ) -> Result<ArtifactsPurchaseDetails> {
-        let db = ctx.data::<Extension<Arc<Surreal<Client>>>>().unwrap();
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
 
         if let Some(headers) = ctx.data_opt::<HeaderMap>() {
             let _auth_status = check_auth_from_acl(headers).await?;
@@ -69,7 +73,7 @@ impl OrderQuery {
         ctx: &Context<'_>,
         status: OrderStatus,
     ) -> Result<Vec<CartProduct>> {
-        let db = ctx.data::<Extension<Arc<Surreal<Client>>>>().unwrap();
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
 
         if let Some(headers) = ctx.data_opt::<HeaderMap>() {
             let auth_status = check_auth_from_acl(headers).await?;
@@ -98,4 +102,27 @@ impl OrderQuery {
             Err(ExtendedError::new("Cart is empty!", Some(400.to_string())).build())
         }
     }
+
+    /// Federation entity resolver for the orders of the current user
+    #[graphql(entity)]
+    async fn find_order_by_id(&self, ctx: &Context<'_>, id: String) -> Result<Order> {
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
+
+        if let Some(headers) = ctx.data_opt::<HeaderMap>() {
+            let auth_status = check_auth_from_acl(headers).await?;
+
+            if get_order_buyer(db, id.as_str()).await? != auth_status.sub {
+                return Err(AppError::Forbidden("Not the buyer of this order".into()).into());
+            }
+
+            let order: Option<Order> = db
+                .select(("order", id.as_str()))
+                .await
+                .map_err(AppError::database)?;
+
+            Ok(order.ok_or_else(|| AppError::NotFound("Order Not Found".into()))?)
+        } else {
+            Err(ExtendedError::new("Invalid Request!", Some(400.to_string())).build())
+        }
+    }
 }
@@ -0,0 +1,159 @@
+use std::sync::Arc;
+
+use async_graphql::{Context, Result, Subscription};
+use axum::Extension;
+use futures_util::{future, Stream, StreamExt};
+use hyper::HeaderMap;
+use lib::{
+    error::AppError, middleware::auth::graphql::check_auth_from_acl,
+    utils::custom_error::ExtendedError,
+};
+use surrealdb::{engine::any::Any, Action, Surreal};
+
+use crate::{graphql::schemas::general::Order, utils::orders::get_order_buyer};
+
+#[derive(Default)]
+pub struct OrderSubscription;
+
+#[Subscription]
+impl OrderSubscription {
+    /// Emits the order whenever its status changes, e.g. when the payment webhook confirms it.
+    /// Only the buyer can subscribe.
+    async fn order_status_changed(
+        &self,
+        ctx: &Context<'_>,
+        order_id: String,
+    ) -> Result<impl Stream<Item = Order>> {
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
+
+        if let Some(headers) = ctx.data_opt::<HeaderMap>() {
+            let auth_status = check_auth_from_acl(headers).await?;
+
+            if get_order_buyer(db, order_id.as_str()).await? != auth_status.sub {
+                return Err(AppError::Forbidden("Not the buyer of this order".into()).into());
+            }
+
+            // The live query is killed when the client unsubscribes and the stream is dropped
+            let notifications: surrealdb::method::Stream<Option<Order>> = db
+                .select(("order", order_id.as_str()))
+                .live()
+                .await
+                .map_err(AppError::database)?;
+
+            let mut last_status = None;
+
+            Ok(notifications.filter_map(move |notification| {
+                let changed = match notification {
+                    Ok(notification) if matches!(notification.action, Action::Update) => {
+                        let order = notification.data;
+
+                        if last_status == Some(order.status) {
+                            None
+                        } else {
+                            last_status = Some(order.status);
+                            Some(order)
+                        }
+                    }
+                    Ok(_) => None,
+                    Err(e) => {
+                        tracing::error!("Order live query failed: {}", e);
+                        None
+                    }
+                };
+
+                future::ready(changed)
+            }))
+        } else {
+            Err(ExtendedError::new("Invalid Request!", Some(400.to_string())).build())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{database::migrations::MIGRATIONS, graphql::resolvers::query::EmptyQuery};
+    use async_graphql::{EmptyMutation, Request, Schema};
+    use hyper::header::{AUTHORIZATION, COOKIE};
+    use lib::{database::memory::connect_in_memory, test_support::FakeServices};
+    use serde_json::json;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    const ORDER: &str = "
+        CREATE user_id:buyer SET user_id = 'buyer-1';
+        CREATE cart:cart SET owner = user_id:buyer, session_id = 'session-1', total_amount = 100;
+        RELATE user_id:buyer->order:order->cart:cart CONTENT { status: 'Pending' };
+    ";
+
+    fn user_headers() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, "Bearer user-token".parse().unwrap());
+        headers.insert(COOKIE, "session_id=session-1".parse().unwrap());
+        headers
+    }
+
+    fn subscribe(
+        schema: &Schema<EmptyQuery, EmptyMutation, OrderSubscription>,
+        db: Arc<Surreal<Any>>,
+    ) -> impl Stream<Item = async_graphql::Response> {
+        schema.execute_stream(
+            Request::new(r#"subscription { orderStatusChanged(orderId: "order") { id status } }"#)
+                .data(Extension(db))
+                .data(user_headers()),
+        )
+    }
+
+    #[tokio::test]
+    async fn buyers_get_the_status_changes_of_their_order() {
+        let fake_services = FakeServices::start().await.unwrap();
+        fake_services.acl.authenticate_as("buyer-1");
+        let db = connect_in_memory(MIGRATIONS).await.unwrap();
+        db.query(ORDER).await.unwrap().check().unwrap();
+
+        let schema = Schema::new(EmptyQuery, EmptyMutation, OrderSubscription);
+        let mut orders = Box::pin(subscribe(&schema, db.clone()));
+        // Polling starts the live query, nothing changed yet
+        assert!(timeout(Duration::from_millis(500), orders.next())
+            .await
+            .is_err());
+
+        db.query("UPDATE order:order SET status = 'Confirmed'")
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+        let response = timeout(Duration::from_secs(5), orders.next())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        assert_eq!(
+            response.data.into_json().unwrap(),
+            json!({ "orderStatusChanged": { "id": "order", "status": "Confirmed" } })
+        );
+
+        // Updates that keep the status aren't sent
+        db.query("UPDATE order:order SET status = 'Confirmed'")
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+        assert!(timeout(Duration::from_millis(500), orders.next())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn only_the_buyer_can_subscribe() {
+        let fake_services = FakeServices::start().await.unwrap();
+        fake_services.acl.authenticate_as("other-1");
+        let db = connect_in_memory(MIGRATIONS).await.unwrap();
+        db.query(ORDER).await.unwrap().check().unwrap();
+
+        let schema = Schema::new(EmptyQuery, EmptyMutation, OrderSubscription);
+        let response = Box::pin(subscribe(&schema, db)).next().await.unwrap();
+
+        assert_eq!(response.errors[0].message, "Not the buyer of this order");
+    }
+}
@@ -0,0 +1,6 @@
+use async_graphql::MergedSubscription;
+
+use super::{cart::subscription::CartSubscription, orders::subscription::OrderSubscription};
+
+#[derive(MergedSubscription, Default)]
+pub struct Subscription(CartSubscription, OrderSubscription);
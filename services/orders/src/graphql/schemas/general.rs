@@ -1,5 +1,5 @@
 use async_graphql::{ComplexObject, Enum, InputObject, SimpleObject};
-use lib::utils::models::OrderStatus;
+use lib::utils::models::{OrderStatus, ProductReference};
 use serde::{Deserialize, Serialize};
 use surrealdb::sql::Thing;
 
@@ -64,6 +64,13 @@ impl CartProduct {
             .expect("license")
             .to_raw()
     }
+
+    /// The product from the products service, resolved by the federation gateway
+    async fn product(&self) -> ProductReference {
+        ProductReference {
+            id: self.ext_product_id.clone(),
+        }
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, Enum, Eq, Copy, PartialEq)]
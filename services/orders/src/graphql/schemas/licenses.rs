@@ -0,0 +1,45 @@
+use async_graphql::{ComplexObject, SimpleObject};
+use serde::{Deserialize, Serialize};
+use surrealdb::sql::Thing;
+
+/// The signed part of a license certificate. Field order matters because the serialized form is what gets signed.
+#[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
+pub struct LicenseCertificatePayload {
+    pub certificate_id: String,
+    pub order_id: String,
+    pub product_id: String,
+    pub license_id: String,
+    pub buyer_id: String,
+    pub issued_at: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
+#[graphql(complex)]
+pub struct LicenseCertificate {
+    #[graphql(skip)]
+    pub id: Option<Thing>,
+    #[graphql(skip)]
+    pub order: Option<Thing>,
+    pub product_id: String,
+    pub license_id: String,
+    pub buyer_id: String,
+    pub issued_at: String,
+    pub license_key: String,
+}
+
+#[ComplexObject]
+impl LicenseCertificate {
+    async fn id(&self) -> String {
+        self.id.as_ref().map(|t| &t.id).expect("id").to_raw()
+    }
+
+    async fn order_id(&self) -> String {
+        self.order.as_ref().map(|t| &t.id).expect("order").to_raw()
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
+pub struct LicenseVerification {
+    pub valid: bool,
+    pub certificate: Option<LicenseCertificatePayload>,
+}
@@ -1 +1,2 @@
 pub mod general;
+pub mod licenses;
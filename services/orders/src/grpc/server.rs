@@ -4,7 +4,7 @@ use orders_service::{
     orders_service_server::OrdersService, ArtifactsPurchaseDetails, GetAllArtifactsForOrderPayload,
     UpdateOrderPayload, UpdateOrderResponse,
 };
-use surrealdb::{engine::remote::ws::Client, Surreal};
+use surrealdb::{engine::any::Any, Surreal};
 use tonic::{Request, Response, Status};
 
 use crate::utils;
@@ -14,11 +14,11 @@ pub mod orders_service {
 }
 
 pub struct OrdersServiceImplementation {
-    db: Arc<Surreal<Client>>,
+    db: Arc<Surreal<Any>>,
 }
 
 impl OrdersServiceImplementation {
-    pub fn new(db: Arc<Surreal<Client>>) -> Self {
+    pub fn new(db: Arc<Surreal<Any>>) -> Self {
         Self { db }
     }
 }
@@ -29,8 +29,6 @@ impl OrdersService for OrdersServiceImplementation {
         &self,
         request: Request<UpdateOrderPayload>,
     ) -> Result<Response<UpdateOrderResponse>, Status> {
-        let req_clone = request.extensions().clone();
-        let current_user = req_clone.get::<String>().unwrap();
         let payload = request.into_inner();
         let status = payload
             .status
@@ -43,7 +41,7 @@ impl OrdersService for OrdersServiceImplementation {
             Ok(status_str) => Ok(Response::new(UpdateOrderResponse { status_str })),
             Err(e) => {
                 tracing::error!("Error updating order: {:?}", e);
-                Err(Status::internal("Failed"))
+                Err(e.into())
             }
         }
     }
@@ -64,8 +62,118 @@ impl OrdersService for OrdersServiceImplementation {
             })),
             Err(e) => {
                 tracing::error!("Error getting order artifacts: {:?}", e);
-                Err(Status::internal("Failed"))
+                Err(e.into())
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{database::migrations::MIGRATIONS, utils::licenses::use_test_signing_key};
+    use lib::{
+        database::memory::connect_in_memory,
+        integration::grpc::clients::orders_service::{
+            orders_service_client::OrdersServiceClient,
+            GetAllArtifactsForOrderPayload as ClientArtifactsPayload,
+            OrderStatus as ClientOrderStatus, UpdateOrderPayload as ClientUpdateOrderPayload,
+        },
+        middleware::auth::grpc::AuthMiddleware,
+        test_support::FakeServices,
+        utils::grpc::{create_grpc_client, GrpcChannel},
+    };
+    use orders_service::orders_service_server::OrdersServiceServer;
+    use tonic::{
+        transport::{Endpoint, Server},
+        Code,
+    };
+    use tonic_middleware::MiddlewareLayer;
+
+    /// Mirrors what the payments webhook does once a charge succeeds. The clients sign the calls
+    /// with the service identity, so ACL is only asked about the user.
+    #[tokio::test]
+    async fn only_services_confirm_orders() {
+        use_test_signing_key();
+        let mut fake_services = FakeServices::start().await.unwrap();
+        let db = connect_in_memory(MIGRATIONS).await.unwrap();
+        db.query(
+            "
+            CREATE user_id:buyer SET user_id = 'buyer-1';
+            CREATE product_id:product SET product_id = 'product-1';
+            CREATE license_id:license SET license_id = 'license-1';
+            CREATE cart:cart SET owner = user_id:buyer, session_id = 'session-1', total_amount = 100;
+            RELATE cart:cart->cart_product->product_id:product CONTENT {
+                quantity: 1,
+                license: license_id:license,
+                artifact: 'artifact-1'
+            };
+            RELATE user_id:buyer->order:order->cart:cart CONTENT { status: 'Pending' };
+            ",
+        )
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+
+        let incoming = fake_services.serve_endpoint("orders").await.unwrap();
+        let orders_endpoint = fake_services.config().endpoints.orders.clone();
+        tokio::spawn(
+            Server::builder()
+                .layer(MiddlewareLayer::new(AuthMiddleware))
+                .add_service(OrdersServiceServer::new(OrdersServiceImplementation::new(
+                    db.clone(),
+                )))
+                .serve_with_incoming_shutdown(incoming, fake_services.shutdown_signal()),
+        );
+
+        let mut orders_grpc_client = create_grpc_client::<
+            ClientUpdateOrderPayload,
+            OrdersServiceClient<GrpcChannel>,
+        >(&orders_endpoint, false, None)
+        .await
+        .unwrap();
+
+        let update_order_response = orders_grpc_client
+            .update_order(ClientUpdateOrderPayload {
+                order_id: "order".to_string(),
+                status: ClientOrderStatus::Confirmed.into(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(update_order_response.status_str, "Confirmed");
+
+        let purchase_details = orders_grpc_client
+            .get_all_artifacts_for_order(ClientArtifactsPayload {
+                order_id: "order".to_string(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(purchase_details.buyer_id, "buyer-1");
+        assert_eq!(purchase_details.artifacts, vec!["artifact-1".to_string()]);
+
+        assert_eq!(fake_services.acl.check_auth.call_count(), 0);
+
+        // Signed in users can't confirm their own orders
+        let mut user_client = OrdersServiceClient::new(
+            Endpoint::from_shared(orders_endpoint)
+                .unwrap()
+                .connect_lazy(),
+        );
+        let mut request = tonic::Request::new(ClientUpdateOrderPayload {
+            order_id: "order".to_string(),
+            status: ClientOrderStatus::Confirmed.into(),
+        });
+        request
+            .metadata_mut()
+            .insert("authorization", "Bearer user-token".parse().unwrap());
+        request
+            .metadata_mut()
+            .insert("cookie", "oauth_client=;t=user-token".parse().unwrap());
+        let status = user_client.update_order(request).await.unwrap_err();
+        assert_eq!(status.code(), Code::PermissionDenied);
+        assert_eq!(fake_services.acl.check_auth.call_count(), 1);
+    }
+}
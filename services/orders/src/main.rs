@@ -4,18 +4,21 @@ mod grpc;
 mod rest;
 mod utils;
 
-use std::{env, net::SocketAddr, sync::Arc};
+use std::{net::SocketAddr, sync::Arc};
 
-use async_graphql::{EmptySubscription, Schema};
-use async_graphql_axum::{GraphQLRequest, GraphQLResponse};
+use async_graphql::{http::ALL_WEBSOCKET_PROTOCOLS, Data, Schema};
+use async_graphql_axum::{GraphQLProtocol, GraphQLRequest, GraphQLResponse, GraphQLWebSocket};
 use axum::{
-    extract::Extension,
-    http::{HeaderMap, HeaderValue},
-    routing::post,
+    extract::{ws::WebSocketUpgrade, Extension},
+    http::HeaderMap,
+    middleware,
+    response::Response,
+    routing::{get, post},
     serve, Router,
 };
 
-use graphql::resolvers::query::Query;
+use database::migrations::MIGRATIONS;
+use graphql::{connection_init_headers, resolvers::query::Query};
 use grpc::server::{
     orders_service::orders_service_server::OrdersServiceServer, OrdersServiceImplementation,
 };
@@ -28,27 +31,45 @@ use hyper::{
     Method,
 };
 
-use lib::middleware::auth::grpc::AuthMiddleware;
+use dotenvy::dotenv;
+use lib::{
+    config::{self, AppConfig},
+    database::{connection::connect_and_migrate, migrations::MigrationMode},
+    events::{
+        dispatcher::Dispatcher,
+        transport::{events_router, EventBus, HttpTransport},
+    },
+    health::{grpc_health_service, health_router, HealthState},
+    logging::{self, request_id, REQUEST_ID_HEADER},
+    middleware::{
+        auth::grpc::AuthMiddleware,
+        rate_limit::{rate_limit_graphql, RateLimiter},
+    },
+    observability::{serve_metrics, track_http, MetricsMiddleware},
+    schema::configure_schema,
+    telemetry::{self, trace_http},
+    utils::grpc::grpc_server,
+};
 // use serde::Deserialize;
-use surrealdb::{engine::remote::ws::Client, Result, Surreal};
-use tonic::transport::Server;
+use surrealdb::{engine::any::Any, Surreal};
 use tonic_middleware::MiddlewareLayer;
 use tower_http::cors::CorsLayer;
 
-use graphql::resolvers::mutation::Mutation;
-use tracing_subscriber::fmt::writer::MakeWriterExt;
+use graphql::resolvers::{mutation::Mutation, subscription::Subscription};
 
-type MySchema = Schema<Query, Mutation, EmptySubscription>;
+type MySchema = Schema<Query, Mutation, Subscription>;
 
 /// Main GraphQL handler, all requests pass through here.
 async fn graphql_handler(
     schema: Extension<MySchema>,
-    db: Extension<Arc<Surreal<Client>>>,
+    db: Extension<Arc<Surreal<Any>>>,
+    config: Extension<Arc<AppConfig>>,
     headers: HeaderMap,
     req: GraphQLRequest,
 ) -> GraphQLResponse {
     let mut request = req.0;
     request = request.data(db.clone());
+    request = request.data(config.clone());
     request = request.data(headers.clone());
     let operation_name = request.operation_name.clone();
 
@@ -62,9 +83,14 @@ async fn graphql_handler(
     let duration = start.elapsed();
     tracing::info!("{:?} request processed in {:?}", operation_name, duration);
 
-    // Debug the response
-    if response.errors.len() > 0 {
-        tracing::debug!("GraphQL Error: {:?}", response.errors);
+    // Debug the response. Only the messages, the full errors can carry user data
+    if !response.errors.is_empty() {
+        let messages: Vec<&str> = response
+            .errors
+            .iter()
+            .map(|error| error.message.as_str())
+            .collect();
+        tracing::debug!("GraphQL Error: {:?}", messages);
     } else {
         tracing::info!("GraphQL request completed without errors");
     }
@@ -73,56 +99,92 @@ async fn graphql_handler(
     response.into()
 }
 
+/// GraphQL subscriptions over WebSocket. Browsers can't set headers on the upgrade request, so an
+/// `Authorization` value in the `connection_init` payload is accepted too, see
+/// `connection_init_headers`. Cookies are sent as usual, either one authenticates the client.
+async fn graphql_ws_handler(
+    Extension(schema): Extension<MySchema>,
+    db: Extension<Arc<Surreal<Any>>>,
+    config: Extension<Arc<AppConfig>>,
+    headers: HeaderMap,
+    protocol: GraphQLProtocol,
+    websocket: WebSocketUpgrade,
+) -> Response {
+    websocket
+        .protocols(ALL_WEBSOCKET_PROTOCOLS)
+        .on_upgrade(move |stream| {
+            GraphQLWebSocket::new(stream, schema, protocol)
+                .on_connection_init(move |payload| async move {
+                    let mut data = Data::default();
+                    data.insert(db);
+                    data.insert(config);
+                    data.insert(connection_init_headers(headers, &payload));
+
+                    Ok(data)
+                })
+                .serve()
+        })
+}
+
 #[tokio::main]
-async fn main() -> Result<()> {
-    let db = Arc::new(database::connection::create_db_connection().await.unwrap());
-
-    // Bring in some needed env vars
-    let deployment_env = env::var("ENVIRONMENT").unwrap_or_else(|_| "prod".to_string()); // default to production because it's the most secure
-    let allowed_services_cors = env::var("ALLOWED_SERVICES_CORS")
-        .expect("Missing the ALLOWED_SERVICES environment variable.");
-    let orders_http_port =
-        env::var("ORDERS_HTTP_PORT").expect("Missing the ORDERS_HTTP_PORT environment variable.");
-    let orders_grpc_port =
-        env::var("ORDERS_GRPC_PORT").expect("Missing the ORDERS_GRPC_PORT environment variable.");
-
-    let mut schema_builder =
-        Schema::build(Query::default(), Mutation::default(), EmptySubscription);
-
-    // Disable introspection & limit query depth in production
-    schema_builder = match deployment_env.as_str() {
-        "prod" => schema_builder.disable_introspection().limit_depth(5),
-        _ => schema_builder,
-    };
-
-    let schema = schema_builder.finish();
-
-    let origins: Vec<HeaderValue> = allowed_services_cors
-        .as_str()
-        .split(",")
-        .into_iter()
-        .map(|endpoint| endpoint.parse::<HeaderValue>().unwrap())
-        .collect();
+async fn main() {
+    dotenv().ok();
+    let config = config::init("orders").expect("Invalid configuration");
+    // JSON logs to stdout and ./logs, traces to the OTLP collector if configured
+    let _guard = logging::init_logging(&config);
 
-    // Persist the server logs to a file on a daily basis using "tracing_subscriber"
-    let file_appender = tracing_appender::rolling::daily("./logs", "orders.log");
-    let (non_blocking, _guard) = tracing_appender::non_blocking(file_appender);
+    let db = connect_and_migrate(&config.database, MIGRATIONS)
+        .await
+        .expect("Failed to connect to the database");
 
-    let stdout = std::io::stdout.with_max_level(tracing::Level::DEBUG); // Log to console at DEBUG level
+    // The dry-run and status modes only report the migrations
+    if config.database.migrations_mode != MigrationMode::Apply {
+        telemetry::shutdown_tracing();
+        return;
+    }
+
+    let schema_builder = Schema::build(
+        Query::default(),
+        Mutation::default(),
+        Subscription::default(),
+    )
+    .enable_federation();
+
+    // Complexity budget, timeouts & persisted queries, plus the production-only limits
+    let schema = configure_schema(schema_builder, &config).finish();
+
+    let health_state = HealthState::new(&config, db.clone());
+    // Per-client limits of the expensive operations, see the config's rate_limit section
+    let rate_limiter = RateLimiter::new(&config, db.clone());
 
-    tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::DEBUG)
-        .with_writer(stdout.and(non_blocking))
-        .init();
+    // The service's events, delivered to its own subscribers and to the subscribed services
+    let event_bus = EventBus::default();
+    Dispatcher::new(db.clone(), &config.events)
+        .with_transport(event_bus.clone())
+        .with_transport(HttpTransport::new(&config))
+        .spawn();
 
     let app = Router::new()
-        .route("/", post(graphql_handler))
+        .route(
+            "/",
+            post(graphql_handler).layer(middleware::from_fn_with_state(
+                rate_limiter,
+                rate_limit_graphql,
+            )),
+        )
+        .route("/ws", get(graphql_ws_handler))
+        .merge(health_router(health_state.clone()))
+        .merge(events_router(event_bus))
         // .route("/oauth/callback", get(oauth_handler))
+        .layer(middleware::from_fn(track_http))
+        .layer(middleware::from_fn(trace_http))
+        .layer(middleware::from_fn(request_id))
         .layer(Extension(schema))
         .layer(Extension(db.clone()))
+        .layer(Extension(config.clone()))
         .layer(
             CorsLayer::new()
-                .allow_origin(origins)
+                .allow_origin(config.cors_allowed_origins.clone())
                 .allow_headers([
                     AUTHORIZATION,
                     ACCEPT,
@@ -135,34 +197,45 @@ async fn main() -> Result<()> {
                     ACCESS_CONTROL_ALLOW_ORIGIN,
                     ACCESS_CONTROL_ALLOW_METHODS,
                     ACCESS_CONTROL_EXPOSE_HEADERS,
+                    REQUEST_ID_HEADER,
                 ])
+                .expose_headers([REQUEST_ID_HEADER])
                 .allow_credentials(true)
                 .allow_methods(vec![Method::GET, Method::POST]),
         );
 
     // Set up the gRPC server
     let orders_grpc = OrdersServiceImplementation::new(db.clone());
-    let grpc_address: SocketAddr = format!("[::1]:{}", orders_grpc_port)
+    let grpc_address: SocketAddr = format!("[::1]:{}", config.grpc_port)
         .as_str()
         .parse()
         .unwrap();
-    let tonic_auth_middleware = AuthMiddleware::default();
+    let tonic_auth_middleware = AuthMiddleware;
+    let grpc_builder = grpc_server(&config).expect("Invalid gRPC TLS configuration");
 
     tokio::spawn(async move {
         // let the thread panic if gRPC server fails to start
-        Server::builder()
+        grpc_builder
+            .layer(MiddlewareLayer::new(MetricsMiddleware))
             .layer(MiddlewareLayer::new(tonic_auth_middleware))
             .add_service(OrdersServiceServer::new(orders_grpc))
+            .add_service(grpc_health_service::<
+                OrdersServiceServer<OrdersServiceImplementation>,
+            >(health_state))
             .serve(grpc_address)
             .await
             .unwrap();
     });
 
+    // Prometheus scrapes /metrics on its own port, off the public app
+    if let Some(metrics_port) = config.metrics_port {
+        tokio::spawn(serve_metrics(metrics_port));
+    }
+
     // let listener = tokio::net::TcpListener::bind("0.0.0.0:3010").await.unwrap();
-    let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", orders_http_port))
+    let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", config.http_port))
         .await
         .unwrap();
     serve(listener, app).await.unwrap();
-
-    Ok(())
+    telemetry::shutdown_tracing();
 }
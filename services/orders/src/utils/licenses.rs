@@ -0,0 +1,212 @@
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use chrono::{SecondsFormat, Utc};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier};
+use lib::{error::AppError, utils::custom_traits::AsSurrealClient};
+use serde::Deserialize;
+use std::env;
+use surrealdb::sql::Thing;
+use uuid::Uuid;
+
+use crate::graphql::schemas::licenses::{LicenseCertificate, LicenseCertificatePayload};
+
+#[derive(Debug, Deserialize)]
+struct UncertifiedCartProduct {
+    id: Thing,
+    product_id: String,
+    license_id: String,
+    buyer_id: String,
+}
+
+/// Load the Ed25519 signing key. LICENSE_SIGNING_KEY is the base64 encoded 32 byte secret key.
+fn get_signing_key() -> Result<SigningKey, AppError> {
+    let encoded_key = env::var("LICENSE_SIGNING_KEY").map_err(|_| {
+        tracing::error!("Missing the LICENSE_SIGNING_KEY environment variable.");
+        AppError::Internal("Missing license signing key".into())
+    })?;
+
+    let secret_key: [u8; 32] = STANDARD
+        .decode(encoded_key.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| {
+            tracing::error!("LICENSE_SIGNING_KEY is not a base64 encoded 32 byte key");
+            AppError::Internal("Invalid license signing key".into())
+        })?;
+
+    Ok(SigningKey::from_bytes(&secret_key))
+}
+
+/// Utility function to get the base64 encoded public key that verifies license keys
+pub fn get_license_public_key() -> Result<String, AppError> {
+    let verifying_key = get_signing_key()?.verifying_key();
+
+    Ok(STANDARD.encode(verifying_key.to_bytes()))
+}
+
+/// Sign a certificate payload. The license key is `base64url(payload).base64url(signature)`
+fn sign_license_certificate(payload: &LicenseCertificatePayload) -> Result<String, AppError> {
+    let serialized_payload =
+        serde_json::to_vec(payload).map_err(|e| AppError::internal("Serialization Failed", e))?;
+
+    let signature = get_signing_key()?.sign(&serialized_payload);
+
+    Ok(format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(&serialized_payload),
+        URL_SAFE_NO_PAD.encode(signature.to_bytes())
+    ))
+}
+
+/// Utility function to decode a license key. Returns the payload only if the signature is valid.
+pub fn decode_license_key(license_key: &str) -> Option<LicenseCertificatePayload> {
+    let (encoded_payload, encoded_signature) = license_key.trim().split_once('.')?;
+
+    let payload = URL_SAFE_NO_PAD.decode(encoded_payload).ok()?;
+    let signature: [u8; 64] = URL_SAFE_NO_PAD
+        .decode(encoded_signature)
+        .ok()?
+        .try_into()
+        .ok()?;
+
+    get_signing_key()
+        .ok()?
+        .verifying_key()
+        .verify(&payload, &Signature::from_bytes(&signature))
+        .ok()?;
+
+    serde_json::from_slice(&payload).ok()
+}
+
+/// Utility function to issue a signed license certificate for every cart_product of a confirmed order.
+/// Cart products that already have a certificate are skipped, so it is safe to call more than once.
+pub async fn issue_license_certificates<T: Clone + AsSurrealClient>(
+    db: &T,
+    order_id: &str,
+) -> Result<Vec<LicenseCertificate>, AppError> {
+    let mut uncertified_query = db
+        .as_client()
+        .query(
+            "
+            BEGIN TRANSACTION;
+            LET $order = type::thing($order_id);
+            LET $buyer = (SELECT VALUE in.user_id FROM ONLY $order);
+            LET $certified = (SELECT VALUE cart_product FROM license_certificate WHERE order = $order);
+            LET $uncertified = (
+                SELECT id, (->product_id.product_id)[0] AS product_id, license.license_id AS license_id, $buyer AS buyer_id
+                FROM cart_product
+                WHERE <-cart<-(order WHERE id = $order) AND id NOTINSIDE $certified
+            );
+            RETURN $uncertified;
+            COMMIT TRANSACTION;
+            ",
+        )
+        .bind(("order_id", format!("order:{}", order_id)))
+        .await
+        .map_err(AppError::database)?;
+
+    let uncertified: Vec<UncertifiedCartProduct> = uncertified_query
+        .take(0)
+        .map_err(AppError::deserialization)?;
+
+    for cart_product in uncertified {
+        let payload = LicenseCertificatePayload {
+            certificate_id: Uuid::new_v4().to_string(),
+            order_id: order_id.to_string(),
+            product_id: cart_product.product_id,
+            license_id: cart_product.license_id,
+            buyer_id: cart_product.buyer_id,
+            issued_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        };
+
+        let license_key = sign_license_certificate(&payload)?;
+
+        db.as_client()
+            .query(
+                "
+                CREATE type::thing('license_certificate', $certificate_id) CONTENT {
+                    order: type::thing($order_id),
+                    cart_product: $cart_product,
+                    product_id: $product_id,
+                    license_id: $license_id,
+                    buyer_id: $buyer_id,
+                    issued_at: $issued_at,
+                    license_key: $license_key
+                }
+                ",
+            )
+            .bind(("certificate_id", payload.certificate_id))
+            .bind(("order_id", format!("order:{}", order_id)))
+            .bind(("cart_product", cart_product.id))
+            .bind(("product_id", payload.product_id))
+            .bind(("license_id", payload.license_id))
+            .bind(("buyer_id", payload.buyer_id))
+            .bind(("issued_at", payload.issued_at))
+            .bind(("license_key", license_key))
+            .await
+            .map_err(AppError::database)?
+            .check()
+            .map_err(|e| AppError::internal("Failed to create license certificate", e))?;
+    }
+
+    get_license_certificates(db, None, Some(order_id)).await
+}
+
+/// Utility function to get license certificates, optionally filtered by buyer and/or order
+pub async fn get_license_certificates<T: Clone + AsSurrealClient>(
+    db: &T,
+    buyer_id: Option<&str>,
+    order_id: Option<&str>,
+) -> Result<Vec<LicenseCertificate>, AppError> {
+    let mut certificates_query = db
+        .as_client()
+        .query(
+            "
+            SELECT * FROM license_certificate
+            WHERE ($buyer_id IS NONE OR buyer_id = $buyer_id)
+            AND ($order_id IS NONE OR order = type::thing($order_id))
+            ORDER BY issued_at DESC
+            ",
+        )
+        .bind(("buyer_id", buyer_id.map(|id| id.to_string())))
+        .bind(("order_id", order_id.map(|id| format!("order:{}", id))))
+        .await
+        .map_err(AppError::database)?;
+
+    let certificates: Vec<LicenseCertificate> = certificates_query
+        .take(0)
+        .map_err(AppError::deserialization)?;
+
+    Ok(certificates)
+}
+
+/// Utility function to get the stored certificate that was issued with the given license key
+pub async fn get_license_certificate_by_key<T: Clone + AsSurrealClient>(
+    db: &T,
+    license_key: &str,
+) -> Result<Option<LicenseCertificate>, AppError> {
+    let mut certificate_query = db
+        .as_client()
+        .query(
+            "
+            SELECT * FROM ONLY license_certificate WHERE license_key = $license_key LIMIT 1
+            ",
+        )
+        .bind(("license_key", license_key.trim().to_string()))
+        .await
+        .map_err(AppError::database)?;
+
+    let certificate: Option<LicenseCertificate> = certificate_query
+        .take(0)
+        .map_err(AppError::deserialization)?;
+
+    Ok(certificate)
+}
+
+/// Sign the certificates of the tests with a throwaway key
+#[cfg(test)]
+pub fn use_test_signing_key() {
+    env::set_var("LICENSE_SIGNING_KEY", STANDARD.encode([7u8; 32]));
+}
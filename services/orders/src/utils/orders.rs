@@ -1,19 +1,25 @@
 use lib::{
+    config,
+    error::AppError,
+    events::{
+        outbox::{OutboxEntry, RECORD_EVENT},
+        DomainEvent,
+    },
     integration::foreign_key::add_foreign_key_if_not_exists,
+    observability::record_order_status,
     utils::{
         custom_traits::AsSurrealClient,
         models::{ArtifactsPurchaseDetails, ForeignKey, OrderStatus, User},
     },
 };
-use std::io::{Error, ErrorKind};
 
-use crate::graphql::schemas::general::Order;
+use crate::{graphql::schemas::general::Order, utils::licenses::issue_license_certificates};
 
 pub async fn update_order<T: Clone + AsSurrealClient>(
     db: &T,
     order_id: &str,
     status: OrderStatus,
-) -> Result<String, Error> {
+) -> Result<String, AppError> {
     // TODO: The logic here is wrong, the buyer may not be the same as the current user. The buyer has to the owner of the order. Therefore I need to get the buyer from the order details.
     let mut get_order_owner_query = db
         .as_client()
@@ -28,22 +34,19 @@ pub async fn update_order<T: Clone + AsSurrealClient>(
         )
         .bind(("order_id", format!("order:{}", order_id)))
         .await
-        .map_err(|e| {
-            tracing::error!("DB Query Failed: {}", e);
-            Error::new(ErrorKind::Other, "DB Query Failed")
-        })?;
-
-    let order_owner: Option<User> = get_order_owner_query.take(0).map_err(|e| {
-        tracing::error!("Deserialization Failed: {}", e);
-        Error::new(ErrorKind::Other, "Deserialization Failed")
-    })?;
+        .map_err(AppError::database)?;
+
+    let order_owner: Option<User> = get_order_owner_query
+        .take(0)
+        .map_err(AppError::deserialization)?;
 
     match order_owner {
         Some(order_owner) => {
+            let buyer_id = order_owner.user_id.clone();
             let user_fk = ForeignKey {
                 table: "user_id".into(),
                 column: "user_id".into(),
-                foreign_key: order_owner.user_id.into(),
+                foreign_key: order_owner.user_id,
             };
 
             let buyer_result: Option<User> = add_foreign_key_if_not_exists(db, user_fk).await;
@@ -74,29 +77,46 @@ pub async fn update_order<T: Clone + AsSurrealClient>(
                 .bind(("user_id", format!("user_id:{}", internal_user_id)))
                 .bind(("order_id", format!("order:{}", order_id)))
                 .await
-                .map_err(|e| {
-                    tracing::error!("DB Query Failed: {}", e);
-                    Error::new(ErrorKind::Other, "DB Query Failed")
-                })?;
+                .map_err(AppError::database)?;
 
-            let existing_order: Option<Order> = existing_order_query.take(0).map_err(|e| {
-                tracing::error!("Deserialization Failed: {}", e);
-                Error::new(ErrorKind::Other, "Deserialization Failed")
-            })?;
+            let existing_order: Option<Order> = existing_order_query
+                .take(0)
+                .map_err(AppError::deserialization)?;
 
             match existing_order {
                 Some(order) => {
+                    // Issued before the order is confirmed: a failure leaves it pending, so the payment
+                    // provider retries its webhook and the licenses with it. Issuing is idempotent.
+                    if status == OrderStatus::Confirmed {
+                        issue_license_certificates(db, order_id).await?;
+                    }
+
+                    // Only once, the payment provider may retry its webhook
+                    let is_confirmation =
+                        status == OrderStatus::Confirmed && order.status != OrderStatus::Confirmed;
+                    let order_confirmed = is_confirmation.then(|| {
+                        OutboxEntry::new(
+                            &config::get().service,
+                            DomainEvent::OrderConfirmed {
+                                order_id: order_id.to_string(),
+                                buyer_id,
+                            },
+                        )
+                    });
+
                     let mut update_order_transaction = db
                         .as_client()
-                        .query(
+                        .query(format!(
                             "
                         BEGIN TRANSACTION;
                         LET $order = type::thing($order_id);
                         LET $new_order = UPDATE ONLY $order SET status = $new_status;
+                        IF $event {{ {} }};
                         RETURN $new_order;
                         COMMIT TRANSACTION;
                         ",
-                        )
+                            RECORD_EVENT
+                        ))
                         .bind((
                             "order_id",
                             format!(
@@ -105,60 +125,50 @@ pub async fn update_order<T: Clone + AsSurrealClient>(
                             ),
                         ))
                         .bind(("new_status", status))
+                        .bind(("event", order_confirmed))
                         .await
-                        .map_err(|e| {
-                            tracing::error!("DB Query Failed: {}", e);
-                            Error::new(ErrorKind::Other, "DB Query Failed")
-                        })?;
-
-                    let response: Option<Order> =
-                        update_order_transaction.take(0).map_err(|e| {
-                            tracing::error!("Deserialization Failed: {}", e);
-                            Error::new(ErrorKind::Other, "Deserialization Failed")
-                        })?;
-
-                    match status {
-                        OrderStatus::Confirmed => {
-                            let mut _update_order_transaction = db
-                                .as_client()
-                            .query(
-                                "
-                                LET $order = type::thing($order_id);
-                                LET $active_cart = (SELECT VALUE ->(cart WHERE archived=false) FROM ONLY $order LIMIT 1)[0];
-                                LET $updated = (UPDATE ONLY $active_cart SET archived=true);
-
-                                RETURN $updated;
-                                "
-                            )
-                            .bind(("order_id", format!("order:{}", order.id.as_ref().map(|t| &t.id).expect("id").to_raw())))
-                            .await
-                            .map_err(|e| {
-                                tracing::error!("DB Query Failed: {}", e);
-                                Error::new(ErrorKind::Other, "DB Query Failed")
-                            })?;
-                        }
-                        _ => {}
+                        .map_err(AppError::database)?;
+
+                    let response: Option<Order> = update_order_transaction
+                        .take(0)
+                        .map_err(AppError::deserialization)?;
+
+                    if status == OrderStatus::Confirmed {
+                        let mut _update_order_transaction = db
+                            .as_client()
+                        .query(
+                            "
+                            LET $order = type::thing($order_id);
+                            LET $active_cart = (SELECT VALUE ->(cart WHERE archived=false) FROM ONLY $order LIMIT 1)[0];
+                            LET $updated = (UPDATE ONLY $active_cart SET archived=true);
+
+                            RETURN $updated;
+                            "
+                        )
+                        .bind(("order_id", format!("order:{}", order.id.as_ref().map(|t| &t.id).expect("id").to_raw())))
+                        .await
+                        .map_err(AppError::database)?;
                     }
 
                     match response {
-                        Some(updated_order) => Ok(format!("{:?}", updated_order.status)),
+                        Some(updated_order) => {
+                            record_order_status(&status);
+                            Ok(format!("{:?}", updated_order.status))
+                        }
                         None => {
                             // Err(ExtendedError::new("Cart is empty!", Some(400.to_string())).build())
-                            Err(Error::new(
-                                ErrorKind::NotFound,
-                                "Couldn't update the order!",
-                            ))
+                            Err(AppError::NotFound("Couldn't update the order!".into()))
                         }
                     }
 
                     // Ok(response)
                 }
-                None => Err(Error::new(ErrorKind::NotFound, "No existing order!")),
+                None => Err(AppError::NotFound("No existing order!".into())),
             }
         }
         None => {
             tracing::error!("Order owner not found");
-            return Err(Error::new(ErrorKind::Other, "Order owner not found"));
+            Err(AppError::NotFound("Order owner not found".into()))
         }
     }
 }
@@ -166,7 +176,7 @@ pub async fn update_order<T: Clone + AsSurrealClient>(
 pub async fn get_all_artifacts_for_order<T: Clone + AsSurrealClient>(
     db: &T,
     order_id: &str,
-) -> Result<ArtifactsPurchaseDetails, Error> {
+) -> Result<ArtifactsPurchaseDetails, AppError> {
     let mut order_artifacts_query = db
         .as_client()
         .query(
@@ -180,14 +190,11 @@ pub async fn get_all_artifacts_for_order<T: Clone + AsSurrealClient>(
         )
         .bind(("order_id", format!("order:{}", order_id)))
         .await
-        .map_err(|e| {
-            tracing::error!("DB Query Failed: {}", e);
-            Error::new(ErrorKind::Other, "DB Query Failed")
-        })?;
+        .map_err(AppError::database)?;
 
     let artifacts: Vec<String> = order_artifacts_query.take(0).map_err(|e| {
         tracing::error!("order_artifacts_query Deserialization Failed: {}", e);
-        Error::new(ErrorKind::Other, "Deserialization Failed")
+        AppError::Database("Deserialization Failed".into())
     })?;
 
     let mut buyer_id_query = db
@@ -203,14 +210,11 @@ pub async fn get_all_artifacts_for_order<T: Clone + AsSurrealClient>(
         )
         .bind(("order_id", format!("order:{}", order_id)))
         .await
-        .map_err(|e| {
-            tracing::error!("DB Query Failed: {}", e);
-            Error::new(ErrorKind::Other, "DB Query Failed")
-        })?;
+        .map_err(AppError::database)?;
 
     let buyer_id: Option<String> = buyer_id_query.take(0).map_err(|e| {
         tracing::error!("buyer_id_query Deserialization Failed: {}", e);
-        Error::new(ErrorKind::Other, "Deserialization Failed")
+        AppError::Database("Deserialization Failed".into())
     })?;
 
     let purchase_details = ArtifactsPurchaseDetails {
@@ -220,3 +224,111 @@ pub async fn get_all_artifacts_for_order<T: Clone + AsSurrealClient>(
 
     Ok(purchase_details)
 }
+
+/// Utility function to get the external user ID of the buyer of an order
+pub async fn get_order_buyer<T: Clone + AsSurrealClient>(
+    db: &T,
+    order_id: &str,
+) -> Result<String, AppError> {
+    let mut buyer_query = db
+        .as_client()
+        .query("SELECT VALUE in.user_id FROM type::thing($order_id)")
+        .bind(("order_id", format!("order:{}", order_id)))
+        .await
+        .map_err(AppError::database)?;
+
+    let buyer_id: Option<String> = buyer_query.take(0).map_err(AppError::deserialization)?;
+
+    buyer_id.ok_or_else(|| AppError::NotFound("Order Not Found".into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        database::migrations::MIGRATIONS,
+        utils::licenses::{get_license_certificates, use_test_signing_key},
+    };
+    use lib::database::memory::connect_in_memory;
+
+    const PENDING_ORDER: &str = "
+        CREATE user_id:buyer SET user_id = 'buyer-1';
+        CREATE product_id:product SET product_id = 'product-1';
+        CREATE license_id:license SET license_id = 'license-1';
+        CREATE cart:cart SET owner = user_id:buyer, session_id = 'session-1', total_amount = 100;
+        RELATE cart:cart->cart_product->product_id:product CONTENT {
+            quantity: 1,
+            license: license_id:license,
+            artifact: 'artifact-1'
+        };
+        RELATE user_id:buyer->order:order->cart:cart CONTENT { status: 'Pending' };
+    ";
+
+    #[tokio::test]
+    async fn confirming_an_order_archives_its_cart_and_records_the_event() {
+        use_test_signing_key();
+        let db = connect_in_memory(MIGRATIONS).await.unwrap();
+        db.query(PENDING_ORDER).await.unwrap().check().unwrap();
+
+        let status = update_order(&db, "order", OrderStatus::Confirmed)
+            .await
+            .unwrap();
+        assert_eq!(status, "Confirmed");
+
+        let mut archived_query = db
+            .query("SELECT VALUE archived FROM ONLY cart:cart")
+            .await
+            .unwrap();
+        let archived: Option<bool> = archived_query.take(0).unwrap();
+        assert_eq!(archived, Some(true));
+
+        // Confirming it again doesn't record another event
+        update_order(&db, "order", OrderStatus::Confirmed)
+            .await
+            .unwrap();
+        let mut events_query = db
+            .query("SELECT VALUE event_type FROM outbox")
+            .await
+            .unwrap();
+        let events: Vec<String> = events_query.take(0).unwrap();
+        assert_eq!(events, vec!["OrderConfirmed".to_string()]);
+
+        // Nor issue the licenses again
+        let certificates = get_license_certificates(&db, Some("buyer-1"), Some("order"))
+            .await
+            .unwrap();
+        assert_eq!(certificates.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn updating_a_missing_order_fails() {
+        let db = connect_in_memory(MIGRATIONS).await.unwrap();
+
+        assert!(update_order(&db, "missing", OrderStatus::Confirmed)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn gets_the_artifacts_and_buyer_of_an_order() {
+        let db = connect_in_memory(MIGRATIONS).await.unwrap();
+        db.query(PENDING_ORDER).await.unwrap().check().unwrap();
+
+        let purchase_details = get_all_artifacts_for_order(&db, "order").await.unwrap();
+
+        assert_eq!(purchase_details.buyer_id, "buyer-1");
+        assert_eq!(purchase_details.artifacts, vec!["artifact-1".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn gets_the_buyer_of_an_order() {
+        let db = connect_in_memory(MIGRATIONS).await.unwrap();
+        db.query(PENDING_ORDER).await.unwrap().check().unwrap();
+
+        assert_eq!(get_order_buyer(&db, "order").await.unwrap(), "buyer-1");
+        assert_eq!(
+            get_order_buyer(&db, "missing").await.unwrap_err().code(),
+            "NOT_FOUND"
+        );
+    }
+}
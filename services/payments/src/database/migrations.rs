@@ -0,0 +1,23 @@
+use lib::{
+    database::migrations::Migration,
+    events::outbox::{OUTBOX_CLAIMS_SCHEMA, OUTBOX_SCHEMA},
+};
+
+/// The migrations of the payments database, applied in order. Never edit an applied migration, add a new one.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial_schema",
+        sql: include_str!("migrations/0001_initial_schema.surql"),
+    },
+    Migration {
+        version: 2,
+        name: "event_outbox",
+        sql: OUTBOX_SCHEMA,
+    },
+    Migration {
+        version: 3,
+        name: "outbox_claims",
+        sql: OUTBOX_CLAIMS_SCHEMA,
+    },
+];
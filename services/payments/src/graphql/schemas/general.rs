@@ -3,6 +3,8 @@ use std::collections::HashMap;
 use async_graphql::{InputObject, SimpleObject};
 use serde::{Deserialize, Serialize};
 
+/// For the USD conversion of `initiate_payment_integration`, which uses a fixed rate for now
+#[allow(dead_code)]
 #[derive(Clone, Debug, Serialize, Deserialize, SimpleObject, InputObject)]
 pub struct ExchangeRatesResponse {
     pub success: bool,
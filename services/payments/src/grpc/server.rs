@@ -1,9 +1,7 @@
-use std::sync::Arc;
-
+use lib::utils::models::PaymentDetailsMetaData;
 use payments_service::{
     payments_service_server::PaymentsService, PaymentIntegrationResponse, UserPaymentDetails,
 };
-use surrealdb::{engine::remote::ws::Client, Surreal};
 use tonic::{Request, Response, Status};
 
 use crate::utils;
@@ -12,15 +10,8 @@ pub mod payments_service {
     tonic::include_proto!("payments");
 }
 
-pub struct PaymentsServiceImplementation {
-    db: Arc<Surreal<Client>>,
-}
-
-impl PaymentsServiceImplementation {
-    pub fn new(db: Arc<Surreal<Client>>) -> Self {
-        Self { db }
-    }
-}
+/// Payments are initialized with Paystack, nothing is stored here
+pub struct PaymentsServiceImplementation;
 
 impl From<UserPaymentDetails> for lib::utils::models::UserPaymentDetails {
     fn from(user: UserPaymentDetails) -> Self {
@@ -28,6 +19,10 @@ impl From<UserPaymentDetails> for lib::utils::models::UserPaymentDetails {
             email: user.email,
             amount: user.amount,
             reference: user.reference,
+            metadata: Some(PaymentDetailsMetaData {
+                cart_id: None,
+                locale: Some(user.locale).filter(|locale| !locale.is_empty()),
+            }),
         }
     }
 }
@@ -43,7 +38,7 @@ impl PaymentsService for PaymentsServiceImplementation {
             Ok(res) => Ok(Response::new(PaymentIntegrationResponse {
                 authorization_url: res.data.authorization_url,
             })),
-            Err(_e) => Err(Status::internal("Failed")),
+            Err(e) => Err(e.into()),
         }
     }
 }
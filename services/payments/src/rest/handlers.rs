@@ -7,6 +7,9 @@ use hex;
 use hmac::{Hmac, Mac};
 use hyper::header::{AUTHORIZATION, COOKIE};
 use lib::{
+    config::AppConfig,
+    email::{contexts::OrderConfirmation, render_email, Locale},
+    events::{outbox::record_event_once, DomainEvent},
     integration::grpc::clients::{
         acl_service::{acl_client::AclClient, Empty},
         email_service::{
@@ -18,35 +21,44 @@ use lib::{
             orders_service_client::OrdersServiceClient, GetAllArtifactsForOrderPayload,
             UpdateOrderPayload,
         },
+        shared_service::{
+            shared_service_client::SharedServiceClient, NotificationCategory, NotificationRequest,
+        },
     },
+    observability::{observe_grpc_call, record_revenue, record_webhook},
     utils::{
-        grpc::{create_grpc_client, AuthMetaData},
-        models::{Email, EmailUser, OrderStatus},
+        grpc::{create_grpc_client, AuthMetaData, GrpcChannel},
+        models::OrderStatus,
     },
 };
 use serde_json::Value;
 use sha2::Sha512;
-use std::{env, sync::Arc};
-use surrealdb::{engine::remote::ws::Client, Surreal};
-use tonic::transport::Channel;
+use std::sync::Arc;
+use surrealdb::{engine::any::Any, Surreal};
 
 // Type alias for HMAC-SHA512
 type HmacSha512 = Hmac<Sha512>;
 
 pub async fn handle_paystack_webhook(
-    Extension(_db): Extension<Arc<Surreal<Client>>>,
+    Extension(db): Extension<Arc<Surreal<Any>>>,
+    Extension(config): Extension<Arc<AppConfig>>,
     headers: HeaderMap,
     Json(body): Json<Value>,
 ) -> impl IntoResponse {
+    record_webhook("paystack", "received");
+
     // Retrieve the x-paystack-signature header
     let signature = headers
         .get("x-paystack-signature")
         .and_then(|v| v.to_str().ok())
         .unwrap_or("");
 
-    // Get the secret key
-    let secret = env::var("PAYSTACK_SECRET").expect("PAYSTACK_SECRET must be set");
-    let deployment_env = env::var("ENVIRONMENT").unwrap_or_else(|_| "prod".to_string()); // default to production because it's the most secure
+    // Get the secret key. Validated at boot, so it's always set for the payments service
+    let secret = config
+        .paystack
+        .as_ref()
+        .map(|paystack| paystack.secret.clone())
+        .unwrap_or_default();
 
     // Verify the webhook payload
     let mut mac =
@@ -59,10 +71,7 @@ pub async fn handle_paystack_webhook(
     let result = mac.finalize();
     let hash = hex::encode(result.into_bytes());
 
-    let paystack_signature_is_valid = match deployment_env.as_str() {
-        "prod" => hash == signature,
-        _ => true,
-    };
+    let paystack_signature_is_valid = !config.is_prod() || hash == signature;
 
     tracing::debug!(
         "paystack_signature_is_valid: {:?}",
@@ -75,279 +84,294 @@ pub async fn handle_paystack_webhook(
             if event == "charge.success" {
                 if let Some(data) = body.get("data") {
                     if let Some(reference) = data.get("reference").and_then(|r| r.as_str()) {
-                        // Internal sign in logic using gRPC
-                        let request = tonic::Request::new(Empty {});
+                        // The reference is the order ID, it ties this trace to the checkout's
+                        tracing::info!(order_id = reference, "Processing successful charge");
+
+                        // Known once the order's artifacts are found
+                        let mut buyer_id = None;
+                        // Paystack retries its webhooks, only the first delivery is recorded
+                        let mut first_delivery = true;
+
+                        let Some(service_headers) = sign_in_as_service(&config).await else {
+                            record_webhook("paystack", "failed");
+                            return (
+                                StatusCode::BAD_REQUEST,
+                                "Transaction successful but could not reach ACL service!"
+                                    .to_string(),
+                            )
+                                .into_response();
+                        };
+
+                        // Signed with the service identity, internal-only methods included
+                        let mut request = tonic::Request::new(UpdateOrderPayload {
+                            order_id: reference.to_string(),
+                            status: OrderStatus::Confirmed.into(),
+                        });
+
+                        let auth_metadata: AuthMetaData<UpdateOrderPayload> =
+                            AuthMetaData {
+                                auth_header: None,
+                                cookie_header: None,
+                                constructed_grpc_request: Some(&mut request),
+                            };
 
-                        if let Ok(mut acl_grpc_client) = create_grpc_client::<
-                            Empty,
-                            AclClient<Channel>,
+                        if let Ok(mut orders_grpc_client) = create_grpc_client::<
+                            UpdateOrderPayload,
+                            OrdersServiceClient<GrpcChannel>,
                         >(
-                            "http://[::1]:50051", false, None
+                            &config.endpoints.orders, false, Some(auth_metadata)
                         )
                         .await
                         .map_err(|e| {
-                            tracing::error!("Failed to connect to ACL service: {}", e);
-                            (
-                                StatusCode::NOT_FOUND,
-                                format!("Transaction successful but could not reach ACL service."),
+                            tracing::error!(
+                                "Failed to connect to Orders service: {}",
+                                e
+                            );
+                             (
+                                    StatusCode::NOT_FOUND,
+                                    "Transaction successful but could not reach Orders service!".to_string(),
+                                )
+                                    .into_response()
+                        })
+                        {
+                            // Update order status
+                            if let Err(e) = observe_grpc_call(
+                                "orders",
+                                "UpdateOrder",
+                                orders_grpc_client.update_order(request),
                             )
-                                .into_response()
-                        }) {
-                            if let Ok(auth_res) = acl_grpc_client.sign_in_as_service(request).await
+                            .await
                             {
-                                let mut header_map = HeaderMap::new();
-                                let internal_jwt = auth_res.into_inner().token;
-                                header_map.insert(
-                                    AUTHORIZATION,
-                                    format!("Bearer {}", &internal_jwt)
-                                        .as_str()
-                                        .parse()
-                                        .unwrap(),
-                                );
-                                header_map.insert(
-                                    COOKIE,
-                                    format!("oauth_client=;t={}", &internal_jwt)
-                                        .as_str()
-                                        .parse()
-                                        .unwrap(),
-                                );
+                                tracing::error!("Failed to update order: {:?}", e);
+                                record_webhook("paystack", "failed");
+                                return (
+                                    StatusCode::BAD_REQUEST,
+                                    "Transaction successful but could not update order status!"
+                                        .to_string(),
+                                )
+                                    .into_response();
+                            }
 
-                                let auth_header = header_map.get(AUTHORIZATION);
-                                let cookie_header = header_map.get(COOKIE);
+                            tracing::debug!("rest webhook: updated order!");
 
-                                tracing::debug!("auth_header: {:?}", auth_header);
+                            // Once per reference, Paystack retries its webhooks
+                            let payment_succeeded = DomainEvent::PaymentSucceeded {
+                                reference: reference.to_string(),
+                                amount: data.get("amount").and_then(|a| a.as_u64()),
+                                currency: data
+                                    .get("currency")
+                                    .and_then(|c| c.as_str())
+                                    .map(|c| c.to_string()),
+                            };
+                            match record_event_once(
+                                &db,
+                                &config.service,
+                                &format!("payment-succeeded-{}", reference),
+                                payment_succeeded,
+                            )
+                            .await
+                            {
+                                // The first delivery confirmed the order. Paystack amounts are in
+                                // the currency's subunit
+                                Ok(true) => {
+                                    if let (Some(amount), Some(currency)) = (
+                                        data.get("amount").and_then(|a| a.as_f64()),
+                                        data.get("currency").and_then(|c| c.as_str()),
+                                    ) {
+                                        record_revenue(currency, amount / 100.0);
+                                    }
+                                }
+                                Ok(false) => first_delivery = false,
+                                Err(e) => {
+                                    tracing::error!("Failed to record the payment: {:?}", e);
+                                }
+                            }
 
-                                let mut request = tonic::Request::new(UpdateOrderPayload {
-                                    order_id: reference.to_string(),
-                                    status: OrderStatus::Confirmed.try_into().unwrap(),
-                                });
+                            let mut request = tonic::Request::new(GetAllArtifactsForOrderPayload {
+                                order_id: reference.to_string(),
+                            });
 
-                                let auth_metadata: AuthMetaData<UpdateOrderPayload> =
-                                    AuthMetaData {
-                                        auth_header,
-                                        cookie_header,
-                                        constructed_grpc_request: Some(&mut request),
-                                    };
+                            let auth_metadata: AuthMetaData<GetAllArtifactsForOrderPayload> =
+                                AuthMetaData {
+                                    auth_header: None,
+                                    cookie_header: None,
+                                    constructed_grpc_request: Some(&mut request),
+                                };
 
-                                if let Ok(mut orders_grpc_client) = create_grpc_client::<
-                                    UpdateOrderPayload,
-                                    OrdersServiceClient<Channel>,
-                                >(
-                                    "http://[::1]:50055", true, Some(auth_metadata)
+                            // give ownership rights to artifacts
+                            if let Ok(mut orders_grpc_client) = create_grpc_client::<
+                                GetAllArtifactsForOrderPayload,
+                                OrdersServiceClient<GrpcChannel>,
+                            >(
+                                &config.endpoints.orders, false, Some(auth_metadata)
+                            )
+                            .await
+                            .map_err(|e| {
+                                tracing::error!(
+                                    "Failed to connect to Orders service: {}",
+                                    e
+                                );
+                                 (
+                                        StatusCode::NOT_FOUND,
+                                        "Transaction successful but could not reach Orders service!".to_string(),
+                                    )
+                                        .into_response()
+                            })
+                            {
+                                if let Ok(artifacts) = observe_grpc_call(
+                                    "orders",
+                                    "GetAllArtifactsForOrder",
+                                    orders_grpc_client.get_all_artifacts_for_order(request),
                                 )
                                 .await
-                                .map_err(|e| {
-                                    tracing::error!(
-                                        "Failed to connect to Orders service: {}",
-                                        e
-                                    );
-                                     (
-                                            StatusCode::NOT_FOUND,
-                                            format!(
-                                            "Transaction successful but could not reach Orders service!"
-                                        ),
-                                        )
-                                            .into_response()
-                                })
                                 {
-                                    // Update order status
-                                    if let Err(e) = orders_grpc_client.update_order(request).await {
-                                        tracing::error!("Failed to update order: {:?}", e);
-                                        return (
-                                            StatusCode::BAD_REQUEST,
-                                            format!(
-                                                "Transaction successful but could not update order status!"
-                                            ),
-                                        )
-                                            .into_response();
-                                    }
+                                    let artifacts = artifacts.into_inner();
+                                    buyer_id = Some(artifacts.buyer_id.clone());
 
-                                    tracing::debug!("rest webhook: updated order!");
-
-                                    let mut request = tonic::Request::new(GetAllArtifactsForOrderPayload {
-                                        order_id: reference.to_string(),
-                                    });
-
-                                    let auth_metadata: AuthMetaData<GetAllArtifactsForOrderPayload> =
-                                        AuthMetaData {
-                                            auth_header,
-                                            cookie_header,
-                                            constructed_grpc_request: Some(&mut request),
-                                        };
-
-                                    // give ownership rights to artifacts
-                                    if let Ok(mut orders_grpc_client) = create_grpc_client::<
-                                        GetAllArtifactsForOrderPayload,
-                                        OrdersServiceClient<Channel>,
-                                    >(
-                                        "http://[::1]:50055", true, Some(auth_metadata)
-                                    )
-                                    .await
-                                    .map_err(|e| {
-                                        tracing::error!(
-                                            "Failed to connect to Orders service: {}",
-                                            e
-                                        );
-                                         (
+                                    for artifact in artifacts.artifacts.iter() {
+                                        tracing::debug!("Found buyer_id: {:?}", artifacts.buyer_id);
+                                        let mut request =
+                                            tonic::Request::new(PurchaseFileDetails {
+                                                buyer_id: artifacts.buyer_id.clone(),
+                                                file_id: artifact.clone(),
+                                            });
+
+                                        let auth_metadata: AuthMetaData<PurchaseFileDetails> =
+                                            AuthMetaData {
+                                                auth_header: service_headers.get(AUTHORIZATION),
+                                                cookie_header: service_headers.get(COOKIE),
+                                                constructed_grpc_request: Some(&mut request),
+                                            };
+
+                                        if let Ok(mut files_service_grpc_client) = create_grpc_client::<
+                                            PurchaseFileDetails,
+                                            FilesServiceClient<GrpcChannel>,
+                                        >(
+                                            &config.endpoints.files, true, Some(auth_metadata)
+                                        )
+                                        .await
+                                        .map_err(|e| {
+                                            tracing::error!("Transaction successful but could not reach Files service: {}", e);
+                                            (
                                                 StatusCode::NOT_FOUND,
-                                                format!(
-                                                "Transaction successful but could not reach Orders service!"
-                                            ),
+                                                "Transaction successful but could not reach Files service.".to_string(),
                                             )
                                                 .into_response()
-                                    })
-                                    {
-                                        if let Ok(artifacts) = orders_grpc_client
-                                            .get_all_artifacts_for_order(request)
+                                        }) {
+                                            if let Err(e) = observe_grpc_call(
+                                                "files",
+                                                "PurchaseFile",
+                                                files_service_grpc_client.purchase_file(request),
+                                            )
                                             .await
-                                        {
-                                            let artifacts = artifacts.into_inner();
-
-                                            for artifact in artifacts.artifacts.iter() {
-                                                tracing::debug!("Found buyer_id: {:?}", artifacts.buyer_id);
-                                                let mut request =
-                                                    tonic::Request::new(PurchaseFileDetails {
-                                                        buyer_id: artifacts.buyer_id.clone(),
-                                                        file_id: artifact.clone(),
-                                                    });
-
-                                                let auth_metadata: AuthMetaData<PurchaseFileDetails> =
-                                                    AuthMetaData {
-                                                        auth_header,
-                                                        cookie_header,
-                                                        constructed_grpc_request: Some(&mut request),
-                                                    };
-
-                                                if let Ok(mut files_service_grpc_client) = create_grpc_client::<
-                                                    PurchaseFileDetails,
-                                                    FilesServiceClient<Channel>,
-                                                >(
-                                                    "http://[::1]:50053", true, Some(auth_metadata)
-                                                )
-                                                .await
-                                                .map_err(|e| {
-                                                    tracing::error!("Transaction successful but could not reach Files service: {}", e);
-                                                    (
-                                                        StatusCode::NOT_FOUND,
-                                                        format!("Transaction successful but could not reach Files service."),
-                                                    )
-                                                        .into_response()
-                                                }) {
-                                                    if let Err(e) =
-                                                        files_service_grpc_client.purchase_file(request).await
-                                                    {
-                                                        tracing::error!("Failed to purchase file: {:?}", e);
-                                                    }
-                                                }
-
-                                                tracing::debug!("rest webhook: purchased artifacts!");
+                                            {
+                                                tracing::error!("Failed to purchase file: {:?}", e);
                                             }
                                         }
+
+                                        tracing::debug!("rest webhook: purchased artifacts!");
                                     }
-                                };
+                                }
+                            }
+                        };
 
-                                // Construct and send confirmation email
-                                let confirmed_mail = if let Some(customer) = data.get("customer") {
-                                    if let Some(email) =
-                                        customer.get("email").and_then(|e| e.as_str())
-                                    {
-                                        let email_body = format!(
-                                            r#"
-                                        <div style="font-family: Arial, sans-serif; background-color: #f4f4f4;">
-                                            <div style="max-width: 600px; margin: auto; background-color: #ffffff; border-radius: 8px; box-shadow: 0 0 10px rgba(0, 0, 0, 0.1);">
-                                                <h2 style="background-color: #4CAF50; color: #ffffff; padding: 10px; border-radius: 8px 8px 0 0; text-align: center;">Payment Confirmation</h2>
-                                                <div style="padding: 10px;">
-                                                    <p>Dear Customer,</p>
-                                                    <p>We are pleased to inform you that we have successfully received your payment.</p>
-                                                    <p>Your template is also ready for download. Happy Crabbing 🦀 🚀</p>
-                                                    <p>
-                                                        <a href="https://rustytemplates.com/account" style="display: inline-block; padding: 10px 20px; background-color: #4CAF50; color: white; text-decoration: none; border-radius: 5px;">Download Here</a>
-                                                    </p>
-                                                    <p>If you have any questions or concerns, please do not hesitate to contact our support team.</p>
-                                                    <p>Thank you for your purchase!</p>
-                                                    <p>Sincerely,<br/>The Rusty Templates Team</p>
-                                                </div>
-                                            </div>
-                                        </div>
-                                        "#
-                                        );
+                        // The buyer may have turned off the order emails. Retries were already
+                        // notified and emailed
+                        let email_enabled = match &buyer_id {
+                            _ if !first_delivery => false,
+                            Some(buyer_id) => {
+                                notify_payment_received(&config, buyer_id, reference).await
+                            }
+                            None => true,
+                        };
 
-                                        Some(Email {
-                                            recipient: EmailUser {
-                                                full_name: None,
-                                                email_address: email.to_string(),
-                                            },
-                                            subject: "Payment Confirmation".to_string(),
-                                            title: "Payment Received! Thanks!".to_string(),
-                                            body: email_body.to_string(),
-                                        })
-                                    } else {
-                                        None
-                                    }
-                                } else {
-                                    None
+                        // In the buyer's language, sent with the payment's metadata
+                        let locale = data
+                            .pointer("/metadata/locale")
+                            .and_then(|locale| locale.as_str())
+                            .map(Locale::from_tag)
+                            .unwrap_or_default();
+
+                        // Render and send the confirmation email
+                        let confirmed_mail = data
+                            .get("customer")
+                            .and_then(|customer| customer.get("email"))
+                            .and_then(|e| e.as_str())
+                            .filter(|_| email_enabled)
+                            .and_then(|email| {
+                                let confirmation = OrderConfirmation {
+                                    reference: reference.to_string(),
+                                    download_url: config.email.account_url.clone(),
                                 };
+                                let rendered = render_email(&config.email, locale, &confirmation)
+                                    .map_err(|e| {
+                                        tracing::error!(
+                                            "Failed to render the order confirmation: {:?}",
+                                            e
+                                        );
+                                    })
+                                    .ok()?;
 
-                                if let Some(email) = confirmed_mail {
-                                    let mut request = tonic::Request::new(TonicEmail {
-                                        recipient: Some(TonicEmailUser {
-                                            email_address: email.recipient.email_address,
-                                            full_name: match email.recipient.full_name {
-                                                Some(full_name) => full_name,
-                                                None => "".to_string(),
-                                            },
-                                        }),
-                                        subject: email.subject,
-                                        title: email.title,
-                                        body: email.body,
-                                    });
-
-                                    // let auth_header = headers.get(AUTHORIZATION);
-                                    // let cookie_header = headers.get(COOKIE);
-
-                                    let auth_metadata: AuthMetaData<TonicEmail> = AuthMetaData {
-                                        auth_header,
-                                        cookie_header,
-                                        constructed_grpc_request: Some(&mut request),
-                                    };
-
-                                    if let Ok(mut email_service_grpc_client) =
-                                        create_grpc_client::<TonicEmail, EmailServiceClient<Channel>>(
-                                            "http://[::1]:50052",
-                                            true,
-                                            Some(auth_metadata),
-                                        )
-                                        .await
-                                        .map_err(|e| {
-                                            tracing::error!("Failed to connect to Files service: {}", e);
-                                            (
-                                                    StatusCode::NOT_FOUND,
-                                                    format!("Transaction successful but could not reach Email service!"),
-                                                )
-                                                    .into_response()
-                                        }) {
-                                            if let Err(e) =
-                                                email_service_grpc_client.send_email(request).await
-                                            {
-                                                eprintln!("Failed to send email: {:?}", e);
-                                                return (
-                                                    StatusCode::BAD_REQUEST,
-                                                    format!(
-                                                        "Transaction successful but could not send email!"
-                                                    ),
-                                                )
-                                                    .into_response();
-                                            }
-                                        };
+                                Some(TonicEmail {
+                                    recipient: Some(TonicEmailUser {
+                                        email_address: email.to_string(),
+                                        full_name: "".to_string(),
+                                    }),
+                                    subject: rendered.subject,
+                                    title: rendered.title,
+                                    body: rendered.html,
+                                    text_body: rendered.text,
+                                })
+                            });
 
-                                    tracing::debug!("rest webhook: sent email!");
-                                }
+                        if let Some(email) = confirmed_mail {
+                            let mut request = tonic::Request::new(email);
+
+                            let auth_metadata: AuthMetaData<TonicEmail> = AuthMetaData {
+                                auth_header: service_headers.get(AUTHORIZATION),
+                                cookie_header: service_headers.get(COOKIE),
+                                constructed_grpc_request: Some(&mut request),
                             };
-                        };
+
+                            if let Ok(mut email_service_grpc_client) =
+                                create_grpc_client::<TonicEmail, EmailServiceClient<GrpcChannel>>(
+                                    &config.endpoints.email,
+                                    true,
+                                    Some(auth_metadata),
+                                )
+                                .await
+                                .map_err(|e| {
+                                    tracing::error!("Failed to connect to Files service: {}", e);
+                                    (
+                                            StatusCode::NOT_FOUND,
+                                            "Transaction successful but could not reach Email service!".to_string(),
+                                        )
+                                            .into_response()
+                                }) {
+                                    if let Err(e) = observe_grpc_call(
+                                        "email",
+                                        "SendEmail",
+                                        email_service_grpc_client.send_email(request),
+                                    )
+                                    .await
+                                    {
+                                        eprintln!("Failed to send email: {:?}", e);
+                                        record_webhook("paystack", "failed");
+                                        return (
+                                            StatusCode::BAD_REQUEST,
+                                            "Transaction successful but could not send email!".to_string(),
+                                        )
+                                            .into_response();
+                                    }
+                                };
+
+                            tracing::debug!("rest webhook: sent email!");
+                        }
                     }
                 }
-                (StatusCode::CREATED, format!("Transaction successful!")).into_response()
+                (StatusCode::CREATED, "Transaction successful!".to_string()).into_response()
             } else {
+                record_webhook("paystack", "failed");
                 (
                     StatusCode::BAD_REQUEST,
                     format!("Unhandled event type: {}", event),
@@ -355,14 +379,377 @@ pub async fn handle_paystack_webhook(
                     .into_response()
             }
         } else {
+            record_webhook("paystack", "failed");
             (
                 StatusCode::BAD_REQUEST,
-                format!("Event type missing or invalid"),
+                "Event type missing or invalid".to_string(),
             )
                 .into_response()
         }
     } else {
         tracing::error!("Invalid signature: expected {}, got {}", signature, hash);
-        (StatusCode::BAD_REQUEST, format!("Transaction failed!")).into_response()
+        record_webhook("paystack", "failed");
+        (StatusCode::BAD_REQUEST, "Transaction failed!".to_string()).into_response()
+    }
+}
+
+/// The headers of the JWT ACL signs the payments service in with. The files and email services run
+/// outside this repository and don't verify service tokens, they only accept this JWT.
+async fn sign_in_as_service(config: &AppConfig) -> Option<HeaderMap> {
+    let mut request = tonic::Request::new(Empty {});
+
+    let auth_metadata: AuthMetaData<Empty> = AuthMetaData {
+        auth_header: None,
+        cookie_header: None,
+        constructed_grpc_request: Some(&mut request),
+    };
+
+    let mut acl_grpc_client = match create_grpc_client::<Empty, AclClient<GrpcChannel>>(
+        &config.endpoints.acl,
+        false,
+        Some(auth_metadata),
+    )
+    .await
+    {
+        Ok(client) => client,
+        Err(e) => {
+            tracing::error!("Failed to connect to ACL service: {}", e);
+            return None;
+        }
+    };
+
+    let internal_jwt = match observe_grpc_call(
+        "acl",
+        "SignInAsService",
+        acl_grpc_client.sign_in_as_service(request),
+    )
+    .await
+    {
+        Ok(response) => response.into_inner().token,
+        Err(e) => {
+            tracing::error!("Failed to sign in as a service: {:?}", e);
+            return None;
+        }
+    };
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        AUTHORIZATION,
+        format!("Bearer {}", internal_jwt).parse().ok()?,
+    );
+    headers.insert(
+        COOKIE,
+        format!("oauth_client=;t={}", internal_jwt).parse().ok()?,
+    );
+    Some(headers)
+}
+
+/// Post the payment to the buyer's notification center. Returns whether the buyer accepts order
+/// emails, true when the shared service can't tell.
+async fn notify_payment_received(config: &AppConfig, buyer_id: &str, reference: &str) -> bool {
+    let mut request = tonic::Request::new(NotificationRequest {
+        user_id: buyer_id.to_string(),
+        category: NotificationCategory::Orders.into(),
+        title: "Payment received".to_string(),
+        body: format!(
+            "We received your payment for order {}, your templates are ready for download.",
+            reference
+        ),
+        link: config.email.account_url.clone(),
+    });
+
+    let auth_metadata: AuthMetaData<NotificationRequest> = AuthMetaData {
+        auth_header: None,
+        cookie_header: None,
+        constructed_grpc_request: Some(&mut request),
+    };
+
+    let mut shared_grpc_client = match create_grpc_client::<
+        NotificationRequest,
+        SharedServiceClient<GrpcChannel>,
+    >(&config.endpoints.shared, false, Some(auth_metadata))
+    .await
+    {
+        Ok(client) => client,
+        Err(e) => {
+            tracing::error!("Failed to connect to Shared service: {}", e);
+            return true;
+        }
+    };
+
+    match observe_grpc_call(
+        "shared",
+        "PostNotification",
+        shared_grpc_client.post_notification(request),
+    )
+    .await
+    {
+        Ok(response) => response.into_inner().email_enabled,
+        Err(e) => {
+            tracing::error!("Failed to post the payment notification: {:?}", e);
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::migrations::MIGRATIONS;
+    use lib::{
+        config::PaystackConfig,
+        database::memory::connect_in_memory,
+        events::EventEnvelope,
+        integration::grpc::clients::{
+            orders_service::{
+                orders_service_server::{OrdersService, OrdersServiceServer},
+                ArtifactsPurchaseDetails, OrderStatus as TonicOrderStatus, UpdateOrderResponse,
+            },
+            shared_service::{
+                shared_service_server::{SharedService, SharedServiceServer},
+                NotificationResponse, TermsAcceptanceStatus, UserId,
+            },
+        },
+        middleware::auth::service::verify_service_token,
+        observability::metrics,
+        test_support::{fakes::FAKE_SERVICE_TOKEN, recorder::FakeMethod, FakeServices},
+    };
+    use serde_json::json;
+    use std::time::SystemTime;
+    use tonic::{transport::Server, Request, Response, Status};
+
+    /// Stand-in for the orders service, which lives in its own crate
+    #[derive(Clone)]
+    struct FakeOrders {
+        update_order: Arc<FakeMethod<UpdateOrderPayload, UpdateOrderResponse>>,
+        get_all_artifacts_for_order:
+            Arc<FakeMethod<GetAllArtifactsForOrderPayload, ArtifactsPurchaseDetails>>,
+    }
+
+    #[tonic::async_trait]
+    impl OrdersService for FakeOrders {
+        async fn update_order(
+            &self,
+            request: Request<UpdateOrderPayload>,
+        ) -> Result<Response<UpdateOrderResponse>, Status> {
+            self.update_order.handle(request)
+        }
+
+        async fn get_all_artifacts_for_order(
+            &self,
+            request: Request<GetAllArtifactsForOrderPayload>,
+        ) -> Result<Response<ArtifactsPurchaseDetails>, Status> {
+            self.get_all_artifacts_for_order.handle(request)
+        }
+    }
+
+    /// Stand-in for the shared service, which lives in its own crate
+    #[derive(Clone)]
+    struct FakeShared {
+        post_notification: Arc<FakeMethod<NotificationRequest, NotificationResponse>>,
+    }
+
+    #[tonic::async_trait]
+    impl SharedService for FakeShared {
+        async fn has_accepted_latest_terms(
+            &self,
+            _request: Request<UserId>,
+        ) -> Result<Response<TermsAcceptanceStatus>, Status> {
+            Ok(Response::new(TermsAcceptanceStatus { accepted: true }))
+        }
+
+        async fn post_notification(
+            &self,
+            request: Request<NotificationRequest>,
+        ) -> Result<Response<NotificationResponse>, Status> {
+            self.post_notification.handle(request)
+        }
+    }
+
+    /// Serve the fake orders and shared services, the buyer accepting order emails or not
+    async fn start_fakes(
+        fake_services: &mut FakeServices,
+        email_enabled: bool,
+    ) -> (FakeOrders, FakeShared) {
+        let fake_orders = FakeOrders {
+            update_order: FakeMethod::new(UpdateOrderResponse {
+                status_str: "Confirmed".to_string(),
+            }),
+            get_all_artifacts_for_order: FakeMethod::new(ArtifactsPurchaseDetails {
+                buyer_id: "buyer-1".to_string(),
+                artifacts: vec!["artifact-1".to_string(), "artifact-2".to_string()],
+            }),
+        };
+        let fake_shared = FakeShared {
+            post_notification: FakeMethod::new(NotificationResponse {
+                notification_id: "notification-1".to_string(),
+                email_enabled,
+            }),
+        };
+
+        let incoming = fake_services.serve_endpoint("orders").await.unwrap();
+        tokio::spawn(
+            Server::builder()
+                .add_service(OrdersServiceServer::new(fake_orders.clone()))
+                .serve_with_incoming_shutdown(incoming, fake_services.shutdown_signal()),
+        );
+        let incoming = fake_services.serve_endpoint("shared").await.unwrap();
+        tokio::spawn(
+            Server::builder()
+                .add_service(SharedServiceServer::new(fake_shared.clone()))
+                .serve_with_incoming_shutdown(incoming, fake_services.shutdown_signal()),
+        );
+
+        (fake_orders, fake_shared)
+    }
+
+    /// Paystack's notification of a successful 1500 charge for order-1 by a French speaking buyer.
+    /// Each test charges in its own currency, the revenue metric is process wide.
+    async fn charge_success(
+        db: Arc<Surreal<Any>>,
+        fake_services: &FakeServices,
+        currency: &str,
+    ) -> StatusCode {
+        let mut config = (*fake_services.config()).clone();
+        config.paystack = Some(PaystackConfig {
+            secret: "test-secret".to_string(),
+        });
+        let body = json!({
+            "event": "charge.success",
+            "data": {
+                "reference": "order-1",
+                "amount": 150000,
+                "currency": currency,
+                "metadata": { "cartId": null, "locale": "fr" },
+                "customer": { "email": "buyer@example.com" }
+            }
+        });
+
+        handle_paystack_webhook(
+            Extension(db),
+            Extension(Arc::new(config)),
+            HeaderMap::new(),
+            Json(body),
+        )
+        .await
+        .into_response()
+        .status()
+    }
+
+    #[tokio::test]
+    async fn successful_charges_grant_artifacts_and_send_a_confirmation() {
+        let mut fake_services = FakeServices::start_with_config(AppConfig::local("payments"))
+            .await
+            .unwrap();
+        let (fake_orders, fake_shared) = start_fakes(&mut fake_services, true).await;
+
+        let db = connect_in_memory(MIGRATIONS).await.unwrap();
+        assert_eq!(
+            charge_success(db.clone(), &fake_services, "ngn").await,
+            StatusCode::CREATED
+        );
+
+        // Files and email only accept the JWT ACL signs the service in with
+        assert_eq!(fake_services.acl.sign_in_as_service.call_count(), 1);
+        let service_jwt = Some(format!("Bearer {}", FAKE_SERVICE_TOKEN));
+        assert_eq!(
+            fake_services.files.purchase_file.calls()[0].authorization,
+            service_jwt
+        );
+        assert_eq!(
+            fake_services.email.send_email.calls()[0].authorization,
+            service_jwt
+        );
+
+        let update_order_calls = fake_orders.update_order.calls();
+        assert_eq!(update_order_calls.len(), 1);
+        assert_eq!(update_order_calls[0].message.order_id, "order-1");
+        assert_eq!(
+            update_order_calls[0].message.status,
+            i32::from(TonicOrderStatus::Confirmed)
+        );
+        assert_eq!(update_order_calls[0].authorization, None);
+        // Signed by the process wide identity, since the clients don't get the handler's config
+        let service_config = lib::config::get();
+        assert_eq!(
+            verify_service_token(
+                update_order_calls[0].service_token.as_deref().unwrap(),
+                "/orders.OrdersService/UpdateOrder",
+                &service_config.service_auth.token_secret,
+                SystemTime::now()
+            ),
+            Ok(service_config.service.clone())
+        );
+
+        assert_eq!(
+            fake_services.files.purchases(),
+            vec![
+                ("buyer-1".to_string(), "artifact-1".to_string()),
+                ("buyer-1".to_string(), "artifact-2".to_string())
+            ]
+        );
+
+        let sent = fake_services.email.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(
+            sent[0].recipient.as_ref().unwrap().email_address,
+            "buyer@example.com"
+        );
+        // In the language the buyer checked out in
+        assert_eq!(sent[0].subject, "Confirmation de paiement");
+        assert!(sent[0].body.contains("Télécharger"));
+        assert!(sent[0].text_body.contains("commande order-1"));
+
+        let notifications = fake_shared.post_notification.calls();
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0].message.user_id, "buyer-1");
+        assert_eq!(
+            notifications[0].message.category,
+            i32::from(NotificationCategory::Orders)
+        );
+
+        // Paystack retrying the webhook doesn't record the payment or its revenue again, nor
+        // notify the buyer again
+        assert_eq!(
+            charge_success(db.clone(), &fake_services, "ngn").await,
+            StatusCode::CREATED
+        );
+        assert_eq!(fake_shared.post_notification.call_count(), 1);
+        assert_eq!(fake_services.email.sent().len(), 1);
+        assert!(metrics()
+            .encode()
+            .contains(r#"revenue_total{currency="NGN"} 1500"#));
+        let mut outbox_query = db.query("SELECT VALUE envelope FROM outbox").await.unwrap();
+        let envelopes: Vec<String> = outbox_query.take(0).unwrap();
+        assert_eq!(envelopes.len(), 1);
+        let envelope: EventEnvelope = serde_json::from_str(&envelopes[0]).unwrap();
+        assert_eq!(envelope.id, "payment-succeeded-order-1");
+        assert_eq!(envelope.source, "payments");
+        assert_eq!(
+            envelope.event,
+            DomainEvent::PaymentSucceeded {
+                reference: "order-1".to_string(),
+                amount: Some(150000),
+                currency: Some("ngn".to_string()),
+            }
+        );
+    }
+    #[tokio::test]
+    async fn buyers_who_turned_off_order_emails_are_only_notified() {
+        let mut fake_services = FakeServices::start_with_config(AppConfig::local("payments"))
+            .await
+            .unwrap();
+        let (_fake_orders, fake_shared) = start_fakes(&mut fake_services, false).await;
+
+        let db = connect_in_memory(MIGRATIONS).await.unwrap();
+        assert_eq!(
+            charge_success(db, &fake_services, "kes").await,
+            StatusCode::CREATED
+        );
+
+        assert_eq!(fake_shared.post_notification.call_count(), 1);
+        assert!(fake_services.email.sent().is_empty());
+        // The purchase goes through regardless
+        assert_eq!(fake_services.files.purchases().len(), 2);
     }
 }
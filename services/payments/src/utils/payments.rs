@@ -1,22 +1,25 @@
 use reqwest::{header::HeaderMap as ReqWestHeaderMap, Client as ReqWestClient};
-use std::{
-    env,
-    io::{Error, ErrorKind},
-};
 
 // use crate::graphql::schemas::general::ExchangeRatesResponse;
 use hyper::http::Method;
-use lib::utils::models::{InitializePaymentResponse, UserPaymentDetails};
+use lib::{
+    config,
+    error::AppError,
+    utils::models::{InitializePaymentResponse, UserPaymentDetails},
+};
 
 pub async fn initiate_payment_integration(
     user_payment_details: &mut UserPaymentDetails,
-) -> Result<InitializePaymentResponse, Error> {
+) -> Result<InitializePaymentResponse, AppError> {
     let client = ReqWestClient::builder()
         .danger_accept_invalid_certs(true)
         .build()
         .unwrap();
-    let paystack_secret =
-        env::var("PAYSTACK_SECRET").expect("Missing the PAYSTACK_SECRET environment variable.");
+    let paystack_secret = config::get()
+        .paystack
+        .as_ref()
+        .map(|paystack| paystack.secret.clone())
+        .ok_or_else(|| AppError::Internal("Paystack is not configured".into()))?;
 
     let mut req_headers = ReqWestHeaderMap::new();
     req_headers.insert(
@@ -61,18 +64,18 @@ pub async fn initiate_payment_integration(
             "https://api.paystack.co/transaction/initialize",
         )
         .headers(req_headers)
-        .json::<UserPaymentDetails>(&user_payment_details)
+        .json::<UserPaymentDetails>(user_payment_details)
         .send()
         .await
         .map_err(|e| {
             tracing::error!("Sending error: {:?}", e);
-            Error::new(ErrorKind::Other, "Internal server error")
+            AppError::Upstream("Payment provider unavailable".into())
         })?
         .json::<InitializePaymentResponse>()
         .await
         .map_err(|e| {
             tracing::error!("Decoding error: {:?}", e);
-            Error::new(ErrorKind::Other, "Internal server error")
+            AppError::Upstream("Payment provider unavailable".into())
         })?;
 
     Ok(paystack_response)
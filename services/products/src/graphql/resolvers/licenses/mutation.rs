@@ -0,0 +1,244 @@
+use std::sync::Arc;
+
+use crate::{
+    graphql::schemas::general::{License, LicenseUpdate, ProductLicense},
+    utils::products::get_product_owner,
+};
+use async_graphql::{Context, Error, Object, Result};
+use axum::{http::HeaderMap, Extension};
+use lib::{
+    middleware::auth::graphql::{check_admin_from_acl, check_auth_from_acl},
+    utils::custom_error::ExtendedError,
+};
+use surrealdb::{engine::any::Any, Surreal};
+
+#[derive(Default)]
+pub struct LicenseMutation;
+
+#[Object]
+impl LicenseMutation {
+    /// Resolver method to create a license. Admins only.
+    pub async fn create_license(&self, ctx: &Context<'_>, license: License) -> Result<License> {
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
+
+        if let Some(headers) = ctx.data_opt::<HeaderMap>() {
+            check_admin_from_acl(headers).await.map_err(|_e| {
+                ExtendedError::new("Not Authorized!", Some(403.to_string())).build()
+            })?;
+
+            let mut create_license_query = db
+                .query(
+                    "
+                CREATE ONLY license CONTENT {
+                    name: $license.name,
+                    price_factor: $license.price_factor,
+                    short_description: $license.short_description
+                }
+                ",
+                )
+                .bind(("license", license))
+                .await
+                .map_err(|e| Error::new(e.to_string()))?;
+
+            let response: Option<License> = create_license_query.take(0).map_err(|_e| {
+                ExtendedError::new("License already exists!", Some(409.to_string())).build()
+            })?;
+
+            match response {
+                Some(license) => Ok(license),
+                None => Err(
+                    ExtendedError::new("Failed to create license!", Some(500.to_string())).build(),
+                ),
+            }
+        } else {
+            Err(ExtendedError::new("Not Authorized!", Some(403.to_string())).build())
+        }
+    }
+
+    /// Resolver method to update a license. Only the given fields change. Admins only.
+    pub async fn update_license(
+        &self,
+        ctx: &Context<'_>,
+        license_id: String,
+        license: LicenseUpdate,
+    ) -> Result<License> {
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
+
+        if let Some(headers) = ctx.data_opt::<HeaderMap>() {
+            check_admin_from_acl(headers).await.map_err(|_e| {
+                ExtendedError::new("Not Authorized!", Some(403.to_string())).build()
+            })?;
+
+            update_license(db, license_id, license).await
+        } else {
+            Err(ExtendedError::new("Not Authorized!", Some(403.to_string())).build())
+        }
+    }
+
+    /// Resolver method to deactivate a license. Deactivated licenses can't be bought but existing
+    /// purchases are unaffected. Admins only.
+    pub async fn deactivate_license(&self, ctx: &Context<'_>, license_id: String) -> Result<License> {
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
+
+        if let Some(headers) = ctx.data_opt::<HeaderMap>() {
+            check_admin_from_acl(headers).await.map_err(|_e| {
+                ExtendedError::new("Not Authorized!", Some(403.to_string())).build()
+            })?;
+
+            let changes = LicenseUpdate {
+                name: None,
+                price_factor: None,
+                short_description: None,
+                active: Some(false),
+            };
+
+            update_license(db, license_id, changes).await
+        } else {
+            Err(ExtendedError::new("Not Authorized!", Some(403.to_string())).build())
+        }
+    }
+
+    /// Resolver method to set the availability and price factor override of a license for a product.
+    /// Admins and the owner of the product only.
+    pub async fn set_product_license(
+        &self,
+        ctx: &Context<'_>,
+        product_id: String,
+        license_id: String,
+        available: bool,
+        price_factor: Option<u64>,
+    ) -> Result<ProductLicense> {
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
+
+        if let Some(headers) = ctx.data_opt::<HeaderMap>() {
+            if check_admin_from_acl(headers).await.is_err() {
+                let auth_status = check_auth_from_acl(headers).await?;
+                let product_owner = get_product_owner(db, product_id.as_str()).await?;
+
+                if product_owner != auth_status.sub {
+                    return Err(
+                        ExtendedError::new("Not Authorized!", Some(403.to_string())).build()
+                    );
+                }
+            }
+
+            let mut product_license_transaction = db
+                .query(
+                    "
+                BEGIN TRANSACTION;
+                LET $product = type::thing($product_id);
+                LET $license = type::thing($license_id);
+                IF $license.id IS NONE {
+                    THROW 'License not found!';
+                };
+                DELETE product_license WHERE in = $product AND out = $license;
+                LET $product_license = (RELATE $product -> product_license -> $license CONTENT {
+                    available: $available,
+                    price_factor: $price_factor
+                } RETURN AFTER);
+                RETURN $product_license[0];
+                COMMIT TRANSACTION;
+                ",
+                )
+                .bind(("product_id", format!("product:{}", product_id)))
+                .bind(("license_id", format!("license:{}", license_id)))
+                .bind(("available", available))
+                .bind(("price_factor", price_factor))
+                .await
+                .map_err(|e| Error::new(e.to_string()))?;
+
+            let response: Option<ProductLicense> =
+                product_license_transaction.take(0).map_err(|_e| {
+                    ExtendedError::new("License not found!", Some(404.to_string())).build()
+                })?;
+
+            match response {
+                Some(product_license) => Ok(product_license),
+                None => Err(ExtendedError::new(
+                    "Failed to set product license!",
+                    Some(500.to_string()),
+                )
+                .build()),
+            }
+        } else {
+            Err(ExtendedError::new("Not Authorized!", Some(403.to_string())).build())
+        }
+    }
+}
+
+/// Utility function to merge changes into an existing license
+async fn update_license(
+    db: &Extension<Arc<Surreal<Any>>>,
+    license_id: String,
+    changes: LicenseUpdate,
+) -> Result<License> {
+    let mut update_license_query = db
+        .query(
+            "
+            UPDATE ONLY type::thing($license_id) MERGE $changes RETURN AFTER
+            ",
+        )
+        .bind(("license_id", format!("license:{}", license_id)))
+        .bind(("changes", changes))
+        .await
+        .map_err(|e| Error::new(e.to_string()))?;
+
+    let response: Option<License> = update_license_query.take(0)?;
+
+    match response {
+        Some(license) => Ok(license),
+        None => Err(ExtendedError::new("License not found!", Some(404.to_string())).build()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        database::migrations::MIGRATIONS,
+        graphql::resolvers::{mutation::Mutation, query::Query},
+    };
+    use async_graphql::{EmptySubscription, Schema};
+    use axum::Extension;
+    use lib::{
+        config::AppConfig,
+        database::memory::connect_in_memory,
+        test_support::{auth_headers, FakeServices},
+    };
+
+    const CREATE_LICENSE: &str = r#"
+        mutation {
+            createLicense(license: { name: "Rustacean", priceFactor: 4, shortDescription: "Teams" }) {
+                name
+                priceFactor
+            }
+        }
+    "#;
+
+    #[tokio::test]
+    async fn only_admins_can_create_licenses() {
+        let mut config = AppConfig::local("products");
+        config.admin_user_ids = vec!["admin-1".to_string()];
+
+        let fake_services = FakeServices::start_with_config(config).await.unwrap();
+        let db = connect_in_memory(MIGRATIONS).await.unwrap();
+        let schema = Schema::build(Query::default(), Mutation::default(), EmptySubscription)
+            .data(Extension(db))
+            .data(Extension(fake_services.config()))
+            .data(auth_headers("token"))
+            .finish();
+
+        fake_services.acl.authenticate_as("user-1");
+        let response = schema.execute(CREATE_LICENSE).await;
+        assert_eq!(response.errors[0].message, "Not Authorized!");
+
+        fake_services.acl.authenticate_as("admin-1");
+        let response = schema.execute(CREATE_LICENSE).await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        assert_eq!(
+            response.data.into_json().unwrap()["createLicense"]["priceFactor"],
+            4
+        );
+
+        assert_eq!(fake_services.acl.check_auth.call_count(), 2);
+    }
+}
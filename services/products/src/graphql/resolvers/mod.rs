@@ -1,3 +1,4 @@
+pub mod licenses;
 pub mod mutation;
 pub mod query;
 pub mod products;
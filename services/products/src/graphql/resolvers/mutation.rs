@@ -1,6 +1,6 @@
 use async_graphql::MergedObject;
 
-use super::products::mutation::ProductMutation;
+use super::{licenses::mutation::LicenseMutation, products::mutation::ProductMutation};
 
 #[derive(MergedObject, Default)]
-pub struct Mutation(ProductMutation);
+pub struct Mutation(ProductMutation, LicenseMutation);
@@ -5,19 +5,24 @@ use async_graphql::{Context, Error, Object, Result};
 use axum::{http::HeaderMap, Extension};
 use hyper::header::{AUTHORIZATION, COOKIE};
 use lib::{
+    config,
+    events::{
+        outbox::{OutboxEntry, RECORD_EVENT},
+        DomainEvent,
+    },
     integration::{
         foreign_key::add_foreign_key_if_not_exists,
         grpc::clients::files_service::{files_service_client::FilesServiceClient, FileName},
     },
     middleware::auth::graphql::check_auth_from_acl,
+    observability::observe_grpc_call,
     utils::{
         custom_error::ExtendedError,
-        grpc::{create_grpc_client, AuthMetaData},
+        grpc::{create_grpc_client, AuthMetaData, GrpcChannel},
         models::{ForeignKey, UploadedFile, User},
     },
 };
-use surrealdb::{engine::remote::ws::Client, Surreal};
-use tonic::transport::Channel;
+use surrealdb::{engine::any::Any, sql::Id, Surreal};
 
 #[derive(Default)]
 pub struct ProductMutation;
@@ -25,7 +30,7 @@ pub struct ProductMutation;
 #[Object]
 impl ProductMutation {
     pub async fn create_product(&self, ctx: &Context<'_>, product: Product) -> Result<Product> {
-        let db = ctx.data::<Extension<Arc<Surreal<Client>>>>().unwrap();
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
 
         if let Some(headers) = ctx.data_opt::<HeaderMap>() {
             let auth_status = check_auth_from_acl(headers).await?;
@@ -36,7 +41,7 @@ impl ProductMutation {
                 foreign_key: auth_status.sub,
             };
             let owner_result =
-                add_foreign_key_if_not_exists::<Extension<Arc<Surreal<Client>>>, User>(
+                add_foreign_key_if_not_exists::<Extension<Arc<Surreal<Any>>>, User>(
                     db,
                     foreign_key,
                 )
@@ -44,17 +49,43 @@ impl ProductMutation {
 
             match owner_result {
                 Some(owner) => {
-                    let created_product: Product = db
-                        .create("product")
-                        .content(Product {
-                            owner: owner.id,
-                            ..product
-                        })
+                    let product_key = Id::rand().to_raw();
+                    let product_published = OutboxEntry::new(
+                        &config::get().service,
+                        DomainEvent::ProductPublished {
+                            product_id: product_key.clone(),
+                            owner_id: owner.user_id.clone(),
+                            name: product.name.clone(),
+                        },
+                    );
+
+                    let mut create_product_transaction = db
+                        .query(format!(
+                            "
+                        BEGIN TRANSACTION;
+                        LET $product = type::thing('product', $product_key);
+                        LET $new_product = (CREATE ONLY $product CONTENT $product_body);
+                        {}
+                        RETURN $new_product;
+                        COMMIT TRANSACTION;
+                        ",
+                            RECORD_EVENT
+                        ))
+                        .bind(("product_key", product_key))
+                        .bind((
+                            "product_body",
+                            Product {
+                                owner: owner.id,
+                                ..product
+                            },
+                        ))
+                        .bind(("event", product_published))
                         .await
-                        .map_err(|e| Error::new(e.to_string()))?
-                        .expect("Error creating product");
+                        .map_err(|e| Error::new(e.to_string()))?;
+
+                    let created_product: Option<Product> = create_product_transaction.take(0)?;
 
-                    return Ok(created_product);
+                    Ok(created_product.expect("Error creating product"))
                 }
                 None => Err(ExtendedError::new("Not Authorized!", Some(403.to_string())).build()),
             }
@@ -70,7 +101,7 @@ impl ProductMutation {
         license_id: String,
         file_name: String,
     ) -> Result<UploadedFile> {
-        let db = ctx.data::<Extension<Arc<Surreal<Client>>>>().unwrap();
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
 
         if let Some(headers) = ctx.data_opt::<HeaderMap>() {
             let auth_status = check_auth_from_acl(headers).await?;
@@ -86,19 +117,21 @@ impl ProductMutation {
                 constructed_grpc_request: Some(&mut request),
             };
 
-            let mut files_grpc_client =
-                create_grpc_client::<FileName, FilesServiceClient<Channel>>(
-                    "http://[::1]:50053",
-                    true,
-                    Some(auth_metadata),
-                )
-                .await
-                .map_err(|e| {
-                    tracing::error!("Failed to connect to Files service: {}", e);
-                    Error::new("Failed to connect to Files service".to_string())
-                })?;
-
-            let res = files_grpc_client.get_file_id(request).await?;
+            let mut files_grpc_client = create_grpc_client::<
+                FileName,
+                FilesServiceClient<GrpcChannel>,
+            >(
+                &config::get().endpoints.files, true, Some(auth_metadata)
+            )
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to connect to Files service: {}", e);
+                Error::new("Failed to connect to Files service".to_string())
+            })?;
+
+            let res =
+                observe_grpc_call("files", "GetFileId", files_grpc_client.get_file_id(request))
+                    .await?;
             let file_id: String = res.into_inner().file_id;
 
             let file_fk_body = ForeignKey {
@@ -114,13 +147,13 @@ impl ProductMutation {
             };
 
             let _internal_user = add_foreign_key_if_not_exists::<
-                Extension<Arc<Surreal<Client>>>,
+                Extension<Arc<Surreal<Any>>>,
                 User,
             >(db, user_fk_body)
             .await;
 
             let internal_file = add_foreign_key_if_not_exists::<
-                Extension<Arc<Surreal<Client>>>,
+                Extension<Arc<Surreal<Any>>>,
                 UploadedFile,
             >(db, file_fk_body)
             .await;
@@ -2,12 +2,15 @@ use std::sync::Arc;
 
 use async_graphql::{Context, Error, Object, Result};
 use axum::Extension;
-use lib::utils::custom_error::ExtendedError;
-use surrealdb::{engine::remote::ws::Client, Surreal};
+use lib::{error::AppError, utils::custom_error::ExtendedError};
+use surrealdb::{engine::any::Any, Surreal};
 
 use crate::{
     graphql::schemas::general::{License, Product},
-    utils::products::{get_license_price_factor, get_product_artifact, get_product_price},
+    utils::{
+        licenses::get_licenses,
+        products::{get_license_price_factor, get_product_artifact, get_product_price},
+    },
 };
 
 #[derive(Default)]
@@ -16,15 +19,17 @@ pub struct ProductQuery;
 #[Object]
 impl ProductQuery {
     async fn get_product_price(&self, ctx: &Context<'_>, product_id: String) -> Result<u64> {
-        let db = ctx.data::<Extension<Arc<Surreal<Client>>>>().unwrap();
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
 
         let response = get_product_price(db, product_id.as_str()).await?;
 
         Ok(response)
     }
 
+    /// Unpaginated, so each product's fields are assumed to be selected many times over
+    #[graphql(complexity = "10 * child_complexity")]
     async fn get_products(&self, ctx: &Context<'_>) -> Result<Vec<Product>> {
-        let db = ctx.data::<Extension<Arc<Surreal<Client>>>>().unwrap();
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
 
         let products: Vec<Product> = db
             .select("product")
@@ -34,12 +39,13 @@ impl ProductQuery {
         Ok(products)
     }
 
+    #[graphql(complexity = "product_ids.len() * child_complexity")]
     async fn get_products_by_ids(
         &self,
         ctx: &Context<'_>,
         product_ids: Vec<String>,
     ) -> Result<Vec<Product>> {
-        let db = ctx.data::<Extension<Arc<Surreal<Client>>>>().unwrap();
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
 
         let records = product_ids
             .iter()
@@ -49,7 +55,7 @@ impl ProductQuery {
         // let mut records_iter = records.iter().enumerate();
         let mut products: Vec<Product> = vec![];
 
-        for (_, record) in records.iter().enumerate() {
+        for record in records.iter() {
             // Clone the record to own the String value for the query
             let mut products_query = db
                 .query(
@@ -93,7 +99,7 @@ impl ProductQuery {
     }
 
     async fn get_product_by_slug(&self, ctx: &Context<'_>, slug: String) -> Result<Product> {
-        let db = ctx.data::<Extension<Arc<Surreal<Client>>>>().unwrap();
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
 
         let mut query_response = db
             .query(
@@ -113,13 +119,26 @@ impl ProductQuery {
         }
     }
 
+    /// Federation entity resolver, lets the gateway resolve the products referenced by other services
+    #[graphql(entity)]
+    async fn find_product_by_id(&self, ctx: &Context<'_>, id: String) -> Result<Option<Product>> {
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
+
+        let product: Option<Product> = db
+            .select(("product", id.as_str()))
+            .await
+            .map_err(AppError::database)?;
+
+        Ok(product)
+    }
+
     pub async fn get_product_artifact(
         &self,
         ctx: &Context<'_>,
         product_id: String,
         license_id: String,
     ) -> Result<String> {
-        let db = ctx.data::<Extension<Arc<Surreal<Client>>>>().unwrap();
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
 
         let response = get_product_artifact(db, product_id.as_str(), license_id.as_str()).await?;
 
@@ -130,28 +149,92 @@ impl ProductQuery {
         &self,
         ctx: &Context<'_>,
         license_id: String,
+        product_id: Option<String>,
     ) -> Result<u64> {
-        let db = ctx.data::<Extension<Arc<Surreal<Client>>>>().unwrap();
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
 
-        let response = get_license_price_factor(db, license_id.as_str()).await?;
+        let response =
+            get_license_price_factor(db, license_id.as_str(), product_id.as_deref()).await?;
 
         Ok(response)
     }
 
-    async fn get_licenses(&self, ctx: &Context<'_>) -> Result<Vec<License>> {
-        let db = ctx.data::<Extension<Arc<Surreal<Client>>>>().unwrap();
-
-        let mut external_product_ids_query = db
-            .query(
-                "
-            SELECT * FROM license ORDER BY price_factor ASC
-            ",
-            )
-            .await
-            .map_err(|e| Error::new(e.to_string()))?;
+    /// Resolver method to get the active licenses. When a product ID is given, only the licenses available
+    /// for the product are returned, with the product's price factors.
+    async fn get_licenses(
+        &self,
+        ctx: &Context<'_>,
+        product_id: Option<String>,
+    ) -> Result<Vec<License>> {
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
 
-        let response: Vec<License> = external_product_ids_query.take(0)?;
+        let response = get_licenses(db, product_id.as_deref(), false).await?;
 
         Ok(response)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        database::migrations::MIGRATIONS,
+        graphql::resolvers::{mutation::Mutation, query::Query},
+    };
+    use async_graphql::{EmptySubscription, Schema};
+    use axum::Extension;
+    use lib::database::memory::connect_in_memory;
+
+    #[tokio::test]
+    async fn resolves_product_entities_for_the_gateway() {
+        let db = connect_in_memory(MIGRATIONS).await.unwrap();
+        db.query(
+            "
+            CREATE user_id:seller SET user_id = 'seller-1';
+            CREATE product:dashboard CONTENT {
+                name: 'Dashboard',
+                price: 100,
+                preview_link: '',
+                details_file: '',
+                screenshot: '',
+                owner: user_id:seller,
+                framework: 'Yew',
+                application_layer: 'Frontend',
+                use_case: 'Dashboard'
+            };
+            ",
+        )
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+
+        let schema = Schema::build(Query::default(), Mutation::default(), EmptySubscription)
+            .enable_federation()
+            .data(Extension(db))
+            .finish();
+
+        let response = schema.execute("{ _service { sdl } }").await;
+        let data = response.data.into_json().unwrap();
+        let sdl = data["_service"]["sdl"].as_str().unwrap();
+        assert!(
+            sdl.contains(r#"type Product @key(fields: "id")"#),
+            "{}",
+            sdl
+        );
+
+        let response = schema
+            .execute(
+                r#"{
+                    _entities(representations: [{ __typename: "Product", id: "dashboard" }]) {
+                        ... on Product { name price }
+                    }
+                }"#,
+            )
+            .await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        assert_eq!(
+            response.data.into_json().unwrap()["_entities"][0]["name"],
+            "Dashboard"
+        );
+    }
+}
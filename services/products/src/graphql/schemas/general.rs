@@ -1,6 +1,8 @@
-use std::env;
+use std::sync::Arc;
 
-use async_graphql::{ComplexObject, Enum, InputObject, SimpleObject};
+use async_graphql::{ComplexObject, Context, Enum, InputObject, SimpleObject};
+use axum::Extension;
+use lib::config::AppConfig;
 // use reqwest::Client as ReqWestClient;
 use serde::{Deserialize, Serialize};
 use surrealdb::sql::Thing;
@@ -31,9 +33,16 @@ impl Product {
         self.id.as_ref().map(|t| &t.id).expect("id").to_raw()
     }
 
-    async fn product_details(&self) -> String {
-        let files_service =
-            env::var("FILES_SERVICE").expect("Missing the FILES_SERVICE environment variable.");
+    /// Fetches and renders the details file from the files service, the most expensive field
+    #[graphql(complexity = 50)]
+    async fn product_details(&self, ctx: &Context<'_>) -> String {
+        let config = ctx.data::<Extension<Arc<AppConfig>>>().unwrap();
+
+        // Required for the products service, so only missing when the config wasn't validated
+        let Some(files_service) = config.endpoints.files_http.as_ref() else {
+            tracing::error!("The files service HTTP endpoint is not configured");
+            return "".into();
+        };
 
         let file_url = format!("{}/view/{}", files_service, self.details_file);
 
@@ -111,38 +120,71 @@ pub enum UseCase {
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, SimpleObject, InputObject)]
-#[graphql(input_name = "ProductLicenseArtifactInput")]
+#[graphql(input_name = "LicenseInput")]
 #[graphql(complex)]
-pub struct ProductLicenseArtifact {
+pub struct License {
     #[graphql(skip)]
     pub id: Option<Thing>,
-    #[graphql(skip)]
-    pub license: Option<Thing>,
-    pub product_id: String,
-    pub license_id: String,
-    pub file_id: String,
+    pub name: String,
+    pub price_factor: u64,
+    pub short_description: String,
+    #[graphql(skip_input)]
+    pub active: Option<bool>,
 }
 
 #[ComplexObject]
-impl ProductLicenseArtifact {
+impl License {
     async fn id(&self) -> String {
         self.id.as_ref().map(|t| &t.id).expect("id").to_raw()
     }
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize, InputObject)]
+pub struct LicenseUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub price_factor: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub short_description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active: Option<bool>,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
 #[graphql(complex)]
-pub struct License {
+pub struct ProductLicense {
     #[graphql(skip)]
     pub id: Option<Thing>,
-    pub name: String,
-    pub price_factor: u64,
-    pub short_description: String,
+    #[graphql(skip)]
+    #[serde(rename = "in")]
+    pub product: Option<Thing>,
+    #[graphql(skip)]
+    #[serde(rename = "out")]
+    pub license: Option<Thing>,
+    pub available: bool,
+    pub price_factor: Option<u64>,
 }
 
 #[ComplexObject]
-impl License {
+impl ProductLicense {
     async fn id(&self) -> String {
         self.id.as_ref().map(|t| &t.id).expect("id").to_raw()
     }
+
+    async fn product_id(&self) -> String {
+        self.product
+            .as_ref()
+            .map(|t| &t.id)
+            .expect("product")
+            .to_raw()
+    }
+
+    async fn license_id(&self) -> String {
+        self.license
+            .as_ref()
+            .map(|t| &t.id)
+            .expect("license")
+            .to_raw()
+    }
 }
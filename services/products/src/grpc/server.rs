@@ -2,10 +2,10 @@ use std::sync::Arc;
 
 use products_service::{
     products_service_server::ProductsService, GetLicensePriceFactorArgs,
-    GetLicensePriceFactorResponse, ProductArtifact, ProductId, ProductPrice,
+    GetLicensePriceFactorResponse, ProductArtifact, ProductId, ProductOwner, ProductPrice,
     RetrieveProductArtifactArgs,
 };
-use surrealdb::{engine::remote::ws::Client, Surreal};
+use surrealdb::{engine::any::Any, Surreal};
 use tonic::{Request, Response, Status};
 
 use crate::utils;
@@ -15,11 +15,11 @@ pub mod products_service {
 }
 
 pub struct ProductsServiceImplementation {
-    db: Arc<Surreal<Client>>,
+    db: Arc<Surreal<Any>>,
 }
 
 impl ProductsServiceImplementation {
-    pub fn new(db: Arc<Surreal<Client>>) -> Self {
+    pub fn new(db: Arc<Surreal<Any>>) -> Self {
         Self { db }
     }
 }
@@ -34,7 +34,7 @@ impl ProductsService for ProductsServiceImplementation {
             .await
         {
             Ok(price) => Ok(Response::new(ProductPrice { price })),
-            Err(_e) => Err(Status::internal("Failed")),
+            Err(e) => Err(e.into()),
         }
     }
 
@@ -52,7 +52,7 @@ impl ProductsService for ProductsServiceImplementation {
         .await
         {
             Ok(artifact) => Ok(Response::new(ProductArtifact { artifact })),
-            Err(_e) => Err(Status::internal("Failed")),
+            Err(e) => Err(e.into()),
         }
     }
 
@@ -60,16 +60,32 @@ impl ProductsService for ProductsServiceImplementation {
         &self,
         request: Request<GetLicensePriceFactorArgs>,
     ) -> Result<Response<GetLicensePriceFactorResponse>, Status> {
+        let args = request.into_inner();
+        let product_id = Some(args.product_id.as_str()).filter(|id| !id.is_empty());
+
         match utils::products::get_license_price_factor(
             &self.db,
-            request.into_inner().license_id.as_str(),
+            args.license_id.as_str(),
+            product_id,
         )
         .await
         {
             Ok(price_factor) => Ok(Response::new(GetLicensePriceFactorResponse {
                 price_factor,
             })),
-            Err(_e) => Err(Status::internal("Failed")),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn get_product_owner(
+        &self,
+        request: Request<ProductId>,
+    ) -> Result<Response<ProductOwner>, Status> {
+        match utils::products::get_product_owner(&self.db, request.into_inner().product_id.as_str())
+            .await
+        {
+            Ok(owner_id) => Ok(Response::new(ProductOwner { owner_id })),
+            Err(e) => Err(e.into()),
         }
     }
 }
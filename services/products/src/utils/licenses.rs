@@ -0,0 +1,161 @@
+use lib::{error::AppError, utils::custom_traits::AsSurrealClient};
+use std::collections::HashMap;
+use surrealdb::Response;
+
+use crate::graphql::schemas::general::{License, ProductLicense};
+
+/// The licenses every fresh database starts with. (record ID, name, price_factor, short_description)
+const DEFAULT_LICENSES: [(&str, &str, u64, &str); 3] = [
+    ("rusty", "Rusty", 1, "Rusty (1 project sold to 1 client)"),
+    (
+        "rustier",
+        "Rustier",
+        2,
+        "Rustier (1 project sold to 1 client, DevOps, unlimited personal)",
+    ),
+    (
+        "rustiest",
+        "Rustiest",
+        9,
+        "Rustiest (unlimited projects, DevOps, SaaS)",
+    ),
+];
+
+/// Utility function to create the default licenses that don't exist yet. They have fixed record IDs, so
+/// changes made through the API, renames included, survive restarts. Databases seeded before the IDs were
+/// fixed keep their licenses, which are recognized by name.
+pub async fn seed_licenses<T: Clone + AsSurrealClient>(db: &T) -> Result<(), AppError> {
+    for (key, name, price_factor, short_description) in DEFAULT_LICENSES {
+        db.as_client()
+            .query(
+                "
+                LET $license = type::thing('license', $key);
+                IF !record::exists($license) AND array::len(SELECT id FROM license WHERE name = $name) = 0 {
+                    CREATE $license CONTENT {
+                        name: $name,
+                        price_factor: $price_factor,
+                        short_description: $short_description
+                    };
+                };
+                ",
+            )
+            .bind(("key", key))
+            .bind(("name", name))
+            .bind(("price_factor", price_factor))
+            .bind(("short_description", short_description))
+            .await
+            .and_then(Response::check)
+            .map_err(AppError::database)?;
+    }
+
+    // Licenses created before the active column existed
+    db.as_client()
+        .query("UPDATE license SET active = true WHERE active IS NONE")
+        .await
+        .map_err(AppError::database)?;
+
+    Ok(())
+}
+
+/// Utility function to get the licenses, cheapest first. When a product ID is given, licenses that are
+/// unavailable for the product are left out and the product's price factor overrides are applied.
+pub async fn get_licenses<T: Clone + AsSurrealClient>(
+    db: &T,
+    product_id: Option<&str>,
+    include_inactive: bool,
+) -> Result<Vec<License>, AppError> {
+    let mut licenses_query = db
+        .as_client()
+        .query(
+            "
+            SELECT * FROM license WHERE $include_inactive OR active = true ORDER BY price_factor ASC;
+            IF $product_id IS NONE {
+                []
+            } ELSE {
+                (SELECT * FROM product_license WHERE in = type::thing($product_id))
+            };
+            ",
+        )
+        .bind(("include_inactive", include_inactive))
+        .bind(("product_id", product_id.map(|id| format!("product:{}", id))))
+        .await
+        .map_err(AppError::database)?;
+
+    let licenses: Vec<License> = licenses_query.take(0).map_err(AppError::deserialization)?;
+
+    let product_licenses: Vec<ProductLicense> =
+        licenses_query.take(1).map_err(AppError::deserialization)?;
+
+    let overrides: HashMap<String, ProductLicense> = product_licenses
+        .into_iter()
+        .filter_map(|product_license| {
+            product_license
+                .license
+                .as_ref()
+                .map(|license| license.to_raw())
+                .map(|license| (license, product_license))
+        })
+        .collect();
+
+    let mut licenses: Vec<License> = licenses
+        .into_iter()
+        .filter_map(|license| {
+            let license_id = license.id.as_ref().map(|t| t.to_raw()).unwrap_or_default();
+
+            match overrides.get(&license_id) {
+                Some(product_license) if !product_license.available => None,
+                Some(product_license) => Some(License {
+                    price_factor: product_license.price_factor.unwrap_or(license.price_factor),
+                    ..license
+                }),
+                None => Some(license),
+            }
+        })
+        .collect();
+
+    licenses.sort_by_key(|license| license.price_factor);
+
+    Ok(licenses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::migrations::MIGRATIONS;
+    use lib::database::memory::connect_in_memory;
+
+    #[tokio::test]
+    async fn seeding_is_idempotent_and_keeps_changes() {
+        let db = connect_in_memory(MIGRATIONS).await.unwrap();
+
+        seed_licenses(&db).await.unwrap();
+        db.query("UPDATE license SET price_factor = 3 WHERE name = 'Rustier'")
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+        seed_licenses(&db).await.unwrap();
+
+        let licenses = get_licenses(&db, None, true).await.unwrap();
+        let names: Vec<&str> = licenses.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(names, vec!["Rusty", "Rustier", "Rustiest"]);
+        assert_eq!(licenses[1].price_factor, 3);
+    }
+
+    #[tokio::test]
+    async fn renamed_licenses_are_not_seeded_again() {
+        let db = connect_in_memory(MIGRATIONS).await.unwrap();
+
+        seed_licenses(&db).await.unwrap();
+        db.query("UPDATE license:rusty SET name = 'Rusty Solo'")
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+        seed_licenses(&db).await.unwrap();
+
+        let licenses = get_licenses(&db, None, true).await.unwrap();
+        let names: Vec<&str> = licenses.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(names, vec!["Rusty Solo", "Rustier", "Rustiest"]);
+    }
+}
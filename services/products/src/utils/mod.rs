@@ -1 +1,2 @@
+pub mod licenses;
 pub mod products;
@@ -1,25 +1,24 @@
-use lib::utils::{custom_traits::AsSurrealClient, models::UploadedFile};
-use std::io::{Error, ErrorKind};
+use lib::{
+    error::AppError,
+    utils::{custom_traits::AsSurrealClient, models::UploadedFile},
+};
 
-use crate::graphql::schemas::general::{License, Product};
+use crate::graphql::schemas::general::Product;
 
 /// Utility function to get the price of a product by its ID.
 pub async fn get_product_price<T: Clone + AsSurrealClient>(
     db: &T,
     product_id: &str,
-) -> Result<u64, Error> {
+) -> Result<u64, AppError> {
     let response: Option<Product> = db
         .as_client()
         .select(("product", product_id))
         .await
-        .map_err(|e| {
-            tracing::error!("DB Query Failed: {}", e);
-            Error::new(ErrorKind::Other, "DB Query Failed")
-        })?;
+        .map_err(AppError::database)?;
 
     match response {
         Some(product) => Ok(product.price),
-        None => Err(Error::new(ErrorKind::InvalidInput, "Invalid Request!")),
+        None => Err(AppError::Validation("Invalid Request!".into())),
     }
 }
 
@@ -28,7 +27,7 @@ pub async fn get_product_artifact<T: Clone + AsSurrealClient>(
     db: &T,
     product_id: &str,
     license_id: &str,
-) -> Result<String, Error> {
+) -> Result<String, AppError> {
     let mut product_artifact_query = db
         .as_client()
         .query(
@@ -45,55 +44,207 @@ pub async fn get_product_artifact<T: Clone + AsSurrealClient>(
         .bind(("product_id", format!("product:{}", product_id)))
         .bind(("license_id", format!("license:{}", license_id)))
         .await
-        .map_err(|e| {
-            tracing::error!("DB Query Failed: {}", e);
-            Error::new(ErrorKind::Other, "DB Query Failed")
-        })?;
+        .map_err(AppError::database)?;
 
-    let response: Option<UploadedFile> = product_artifact_query.take(0).map_err(|e| {
-        tracing::error!("Deserialization Failed: {}", e);
-        Error::new(ErrorKind::Other, "Deserialization Failed")
-    })?;
+    let response: Option<UploadedFile> = product_artifact_query
+        .take(0)
+        .map_err(AppError::deserialization)?;
 
     match response {
         Some(file) => Ok(file.file_id),
-        None => Err(Error::new(
-            ErrorKind::NotFound,
-            "Product Artifact Not Found",
-        )),
+        None => Err(AppError::NotFound("Product Artifact Not Found".into())),
     }
 }
 
+/// Utility function to get the price factor of a license. When a product ID is given, the product's price
+/// factor override applies. Inactive licenses and licenses that are unavailable for the product are not found.
 pub async fn get_license_price_factor<T: Clone + AsSurrealClient>(
     db: &T,
     license_id: &str,
-) -> Result<u64, Error> {
+    product_id: Option<&str>,
+) -> Result<u64, AppError> {
     let mut get_license_query = db
         .as_client()
         .query(
             "
             BEGIN TRANSACTION;
-            LET $license_thing = type::thing($license_id);
-
-            LET $license = SELECT * FROM ONLY $license_thing LIMIT 1;
-            RETURN $license;
+            LET $license = (SELECT * FROM ONLY type::thing($license_id) LIMIT 1);
+            LET $override = IF $product_id IS NONE {
+                NONE
+            } ELSE {
+                (SELECT * FROM ONLY product_license WHERE in = type::thing($product_id) AND out = $license.id LIMIT 1)
+            };
+            LET $price_factor = IF $license IS NONE OR $license.active = false OR $override.available = false {
+                NONE
+            } ELSE {
+                $override.price_factor ?? $license.price_factor
+            };
+            RETURN $price_factor;
             COMMIT TRANSACTION;
             ",
         )
         .bind(("license_id", format!("license:{}", license_id)))
+        .bind(("product_id", product_id.map(|id| format!("product:{}", id))))
+        .await
+        .map_err(AppError::database)?;
+
+    let response: Option<u64> = get_license_query
+        .take(0)
+        .map_err(AppError::deserialization)?;
+
+    match response {
+        Some(price_factor) => Ok(price_factor),
+        None => Err(AppError::NotFound("License Not Found".into())),
+    }
+}
+
+/// Utility function to get the external user ID of a product's owner.
+pub async fn get_product_owner<T: Clone + AsSurrealClient>(
+    db: &T,
+    product_id: &str,
+) -> Result<String, AppError> {
+    let mut product_owner_query = db
+        .as_client()
+        .query(
+            "
+            SELECT VALUE owner.user_id FROM ONLY type::thing($product_id)
+            ",
+        )
+        .bind(("product_id", format!("product:{}", product_id)))
         .await
-        .map_err(|e| {
-            tracing::error!("DB Query Failed: {}", e);
-            Error::new(ErrorKind::Other, "DB Query Failed")
-        })?;
+        .map_err(AppError::database)?;
 
-    let response: Option<License> = get_license_query.take(0).map_err(|e| {
-        tracing::error!("Deserialization Failed: {}", e);
-        Error::new(ErrorKind::Other, "Deserialization Failed")
-    })?;
+    let response: Option<String> = product_owner_query
+        .take(0)
+        .map_err(AppError::deserialization)?;
 
     match response {
-        Some(license) => Ok(license.price_factor),
-        None => Err(Error::new(ErrorKind::NotFound, "License Not Found")),
+        Some(owner_id) => Ok(owner_id),
+        None => Err(AppError::NotFound("Product Owner Not Found".into())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::migrations::MIGRATIONS;
+    use lib::database::memory::connect_in_memory;
+
+    const PRODUCT_WITH_ARTIFACT: &str = "
+        CREATE user_id:seller SET user_id = 'seller-1';
+        CREATE product:dashboard CONTENT {
+            name: 'Dashboard',
+            price: 100,
+            preview_link: '',
+            details_file: '',
+            screenshot: '',
+            owner: user_id:seller,
+            framework: 'Yew',
+            application_layer: 'Frontend',
+            use_case: 'Dashboard'
+        };
+        CREATE license:rusty CONTENT { name: 'Rusty', price_factor: 1, short_description: '' };
+        CREATE license:rustiest CONTENT { name: 'Rustiest', price_factor: 9, short_description: '' };
+        CREATE file_id:file SET file_id = 'file-1';
+        RELATE product:dashboard->product_license_artifact->file_id:file CONTENT { license: license:rusty };
+    ";
+
+    #[tokio::test]
+    async fn gets_the_artifact_for_a_license() {
+        let db = connect_in_memory(MIGRATIONS).await.unwrap();
+        db.query(PRODUCT_WITH_ARTIFACT)
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+
+        let artifact = get_product_artifact(&db, "dashboard", "rusty")
+            .await
+            .unwrap();
+        assert_eq!(artifact, "file-1");
+
+        let missing = get_product_artifact(&db, "dashboard", "rustiest").await;
+        assert_eq!(missing.unwrap_err().code(), "NOT_FOUND");
+    }
+
+    #[tokio::test]
+    async fn gets_the_price_and_owner_of_a_product() {
+        let db = connect_in_memory(MIGRATIONS).await.unwrap();
+        db.query(PRODUCT_WITH_ARTIFACT)
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+
+        assert_eq!(get_product_price(&db, "dashboard").await.unwrap(), 100);
+        assert_eq!(
+            get_product_owner(&db, "dashboard").await.unwrap(),
+            "seller-1"
+        );
+    }
+
+    #[tokio::test]
+    async fn applies_product_price_factor_overrides() {
+        let db = connect_in_memory(MIGRATIONS).await.unwrap();
+        db.query(PRODUCT_WITH_ARTIFACT)
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+
+        assert_eq!(
+            get_license_price_factor(&db, "rustiest", Some("dashboard"))
+                .await
+                .unwrap(),
+            9
+        );
+
+        db.query("RELATE product:dashboard->product_license->license:rustiest CONTENT { price_factor: 5 }")
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+
+        assert_eq!(
+            get_license_price_factor(&db, "rustiest", Some("dashboard"))
+                .await
+                .unwrap(),
+            5
+        );
+        assert_eq!(
+            get_license_price_factor(&db, "rustiest", None)
+                .await
+                .unwrap(),
+            9
+        );
+    }
+
+    #[tokio::test]
+    async fn unavailable_and_inactive_licenses_have_no_price_factor() {
+        let db = connect_in_memory(MIGRATIONS).await.unwrap();
+        db.query(PRODUCT_WITH_ARTIFACT)
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+
+        db.query(
+            "
+            RELATE product:dashboard->product_license->license:rusty CONTENT { available: false };
+            UPDATE license:rustiest SET active = false;
+            ",
+        )
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+
+        assert!(get_license_price_factor(&db, "rusty", Some("dashboard"))
+            .await
+            .is_err());
+        assert!(get_license_price_factor(&db, "rusty", None).await.is_ok());
+        assert!(get_license_price_factor(&db, "rustiest", None)
+            .await
+            .is_err());
     }
 }
@@ -1 +1 @@
-pub mod connection;
+pub mod migrations;
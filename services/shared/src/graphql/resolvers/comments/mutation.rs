@@ -4,6 +4,11 @@ use crate::graphql::schemas::comments::Comment;
 use async_graphql::{Context, Error, Object, Result};
 use axum::{http::HeaderMap, Extension};
 use lib::{
+    config,
+    events::{
+        outbox::{OutboxEntry, RECORD_EVENT},
+        DomainEvent,
+    },
     integration::foreign_key::add_foreign_key_if_not_exists,
     middleware::auth::graphql::check_auth_from_acl,
     utils::{
@@ -11,7 +16,7 @@ use lib::{
         models::{ForeignKey, Product, User},
     },
 };
-use surrealdb::{engine::remote::ws::Client, Surreal};
+use surrealdb::{engine::any::Any, sql::Id, Surreal};
 
 #[derive(Default)]
 pub struct CommentMutation;
@@ -24,46 +29,61 @@ impl CommentMutation {
         comment: Comment,
         product_id: String,
     ) -> Result<Vec<Comment>> {
-        let db = ctx.data::<Extension<Arc<Surreal<Client>>>>().unwrap();
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
 
         if let Some(headers) = ctx.data_opt::<HeaderMap>() {
-            let auth_status = check_auth_from_acl(&headers).await?;
+            let auth_status = check_auth_from_acl(headers).await?;
 
             let user_fk = ForeignKey {
                 table: "user_id".into(),
                 column: "user_id".into(),
-                foreign_key: auth_status.sub,
+                foreign_key: auth_status.sub.clone(),
             };
 
             let product_fk = ForeignKey {
                 table: "product_id".into(),
                 column: "product_id".into(),
-                foreign_key: product_id,
+                foreign_key: product_id.clone(),
             };
 
             let author_result =
-                add_foreign_key_if_not_exists::<Extension<Arc<Surreal<Client>>>, User>(db, user_fk)
+                add_foreign_key_if_not_exists::<Extension<Arc<Surreal<Any>>>, User>(db, user_fk)
                     .await;
             let commented_product_result = add_foreign_key_if_not_exists::<
-                Extension<Arc<Surreal<Client>>>,
+                Extension<Arc<Surreal<Any>>>,
                 Product,
             >(db, product_fk)
             .await;
 
+            let comment_key = Id::rand().to_raw();
+            let comment_posted = OutboxEntry::new(
+                &config::get().service,
+                DomainEvent::CommentPosted {
+                    comment_id: comment_key.clone(),
+                    product_id,
+                    author_id: auth_status.sub,
+                },
+            );
+
             let mut post_comment_transaction = db
-                .query(
+                .query(format!(
                     "
                 BEGIN TRANSACTION;
                 LET $user = type::thing($user_id);
                 LET $product = type::thing($product_id);
-                LET $new_comment = (RELATE $user -> comment -> $product CONTENT {
+                LET $comment = type::thing('comment', $comment_key);
+                LET $new_comment = (RELATE $user -> $comment -> $product CONTENT {{
                     content: $comment_body.content,
-                } RETURN content);
+                }} RETURN id, content, out.product_id AS ext_product_id);
+                {}
                 RETURN $new_comment;
                 COMMIT TRANSACTION;
                 ",
-                )
+                    RECORD_EVENT
+                ))
                 .bind(("comment_body", comment))
+                .bind(("comment_key", comment_key))
+                .bind(("event", comment_posted))
                 .bind((
                     "user_id",
                     format!(
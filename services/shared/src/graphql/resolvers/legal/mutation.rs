@@ -0,0 +1,96 @@
+use std::sync::Arc;
+
+use crate::{
+    graphql::schemas::legal::{LegalDocument, LegalDocumentKind},
+    utils::legal::get_legal_document,
+};
+use async_graphql::{Context, Error, Object, Result};
+use axum::{http::HeaderMap, Extension};
+use lib::{
+    integration::foreign_key::add_foreign_key_if_not_exists,
+    middleware::auth::graphql::check_auth_from_acl,
+    utils::{
+        custom_error::ExtendedError,
+        models::{ForeignKey, User},
+    },
+};
+use surrealdb::{engine::any::Any, Surreal};
+
+#[derive(Default)]
+pub struct LegalMutation;
+
+#[Object]
+impl LegalMutation {
+    /// Resolver method to record that the current user accepted a specific version of a legal document
+    pub async fn accept_document(
+        &self,
+        ctx: &Context<'_>,
+        kind: LegalDocumentKind,
+        version: u32,
+    ) -> Result<LegalDocument> {
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
+
+        if let Some(headers) = ctx.data_opt::<HeaderMap>() {
+            let auth_status = check_auth_from_acl(headers).await?;
+
+            let document = match get_legal_document(db, kind, Some(version)).await? {
+                Some(document) => document,
+                None => {
+                    return Err(
+                        ExtendedError::new("Document not found!", Some(404.to_string())).build(),
+                    )
+                }
+            };
+
+            let user_fk = ForeignKey {
+                table: "user_id".into(),
+                column: "user_id".into(),
+                foreign_key: auth_status.sub,
+            };
+
+            let user_result =
+                add_foreign_key_if_not_exists::<Extension<Arc<Surreal<Any>>>, User>(db, user_fk)
+                    .await;
+
+            db.query(
+                "
+                BEGIN TRANSACTION;
+                LET $user = type::thing($user_id);
+                LET $document = type::thing($document_id);
+                IF array::len(SELECT id FROM accepted_document WHERE in = $user AND out = $document) = 0 {
+                    RELATE $user -> accepted_document -> $document;
+                };
+                COMMIT TRANSACTION;
+                ",
+            )
+            .bind((
+                "user_id",
+                format!(
+                    "user_id:{}",
+                    user_result
+                        .unwrap()
+                        .id
+                        .as_ref()
+                        .map(|t| &t.id)
+                        .expect("id")
+                        .to_raw()
+                ),
+            ))
+            .bind((
+                "document_id",
+                format!(
+                    "legal_document:{}",
+                    document.id.as_ref().map(|t| &t.id).expect("id").to_raw()
+                ),
+            ))
+            .await
+            .map_err(|e| Error::new(e.to_string()))?
+            .check()
+            .map_err(|e| Error::new(e.to_string()))?;
+
+            Ok(document)
+        } else {
+            Err(ExtendedError::new("Not Authorized!", Some(403.to_string())).build())
+        }
+    }
+}
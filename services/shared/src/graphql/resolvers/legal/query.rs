@@ -0,0 +1,49 @@
+use std::sync::Arc;
+
+use async_graphql::{resolver_utils::EnumType, Context, Object, Result};
+use axum::Extension;
+use lib::utils::custom_error::ExtendedError;
+use surrealdb::{engine::any::Any, Surreal};
+
+use crate::{
+    graphql::schemas::legal::{LegalDocument, LegalDocumentKind},
+    utils::legal::get_legal_document,
+};
+
+#[derive(Default)]
+pub struct LegalQuery;
+
+#[Object]
+impl LegalQuery {
+    /// Resolver method to get a legal document. Returns the latest version if no version is specified.
+    async fn get_legal_document(
+        &self,
+        ctx: &Context<'_>,
+        kind: LegalDocumentKind,
+        version: Option<u32>,
+    ) -> Result<LegalDocument> {
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
+
+        let response = get_legal_document(db, kind, version).await?;
+
+        match response {
+            Some(document) => Ok(document),
+            None => Err(ExtendedError::new("Document not found!", Some(404.to_string())).build()),
+        }
+    }
+
+    /// Resolver method to get the latest version of every legal document
+    async fn get_legal_documents(&self, ctx: &Context<'_>) -> Result<Vec<LegalDocument>> {
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
+
+        let mut documents = vec![];
+
+        for kind in LegalDocumentKind::items() {
+            if let Some(document) = get_legal_document(db, kind.value, None).await? {
+                documents.push(document);
+            }
+        }
+
+        Ok(documents)
+    }
+}
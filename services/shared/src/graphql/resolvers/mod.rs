@@ -2,3 +2,6 @@ pub mod mutation;
 pub mod query;
 pub mod ratings;
 pub mod comments;
+pub mod questions;
+pub mod legal;
+pub mod notifications;
@@ -1,6 +1,16 @@
 use async_graphql::MergedObject;
 
-use super::{comments::mutation::CommentMutation, ratings::mutation::RatingMutation};
+use super::{
+    comments::mutation::CommentMutation, legal::mutation::LegalMutation,
+    notifications::mutation::NotificationMutation, questions::mutation::QuestionMutation,
+    ratings::mutation::RatingMutation,
+};
 
 #[derive(MergedObject, Default)]
-pub struct Mutation(CommentMutation, RatingMutation);
+pub struct Mutation(
+    CommentMutation,
+    RatingMutation,
+    QuestionMutation,
+    LegalMutation,
+    NotificationMutation,
+);
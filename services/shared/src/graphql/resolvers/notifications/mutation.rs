@@ -0,0 +1,61 @@
+use std::sync::Arc;
+
+use async_graphql::{Context, Object, Result};
+use axum::{http::HeaderMap, Extension};
+use lib::{middleware::auth::graphql::check_auth_from_acl, utils::custom_error::ExtendedError};
+use surrealdb::{engine::any::Any, Surreal};
+
+use crate::{
+    graphql::schemas::notifications::{
+        Notification, NotificationCategory, NotificationChannel, NotificationPreference,
+    },
+    utils::notifications::{mark_notifications_read, set_notification_preference},
+};
+
+#[derive(Default)]
+pub struct NotificationMutation;
+
+#[Object]
+impl NotificationMutation {
+    /// Resolver method to mark the current user's notifications read. Returns them.
+    pub async fn mark_read(
+        &self,
+        ctx: &Context<'_>,
+        notification_ids: Vec<String>,
+    ) -> Result<Vec<Notification>> {
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
+
+        if let Some(headers) = ctx.data_opt::<HeaderMap>() {
+            let auth_status = check_auth_from_acl(headers).await?;
+
+            Ok(mark_notifications_read(db, &auth_status.sub, notification_ids).await?)
+        } else {
+            Err(ExtendedError::new("Not Authorized!", Some(403.to_string())).build())
+        }
+    }
+
+    /// Resolver method to turn the current user's notifications of a category on or off on a channel
+    pub async fn set_notification_preference(
+        &self,
+        ctx: &Context<'_>,
+        channel: NotificationChannel,
+        category: NotificationCategory,
+        enabled: bool,
+    ) -> Result<NotificationPreference> {
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
+
+        if let Some(headers) = ctx.data_opt::<HeaderMap>() {
+            let auth_status = check_auth_from_acl(headers).await?;
+
+            let preference = NotificationPreference {
+                channel,
+                category,
+                enabled,
+            };
+
+            Ok(set_notification_preference(db, &auth_status.sub, preference).await?)
+        } else {
+            Err(ExtendedError::new("Not Authorized!", Some(403.to_string())).build())
+        }
+    }
+}
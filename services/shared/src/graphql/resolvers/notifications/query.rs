@@ -0,0 +1,62 @@
+use std::sync::Arc;
+
+use async_graphql::{Context, Object, Result};
+use axum::{http::HeaderMap, Extension};
+use lib::{middleware::auth::graphql::check_auth_from_acl, utils::custom_error::ExtendedError};
+use surrealdb::{engine::any::Any, Surreal};
+
+use crate::{
+    graphql::schemas::notifications::{Notification, NotificationPreference},
+    utils::notifications::{get_notification_preferences, get_notifications},
+};
+
+/// The most notifications returned at once
+const MAX_NOTIFICATIONS: u32 = 100;
+
+#[derive(Default)]
+pub struct NotificationQuery;
+
+#[Object]
+impl NotificationQuery {
+    /// Resolver method to get the current user's notifications, newest first
+    async fn get_my_notifications(
+        &self,
+        ctx: &Context<'_>,
+        unread_only: Option<bool>,
+        limit: Option<u32>,
+    ) -> Result<Vec<Notification>> {
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
+
+        if let Some(headers) = ctx.data_opt::<HeaderMap>() {
+            let auth_status = check_auth_from_acl(headers).await?;
+
+            let notifications = get_notifications(
+                db,
+                &auth_status.sub,
+                unread_only.unwrap_or(false),
+                limit.unwrap_or(20).min(MAX_NOTIFICATIONS),
+            )
+            .await?;
+
+            Ok(notifications)
+        } else {
+            Err(ExtendedError::new("Not Authorized!", Some(403.to_string())).build())
+        }
+    }
+
+    /// Resolver method to get the current user's notification preference for every channel and category
+    async fn get_my_notification_preferences(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<NotificationPreference>> {
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
+
+        if let Some(headers) = ctx.data_opt::<HeaderMap>() {
+            let auth_status = check_auth_from_acl(headers).await?;
+
+            Ok(get_notification_preferences(db, &auth_status.sub).await?)
+        } else {
+            Err(ExtendedError::new("Not Authorized!", Some(403.to_string())).build())
+        }
+    }
+}
@@ -1,5 +1,10 @@
 use async_graphql::{MergedObject, Object};
 
+use super::{
+    legal::query::LegalQuery, notifications::query::NotificationQuery,
+    questions::query::QuestionQuery,
+};
+
 #[derive(Default)]
 pub struct EmptyQuery;
 
@@ -11,4 +16,4 @@ impl EmptyQuery {
 }
 
 #[derive(MergedObject, Default)]
-pub struct Query(EmptyQuery);
+pub struct Query(EmptyQuery, QuestionQuery, LegalQuery, NotificationQuery);
@@ -0,0 +1,305 @@
+use std::sync::Arc;
+
+use crate::graphql::schemas::questions::{Answer, Question};
+use async_graphql::{Context, Error, Object, Result};
+use axum::{http::HeaderMap, Extension};
+use hyper::header::{AUTHORIZATION, COOKIE};
+use lib::{
+    config,
+    error::AppError,
+    integration::{
+        foreign_key::add_foreign_key_if_not_exists,
+        grpc::clients::products_service::{products_service_client::ProductsServiceClient, ProductId},
+    },
+    middleware::auth::graphql::check_auth_from_acl,
+    observability::observe_grpc_call,
+    utils::{
+        custom_error::ExtendedError,
+        grpc::{create_grpc_client, AuthMetaData, GrpcChannel},
+        models::{ForeignKey, Product, User},
+    },
+};
+use surrealdb::{engine::any::Any, Surreal};
+
+#[derive(Default)]
+pub struct QuestionMutation;
+
+#[Object]
+impl QuestionMutation {
+    /// Resolver method to ask a (pre-sales) question about a product
+    pub async fn ask_question(
+        &self,
+        ctx: &Context<'_>,
+        question: Question,
+        product_id: String,
+    ) -> Result<Question> {
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
+
+        if let Some(headers) = ctx.data_opt::<HeaderMap>() {
+            let auth_status = check_auth_from_acl(headers).await?;
+
+            let user_fk = ForeignKey {
+                table: "user_id".into(),
+                column: "user_id".into(),
+                foreign_key: auth_status.sub,
+            };
+
+            let product_fk = ForeignKey {
+                table: "product_id".into(),
+                column: "product_id".into(),
+                foreign_key: product_id,
+            };
+
+            let author_result =
+                add_foreign_key_if_not_exists::<Extension<Arc<Surreal<Any>>>, User>(db, user_fk)
+                    .await;
+            let product_result = add_foreign_key_if_not_exists::<
+                Extension<Arc<Surreal<Any>>>,
+                Product,
+            >(db, product_fk)
+            .await;
+
+            let mut ask_question_transaction = db
+                .query(
+                    "
+                BEGIN TRANSACTION;
+                LET $user = type::thing($user_id);
+                LET $product = type::thing($product_id);
+                LET $new_question = (RELATE $user -> question -> $product CONTENT {
+                    content: $question_body.content,
+                } RETURN AFTER);
+                LET $response = (SELECT *, in.user_id AS author_id, [] AS answers FROM $new_question);
+                RETURN $response[0];
+                COMMIT TRANSACTION;
+                ",
+                )
+                .bind(("question_body", question))
+                .bind((
+                    "user_id",
+                    format!(
+                        "user_id:{}",
+                        author_result
+                            .unwrap()
+                            .id
+                            .as_ref()
+                            .map(|t| &t.id)
+                            .expect("id")
+                            .to_raw()
+                    ),
+                ))
+                .bind((
+                    "product_id",
+                    format!(
+                        "product_id:{}",
+                        product_result
+                            .unwrap()
+                            .id
+                            .as_ref()
+                            .map(|t| &t.id)
+                            .expect("id")
+                            .to_raw()
+                    ),
+                ))
+                .await
+                .map_err(|e| Error::new(e.to_string()))?;
+
+            let response: Option<Question> = ask_question_transaction.take(0)?;
+
+            match response {
+                Some(question) => Ok(question),
+                None => Err(
+                    ExtendedError::new("Failed to post question!", Some(500.to_string())).build(),
+                ),
+            }
+        } else {
+            Err(ExtendedError::new("Not Authorized!", Some(403.to_string())).build())
+        }
+    }
+
+    /// Resolver method to answer a question. Only the owner of the product can answer.
+    pub async fn answer_question(
+        &self,
+        ctx: &Context<'_>,
+        answer: Answer,
+        question_id: String,
+    ) -> Result<Answer> {
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
+
+        if let Some(headers) = ctx.data_opt::<HeaderMap>() {
+            let auth_status = check_auth_from_acl(headers).await?;
+
+            let mut question_product_query = db
+                .query(
+                    "
+                SELECT VALUE out.product_id FROM ONLY type::thing($question_id)
+                ",
+                )
+                .bind(("question_id", format!("question:{}", question_id)))
+                .await
+                .map_err(|e| Error::new(e.to_string()))?;
+
+            let external_product_id: Option<String> = question_product_query.take(0)?;
+
+            let external_product_id = match external_product_id {
+                Some(product_id) => product_id,
+                None => {
+                    return Err(
+                        ExtendedError::new("Question not found!", Some(404.to_string())).build(),
+                    )
+                }
+            };
+
+            let auth_header = headers.get(AUTHORIZATION);
+            let cookie_header = headers.get(COOKIE);
+
+            let mut request = tonic::Request::new(ProductId {
+                product_id: external_product_id,
+            });
+
+            let auth_metadata: AuthMetaData<ProductId> = AuthMetaData {
+                auth_header,
+                cookie_header,
+                constructed_grpc_request: Some(&mut request),
+            };
+
+            let mut products_grpc_client =
+                create_grpc_client::<ProductId, ProductsServiceClient<GrpcChannel>>(
+                    &config::get().endpoints.products,
+                    true,
+                    Some(auth_metadata),
+                )
+                .await
+                .map_err(|e| {
+                    tracing::error!("Failed to connect to Products service: {}", e);
+                    ExtendedError::new(
+                        "Failed to connect to Products service",
+                        Some(500.to_string()),
+                    )
+                    .build()
+                })?;
+
+            let product_owner = observe_grpc_call(
+                "products",
+                "GetProductOwner",
+                products_grpc_client.get_product_owner(request),
+            )
+            .await?
+            .into_inner()
+            .owner_id;
+
+            if product_owner != auth_status.sub {
+                return Err(ExtendedError::new("Not Authorized!", Some(403.to_string())).build());
+            }
+
+            let user_fk = ForeignKey {
+                table: "user_id".into(),
+                column: "user_id".into(),
+                foreign_key: auth_status.sub,
+            };
+
+            let author_result =
+                add_foreign_key_if_not_exists::<Extension<Arc<Surreal<Any>>>, User>(db, user_fk)
+                    .await;
+
+            let mut answer_question_transaction = db
+                .query(
+                    "
+                BEGIN TRANSACTION;
+                LET $user = type::thing($user_id);
+                LET $question = type::thing($question_id);
+                LET $new_answer = (RELATE $user -> answer -> $question CONTENT {
+                    content: $answer_body.content,
+                } RETURN AFTER);
+                RETURN $new_answer[0];
+                COMMIT TRANSACTION;
+                ",
+                )
+                .bind(("answer_body", answer))
+                .bind((
+                    "user_id",
+                    format!(
+                        "user_id:{}",
+                        author_result
+                            .unwrap()
+                            .id
+                            .as_ref()
+                            .map(|t| &t.id)
+                            .expect("id")
+                            .to_raw()
+                    ),
+                ))
+                .bind(("question_id", format!("question:{}", question_id)))
+                .await
+                .map_err(|e| Error::new(e.to_string()))?;
+
+            let response: Option<Answer> = answer_question_transaction.take(0)?;
+
+            match response {
+                Some(answer) => Ok(answer),
+                None => Err(
+                    ExtendedError::new("Failed to post answer!", Some(500.to_string())).build(),
+                ),
+            }
+        } else {
+            Err(ExtendedError::new("Not Authorized!", Some(403.to_string())).build())
+        }
+    }
+
+    /// Resolver method to mark an answer as the accepted one. Only the author of the question can accept.
+    pub async fn accept_answer(&self, ctx: &Context<'_>, answer_id: String) -> Result<Answer> {
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
+
+        if let Some(headers) = ctx.data_opt::<HeaderMap>() {
+            let auth_status = check_auth_from_acl(headers).await?;
+
+            let mut question_author_query = db
+                .query("SELECT VALUE out.in.user_id FROM ONLY type::thing($answer_id)")
+                .bind(("answer_id", format!("answer:{}", answer_id)))
+                .await
+                .map_err(AppError::database)?;
+
+            let question_author: Option<String> = question_author_query
+                .take(0)
+                .map_err(AppError::deserialization)?;
+
+            match question_author {
+                None => {
+                    return Err(
+                        ExtendedError::new("Answer not found!", Some(404.to_string())).build(),
+                    )
+                }
+                Some(author) if author != auth_status.sub => {
+                    return Err(ExtendedError::new("Not Authorized!", Some(403.to_string())).build())
+                }
+                Some(_) => {}
+            }
+
+            let mut accept_answer_transaction = db
+                .query(
+                    "
+                BEGIN TRANSACTION;
+                LET $answer = type::thing($answer_id);
+                LET $question = (SELECT VALUE out FROM ONLY $answer);
+                UPDATE answer SET accepted = false WHERE out = $question AND id != $answer;
+                LET $accepted = (UPDATE ONLY $answer SET accepted = true RETURN AFTER);
+                RETURN $accepted;
+                COMMIT TRANSACTION;
+                ",
+                )
+                .bind(("answer_id", format!("answer:{}", answer_id)))
+                .await
+                .map_err(AppError::database)?;
+
+            let response: Option<Answer> = accept_answer_transaction
+                .take(0)
+                .map_err(AppError::database)?;
+
+            match response {
+                Some(answer) => Ok(answer),
+                None => Err(ExtendedError::new("Answer not found!", Some(404.to_string())).build()),
+            }
+        } else {
+            Err(ExtendedError::new("Not Authorized!", Some(403.to_string())).build())
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use std::sync::Arc;
+
+use async_graphql::{Context, Error, Object, Result};
+use axum::Extension;
+use surrealdb::{engine::any::Any, Surreal};
+
+use crate::graphql::schemas::questions::PaginatedQuestions;
+
+const DEFAULT_PAGE_SIZE: u32 = 10;
+const MAX_PAGE_SIZE: u32 = 50;
+
+#[derive(Default)]
+pub struct QuestionQuery;
+
+#[Object]
+impl QuestionQuery {
+    /// Resolver method to get the questions (and their answers) of a product, newest first
+    async fn get_product_questions(
+        &self,
+        ctx: &Context<'_>,
+        product_id: String,
+        page: Option<u32>,
+        page_size: Option<u32>,
+    ) -> Result<PaginatedQuestions> {
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
+
+        let page_size = page_size.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+        let start = page.unwrap_or(1).saturating_sub(1) * page_size;
+
+        let mut product_questions_query = db
+            .query(
+                "
+                BEGIN TRANSACTION;
+                LET $product = (SELECT VALUE id FROM ONLY product_id WHERE product_id = $product_id LIMIT 1);
+                LET $questions = (
+                    SELECT
+                        *,
+                        in.user_id AS author_id,
+                        (SELECT * FROM answer WHERE out = $parent.id ORDER BY accepted DESC, created_at ASC) AS answers
+                    FROM question
+                    WHERE out = $product
+                    ORDER BY created_at DESC
+                    LIMIT $page_size
+                    START $start
+                );
+                LET $total = count(SELECT id FROM question WHERE out = $product);
+                RETURN {
+                    total: $total,
+                    questions: $questions
+                };
+                COMMIT TRANSACTION;
+                ",
+            )
+            .bind(("product_id", product_id))
+            .bind(("page_size", page_size))
+            .bind(("start", start))
+            .await
+            .map_err(|e| Error::new(e.to_string()))?;
+
+        let response: Option<PaginatedQuestions> = product_questions_query.take(0)?;
+
+        Ok(response.unwrap_or(PaginatedQuestions {
+            total: 0,
+            questions: vec![],
+        }))
+    }
+}
@@ -4,6 +4,11 @@ use crate::graphql::schemas::ratings::Rating;
 use async_graphql::{Context, Error, Object, Result};
 use axum::{http::HeaderMap, Extension};
 use lib::{
+    config,
+    events::{
+        outbox::{OutboxEntry, RECORD_EVENT},
+        DomainEvent,
+    },
     integration::foreign_key::add_foreign_key_if_not_exists,
     middleware::auth::graphql::check_auth_from_acl,
     utils::{
@@ -11,7 +16,7 @@ use lib::{
         models::{ForeignKey, Product, User},
     },
 };
-use surrealdb::{engine::remote::ws::Client, Surreal};
+use surrealdb::{engine::any::Any, Surreal};
 
 #[derive(Default)]
 pub struct RatingMutation;
@@ -24,46 +29,58 @@ impl RatingMutation {
         rating: Rating,
         product_id: String,
     ) -> Result<Vec<Rating>> {
-        let db = ctx.data::<Extension<Arc<Surreal<Client>>>>().unwrap();
+        let db = ctx.data::<Extension<Arc<Surreal<Any>>>>().unwrap();
 
         if let Some(headers) = ctx.data_opt::<HeaderMap>() {
-            let auth_status = check_auth_from_acl(&headers).await?;
+            let auth_status = check_auth_from_acl(headers).await?;
 
             let user_fk = ForeignKey {
                 table: "user_id".into(),
                 column: "user_id".into(),
-                foreign_key: auth_status.sub,
+                foreign_key: auth_status.sub.clone(),
             };
 
             let product_fk = ForeignKey {
                 table: "product_id".into(),
                 column: "product_id".into(),
-                foreign_key: product_id,
+                foreign_key: product_id.clone(),
             };
 
             let author_result =
-                add_foreign_key_if_not_exists::<Extension<Arc<Surreal<Client>>>, User>(db, user_fk)
+                add_foreign_key_if_not_exists::<Extension<Arc<Surreal<Any>>>, User>(db, user_fk)
                     .await;
             let rated_product_result = add_foreign_key_if_not_exists::<
-                Extension<Arc<Surreal<Client>>>,
+                Extension<Arc<Surreal<Any>>>,
                 Product,
             >(db, product_fk)
             .await;
 
+            let rating_changed = OutboxEntry::new(
+                &config::get().service,
+                DomainEvent::RatingChanged {
+                    product_id,
+                    user_id: auth_status.sub,
+                    rating_value: rating.rating_value,
+                },
+            );
+
             let mut rate_product_transaction = db
-                .query(
+                .query(format!(
                     "
                 BEGIN TRANSACTION;
                 LET $user = type::thing($user_id);
                 LET $product = type::thing($product_id);
-                LET $new_rating = (RELATE $user -> rating -> $product CONTENT {
+                LET $new_rating = (RELATE $user -> rating -> $product CONTENT {{
                     rating_value: $rating_body.rating_value,
-                } RETURN rating_value);
+                }} RETURN rating_value);
+                {}
                 RETURN $new_rating;
                 COMMIT TRANSACTION;
                 ",
-                )
+                    RECORD_EVENT
+                ))
                 .bind(("rating_body", rating))
+                .bind(("event", rating_changed))
                 .bind((
                     "user_id",
                     format!(
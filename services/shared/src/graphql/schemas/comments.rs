@@ -1,4 +1,5 @@
 use async_graphql::{ComplexObject, InputObject, SimpleObject};
+use lib::utils::models::ProductReference;
 use serde::{Deserialize, Serialize};
 use surrealdb::sql::Thing;
 
@@ -9,6 +10,9 @@ pub struct Comment {
     #[graphql(skip)]
     pub id: Option<Thing>,
     pub content: String,
+    #[graphql(skip)]
+    #[serde(default, skip_serializing)]
+    pub ext_product_id: Option<String>,
 }
 
 #[ComplexObject]
@@ -16,4 +20,11 @@ impl Comment {
     async fn id(&self) -> String {
         self.id.as_ref().map(|t| &t.id).expect("id").to_raw()
     }
+
+    /// The commented product from the products service, resolved by the federation gateway
+    async fn product(&self) -> Option<ProductReference> {
+        self.ext_product_id
+            .clone()
+            .map(|id| ProductReference { id })
+    }
 }
@@ -0,0 +1,41 @@
+use async_graphql::{ComplexObject, Enum, SimpleObject};
+use serde::{Deserialize, Serialize};
+use surrealdb::sql::Thing;
+
+#[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
+#[graphql(complex)]
+pub struct LegalDocument {
+    #[graphql(skip)]
+    pub id: Option<Thing>,
+    pub kind: LegalDocumentKind,
+    pub version: u32,
+    #[graphql(skip)]
+    pub content: String,
+    pub requires_acceptance: bool,
+    pub published_at: Option<String>,
+}
+
+#[ComplexObject]
+impl LegalDocument {
+    async fn id(&self) -> String {
+        self.id.as_ref().map(|t| &t.id).expect("id").to_raw()
+    }
+
+    /// The document rendered from markdown to HTML
+    async fn content_html(&self) -> String {
+        markdown::to_html_with_options(self.content.as_str(), &markdown::Options::gfm())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Enum, Copy, Eq, PartialEq)]
+pub enum LegalDocumentKind {
+    #[graphql(name = "TemplateLicense")]
+    TemplateLicense,
+    #[graphql(name = "RefundPolicy")]
+    RefundPolicy,
+    #[graphql(name = "CookiePolicy")]
+    CookiePolicy,
+    #[graphql(name = "Faqs")]
+    Faqs,
+}
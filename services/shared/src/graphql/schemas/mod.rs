@@ -1,2 +1,5 @@
 pub mod ratings;
 pub mod comments;
+pub mod questions;
+pub mod legal;
+pub mod notifications;
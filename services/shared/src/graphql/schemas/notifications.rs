@@ -0,0 +1,72 @@
+use async_graphql::{ComplexObject, Enum, SimpleObject};
+use serde::{Deserialize, Serialize};
+use surrealdb::sql::Thing;
+
+#[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
+#[graphql(complex)]
+pub struct Notification {
+    #[graphql(skip)]
+    pub id: Option<Thing>,
+    pub category: NotificationCategory,
+    pub title: String,
+    pub body: String,
+    pub link: Option<String>,
+    pub read_at: Option<String>,
+    pub created_at: Option<String>,
+}
+
+#[ComplexObject]
+impl Notification {
+    async fn id(&self) -> String {
+        self.id.as_ref().map(|t| &t.id).expect("id").to_raw()
+    }
+
+    async fn read(&self) -> bool {
+        self.read_at.is_some()
+    }
+}
+
+/// Whether the user gets the notifications of a category on a channel
+#[derive(Clone, Debug, Serialize, Deserialize, SimpleObject, Eq, PartialEq)]
+pub struct NotificationPreference {
+    pub channel: NotificationChannel,
+    pub category: NotificationCategory,
+    pub enabled: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Enum, Copy, Eq, PartialEq)]
+pub enum NotificationChannel {
+    #[graphql(name = "Email")]
+    Email,
+    /// The notification center, see `getMyNotifications`
+    #[graphql(name = "InApp")]
+    InApp,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Enum, Copy, Eq, PartialEq)]
+pub enum NotificationCategory {
+    #[graphql(name = "Orders")]
+    Orders,
+    /// Replies to the user's comments
+    #[graphql(name = "CommentReplies")]
+    CommentReplies,
+    /// Updates to the templates the user purchased
+    #[graphql(name = "ProductUpdates")]
+    ProductUpdates,
+    #[graphql(name = "Marketing")]
+    Marketing,
+}
+
+impl TryFrom<i32> for NotificationCategory {
+    type Error = &'static str;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(NotificationCategory::Orders),
+            1 => Ok(NotificationCategory::CommentReplies),
+            2 => Ok(NotificationCategory::ProductUpdates),
+            3 => Ok(NotificationCategory::Marketing),
+            _ => Err("Invalid category"),
+        }
+    }
+}
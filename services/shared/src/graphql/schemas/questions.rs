@@ -0,0 +1,57 @@
+use async_graphql::{ComplexObject, InputObject, SimpleObject};
+use lib::utils::models::UserReference;
+use serde::{Deserialize, Serialize};
+use surrealdb::sql::Thing;
+
+#[derive(Clone, Debug, Serialize, Deserialize, SimpleObject, InputObject)]
+#[graphql(input_name = "QuestionInput")]
+#[graphql(complex)]
+pub struct Question {
+    #[graphql(skip)]
+    pub id: Option<Thing>,
+    pub content: String,
+    #[graphql(skip_input)]
+    pub author_id: Option<String>,
+    #[graphql(skip_input)]
+    pub answers: Option<Vec<Answer>>,
+    #[graphql(skip_input)]
+    pub created_at: Option<String>,
+}
+
+#[ComplexObject]
+impl Question {
+    async fn id(&self) -> String {
+        self.id.as_ref().map(|t| &t.id).expect("id").to_raw()
+    }
+
+    /// The user who asked, resolved by the federation gateway
+    async fn author(&self) -> Option<UserReference> {
+        self.author_id.clone().map(|id| UserReference { id })
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, SimpleObject, InputObject)]
+#[graphql(input_name = "AnswerInput")]
+#[graphql(complex)]
+pub struct Answer {
+    #[graphql(skip)]
+    pub id: Option<Thing>,
+    pub content: String,
+    #[graphql(skip_input)]
+    pub accepted: Option<bool>,
+    #[graphql(skip_input)]
+    pub created_at: Option<String>,
+}
+
+#[ComplexObject]
+impl Answer {
+    async fn id(&self) -> String {
+        self.id.as_ref().map(|t| &t.id).expect("id").to_raw()
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
+pub struct PaginatedQuestions {
+    pub total: u64,
+    pub questions: Vec<Question>,
+}
@@ -0,0 +1,177 @@
+use std::sync::Arc;
+
+use shared_service::{
+    shared_service_server::SharedService, NotificationRequest, NotificationResponse,
+    TermsAcceptanceStatus, UserId,
+};
+use surrealdb::{engine::any::Any, Surreal};
+use tonic::{Request, Response, Status};
+
+use crate::utils::{self, notifications::NewNotification};
+
+pub mod shared_service {
+    tonic::include_proto!("shared");
+}
+
+pub struct SharedServiceImplementation {
+    db: Arc<Surreal<Any>>,
+}
+
+impl SharedServiceImplementation {
+    pub fn new(db: Arc<Surreal<Any>>) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait::async_trait]
+impl SharedService for SharedServiceImplementation {
+    async fn has_accepted_latest_terms(
+        &self,
+        request: Request<UserId>,
+    ) -> Result<Response<TermsAcceptanceStatus>, Status> {
+        match utils::legal::has_accepted_latest_terms(
+            &self.db,
+            request.into_inner().user_id.as_str(),
+        )
+        .await
+        {
+            Ok(accepted) => Ok(Response::new(TermsAcceptanceStatus { accepted })),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn post_notification(
+        &self,
+        request: Request<NotificationRequest>,
+    ) -> Result<Response<NotificationResponse>, Status> {
+        let payload = request.into_inner();
+        let category = payload
+            .category
+            .try_into()
+            .map_err(|_| Status::invalid_argument("Invalid category"))?;
+
+        let new_notification = NewNotification {
+            user_id: payload.user_id,
+            category,
+            title: payload.title,
+            body: payload.body,
+            link: Some(payload.link).filter(|link| !link.is_empty()),
+        };
+
+        match utils::notifications::post_notification(&self.db, new_notification).await {
+            Ok(posted) => Ok(Response::new(NotificationResponse {
+                notification_id: posted
+                    .notification
+                    .and_then(|notification| notification.id)
+                    .map(|id| id.id.to_raw())
+                    .unwrap_or_default(),
+                email_enabled: posted.email_enabled,
+            })),
+            Err(e) => {
+                tracing::error!("Error posting notification: {:?}", e);
+                Err(e.into())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{database::migrations::MIGRATIONS, utils::notifications::get_notifications};
+    use lib::{
+        database::memory::connect_in_memory,
+        integration::grpc::clients::shared_service::{
+            shared_service_client::SharedServiceClient,
+            NotificationCategory as ClientNotificationCategory,
+            NotificationRequest as ClientNotificationRequest, UserId as ClientUserId,
+        },
+        middleware::auth::grpc::AuthMiddleware,
+        test_support::FakeServices,
+        utils::grpc::{create_grpc_client, GrpcChannel},
+    };
+    use shared_service::shared_service_server::SharedServiceServer;
+    use tonic::{
+        transport::{Endpoint, Server},
+        Code,
+    };
+    use tonic_middleware::MiddlewareLayer;
+
+    fn payment_received() -> ClientNotificationRequest {
+        ClientNotificationRequest {
+            user_id: "buyer-1".to_string(),
+            category: ClientNotificationCategory::Orders.into(),
+            title: "Payment received".to_string(),
+            body: "Your templates are ready for download".to_string(),
+            link: "".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn only_services_post_notifications() {
+        let mut fake_services = FakeServices::start().await.unwrap();
+        let db = connect_in_memory(MIGRATIONS).await.unwrap();
+
+        let incoming = fake_services.serve_endpoint("shared").await.unwrap();
+        let shared_endpoint = fake_services.config().endpoints.shared.clone();
+        tokio::spawn(
+            Server::builder()
+                .layer(MiddlewareLayer::new(AuthMiddleware))
+                .add_service(SharedServiceServer::new(SharedServiceImplementation::new(
+                    db.clone(),
+                )))
+                .serve_with_incoming_shutdown(incoming, fake_services.shutdown_signal()),
+        );
+
+        let mut shared_grpc_client = create_grpc_client::<
+            ClientNotificationRequest,
+            SharedServiceClient<GrpcChannel>,
+        >(&shared_endpoint, false, None)
+        .await
+        .unwrap();
+
+        let posted = shared_grpc_client
+            .post_notification(payment_received())
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(!posted.notification_id.is_empty());
+        assert!(posted.email_enabled);
+
+        let notifications = get_notifications(&db, "buyer-1", true, 10).await.unwrap();
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0].link, None);
+
+        // Users can't notify each other
+        let mut user_client = SharedServiceClient::new(
+            Endpoint::from_shared(shared_endpoint)
+                .unwrap()
+                .connect_lazy(),
+        );
+        let mut request = tonic::Request::new(payment_received());
+        request
+            .metadata_mut()
+            .insert("authorization", "Bearer user-token".parse().unwrap());
+        request
+            .metadata_mut()
+            .insert("cookie", "oauth_client=;t=user-token".parse().unwrap());
+        let status = user_client.post_notification(request).await.unwrap_err();
+        assert_eq!(status.code(), Code::PermissionDenied);
+
+        // Nor look up whether another user accepted the terms
+        let mut request = tonic::Request::new(ClientUserId {
+            user_id: "buyer-1".to_string(),
+        });
+        request
+            .metadata_mut()
+            .insert("authorization", "Bearer user-token".parse().unwrap());
+        request
+            .metadata_mut()
+            .insert("cookie", "oauth_client=;t=user-token".parse().unwrap());
+        let status = user_client
+            .has_accepted_latest_terms(request)
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::PermissionDenied);
+    }
+}
@@ -0,0 +1,139 @@
+use lib::{error::AppError, utils::custom_traits::AsSurrealClient};
+use surrealdb::sql::Thing;
+
+use crate::graphql::schemas::legal::{LegalDocument, LegalDocumentKind};
+
+/// The markdown files in the repo root are the source of truth for the legal documents.
+/// (kind, content, requires_acceptance at checkout)
+const LEGAL_DOCUMENT_SOURCES: [(LegalDocumentKind, &str, bool); 4] = [
+    (
+        LegalDocumentKind::TemplateLicense,
+        include_str!("../../../../template_license.md"),
+        true,
+    ),
+    (
+        LegalDocumentKind::RefundPolicy,
+        include_str!("../../../../refund_policy.md"),
+        true,
+    ),
+    (
+        LegalDocumentKind::CookiePolicy,
+        include_str!("../../../../cookie_policy.md"),
+        false,
+    ),
+    (
+        LegalDocumentKind::Faqs,
+        include_str!("../../../../faqs.md"),
+        false,
+    ),
+];
+
+/// Utility function to publish a new version of each legal document whose content has changed since the last version.
+pub async fn seed_legal_documents<T: Clone + AsSurrealClient>(db: &T) -> Result<(), AppError> {
+    for (kind, content, requires_acceptance) in LEGAL_DOCUMENT_SOURCES {
+        let latest = get_legal_document(db, kind, None).await?;
+
+        let next_version = match latest {
+            Some(document) if document.content == content => continue,
+            Some(document) => document.version + 1,
+            None => 1,
+        };
+
+        db.as_client()
+            .query(
+                "
+                CREATE legal_document CONTENT {
+                    kind: $kind,
+                    version: $version,
+                    content: $content,
+                    requires_acceptance: $requires_acceptance
+                }
+                ",
+            )
+            .bind(("kind", kind))
+            .bind(("version", next_version))
+            .bind(("content", content))
+            .bind(("requires_acceptance", requires_acceptance))
+            .await
+            .map_err(AppError::database)?;
+
+        tracing::info!("Published {:?} version {}", kind, next_version);
+    }
+
+    Ok(())
+}
+
+/// Utility function to get a legal document by its kind. Returns the latest version if no version is specified.
+pub async fn get_legal_document<T: Clone + AsSurrealClient>(
+    db: &T,
+    kind: LegalDocumentKind,
+    version: Option<u32>,
+) -> Result<Option<LegalDocument>, AppError> {
+    let mut legal_document_query = db
+        .as_client()
+        .query(
+            "
+            SELECT * FROM ONLY legal_document
+            WHERE kind = $kind AND ($version IS NONE OR version = $version)
+            ORDER BY version DESC
+            LIMIT 1
+            ",
+        )
+        .bind(("kind", kind))
+        .bind(("version", version))
+        .await
+        .map_err(AppError::database)?;
+
+    let response: Option<LegalDocument> = legal_document_query
+        .take(0)
+        .map_err(AppError::deserialization)?;
+
+    Ok(response)
+}
+
+/// Utility function to check whether a user has accepted the latest version of every document required at checkout.
+pub async fn has_accepted_latest_terms<T: Clone + AsSurrealClient>(
+    db: &T,
+    external_user_id: &str,
+) -> Result<bool, AppError> {
+    let mut acceptance_query = db
+        .as_client()
+        .query(
+            "
+            SELECT * FROM legal_document ORDER BY version DESC;
+            SELECT VALUE out FROM accepted_document WHERE in.user_id = $user_id;
+            ",
+        )
+        .bind(("user_id", external_user_id.to_string()))
+        .await
+        .map_err(AppError::database)?;
+
+    let documents: Vec<LegalDocument> = acceptance_query
+        .take(0)
+        .map_err(AppError::deserialization)?;
+
+    let accepted_documents: Vec<Thing> = acceptance_query
+        .take(1)
+        .map_err(AppError::deserialization)?;
+
+    let mut seen_kinds: Vec<LegalDocumentKind> = vec![];
+    let mut latest_required: Vec<Thing> = vec![];
+
+    // Documents are ordered by version, so the first one of each kind is the latest
+    for document in documents {
+        if seen_kinds.contains(&document.kind) {
+            continue;
+        }
+        seen_kinds.push(document.kind);
+
+        if document.requires_acceptance {
+            if let Some(id) = document.id {
+                latest_required.push(id);
+            }
+        }
+    }
+
+    Ok(latest_required
+        .iter()
+        .all(|required| accepted_documents.contains(required)))
+}
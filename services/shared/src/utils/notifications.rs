@@ -0,0 +1,355 @@
+use async_graphql::resolver_utils::EnumType;
+use lib::{
+    error::AppError,
+    integration::foreign_key::add_foreign_key_if_not_exists,
+    utils::{
+        custom_traits::AsSurrealClient,
+        models::{ForeignKey, User},
+    },
+};
+
+use surrealdb::Response;
+
+use crate::graphql::schemas::notifications::{
+    Notification, NotificationCategory, NotificationChannel, NotificationPreference,
+};
+
+/// A notification posted by another service
+#[derive(Clone, Debug)]
+pub struct NewNotification {
+    /// The ACL user ID of the recipient
+    pub user_id: String,
+    pub category: NotificationCategory,
+    pub title: String,
+    pub body: String,
+    pub link: Option<String>,
+}
+
+/// What became of a posted notification
+#[derive(Clone, Debug)]
+pub struct PostedNotification {
+    /// None when the user turned off the in-app notifications of the category
+    pub notification: Option<Notification>,
+    /// Whether the user accepts emails of the category. Sending them is up to the poster.
+    pub email_enabled: bool,
+}
+
+/// The preference of the users who didn't choose one. Marketing is opt-in, on every channel.
+pub fn default_preference(category: NotificationCategory) -> bool {
+    category != NotificationCategory::Marketing
+}
+
+/// Utility function to get the internal `user_id` record of a user, added if the shared service hasn't seen them yet
+async fn get_user_record<T: Clone + AsSurrealClient>(
+    db: &T,
+    external_user_id: &str,
+) -> Result<String, AppError> {
+    let user_fk = ForeignKey {
+        table: "user_id".into(),
+        column: "user_id".into(),
+        foreign_key: external_user_id.to_string(),
+    };
+
+    let user = add_foreign_key_if_not_exists::<T, User>(db, user_fk)
+        .await
+        .ok_or_else(|| AppError::Database("Failed to add the user".into()))?;
+
+    Ok(format!(
+        "user_id:{}",
+        user.id.as_ref().map(|t| &t.id).expect("id").to_raw()
+    ))
+}
+
+/// Utility function to get a user's preference for every channel and category, the defaults filled in.
+pub async fn get_notification_preferences<T: Clone + AsSurrealClient>(
+    db: &T,
+    external_user_id: &str,
+) -> Result<Vec<NotificationPreference>, AppError> {
+    let user_id = get_user_record(db, external_user_id).await?;
+
+    preferences_of_user_record(db, &user_id).await
+}
+
+/// The preferences of a `user_id` record, e.g. "user_id:abc", the defaults filled in
+async fn preferences_of_user_record<T: Clone + AsSurrealClient>(
+    db: &T,
+    user_id: &str,
+) -> Result<Vec<NotificationPreference>, AppError> {
+    let mut preferences_query = db
+        .as_client()
+        .query(
+            "SELECT channel, category, enabled FROM notification_preference WHERE user = type::thing($user_id)",
+        )
+        .bind(("user_id", user_id.to_string()))
+        .await
+        .map_err(AppError::database)?;
+
+    let chosen: Vec<NotificationPreference> = preferences_query
+        .take(0)
+        .map_err(AppError::deserialization)?;
+
+    let mut preferences = vec![];
+
+    for channel in NotificationChannel::items() {
+        for category in NotificationCategory::items() {
+            let enabled = chosen
+                .iter()
+                .find(|preference| {
+                    preference.channel == channel.value && preference.category == category.value
+                })
+                .map(|preference| preference.enabled)
+                .unwrap_or_else(|| default_preference(category.value));
+
+            preferences.push(NotificationPreference {
+                channel: channel.value,
+                category: category.value,
+                enabled,
+            });
+        }
+    }
+
+    Ok(preferences)
+}
+
+/// Utility function to turn the notifications of a category on or off on a channel
+pub async fn set_notification_preference<T: Clone + AsSurrealClient>(
+    db: &T,
+    external_user_id: &str,
+    preference: NotificationPreference,
+) -> Result<NotificationPreference, AppError> {
+    let user_id = get_user_record(db, external_user_id).await?;
+
+    // One record per user, channel and category
+    db.as_client()
+        .query(
+            "
+            LET $user = type::thing($user_id);
+            UPSERT type::thing('notification_preference', [$user, $channel, $category]) SET
+                user = $user,
+                channel = $channel,
+                category = $category,
+                enabled = $enabled;
+            ",
+        )
+        .bind(("user_id", user_id))
+        .bind(("channel", preference.channel))
+        .bind(("category", preference.category))
+        .bind(("enabled", preference.enabled))
+        .await
+        .and_then(Response::check)
+        .map_err(AppError::database)?;
+
+    Ok(preference)
+}
+
+/// Utility function to post a notification to the user's notification center, if they didn't turn off its category.
+pub async fn post_notification<T: Clone + AsSurrealClient>(
+    db: &T,
+    new_notification: NewNotification,
+) -> Result<PostedNotification, AppError> {
+    let user_id = get_user_record(db, &new_notification.user_id).await?;
+    let preferences = preferences_of_user_record(db, &user_id).await?;
+    let is_enabled = |channel: NotificationChannel| {
+        preferences.iter().any(|preference| {
+            preference.channel == channel
+                && preference.category == new_notification.category
+                && preference.enabled
+        })
+    };
+
+    let email_enabled = is_enabled(NotificationChannel::Email);
+
+    if !is_enabled(NotificationChannel::InApp) {
+        return Ok(PostedNotification {
+            notification: None,
+            email_enabled,
+        });
+    }
+
+    let mut create_notification_query = db
+        .as_client()
+        .query(
+            "
+            CREATE ONLY notification CONTENT {
+                recipient: type::thing($user_id),
+                category: $category,
+                title: $title,
+                body: $body,
+                link: $link
+            }
+            ",
+        )
+        .bind(("user_id", user_id))
+        .bind(("category", new_notification.category))
+        .bind(("title", new_notification.title))
+        .bind(("body", new_notification.body))
+        .bind(("link", new_notification.link))
+        .await
+        .map_err(AppError::database)?;
+
+    let notification: Option<Notification> = create_notification_query
+        .take(0)
+        .map_err(AppError::deserialization)?;
+
+    Ok(PostedNotification {
+        notification,
+        email_enabled,
+    })
+}
+
+/// Utility function to get a user's notifications, newest first
+pub async fn get_notifications<T: Clone + AsSurrealClient>(
+    db: &T,
+    external_user_id: &str,
+    unread_only: bool,
+    limit: u32,
+) -> Result<Vec<Notification>, AppError> {
+    let user_id = get_user_record(db, external_user_id).await?;
+
+    let mut notifications_query = db
+        .as_client()
+        .query(
+            "
+            SELECT * FROM notification
+            WHERE recipient = type::thing($user_id) AND ($unread_only = false OR read_at IS NONE)
+            ORDER BY created_at DESC
+            LIMIT $limit
+            ",
+        )
+        .bind(("user_id", user_id))
+        .bind(("unread_only", unread_only))
+        .bind(("limit", limit))
+        .await
+        .map_err(AppError::database)?;
+
+    notifications_query
+        .take(0)
+        .map_err(AppError::deserialization)
+}
+
+/// Utility function to mark a user's notifications read. The ones of other users are left alone.
+pub async fn mark_notifications_read<T: Clone + AsSurrealClient>(
+    db: &T,
+    external_user_id: &str,
+    notification_ids: Vec<String>,
+) -> Result<Vec<Notification>, AppError> {
+    let user_id = get_user_record(db, external_user_id).await?;
+
+    let mut mark_read_query = db
+        .as_client()
+        .query(
+            "
+            LET $user = type::thing($user_id);
+            UPDATE notification SET read_at = time::now()
+            WHERE recipient = $user
+                AND meta::id(id) INSIDE $notification_ids
+                AND read_at IS NONE;
+            SELECT * FROM notification
+            WHERE recipient = $user AND meta::id(id) INSIDE $notification_ids
+            ORDER BY created_at DESC;
+            ",
+        )
+        .bind(("notification_ids", notification_ids))
+        .bind(("user_id", user_id))
+        .await
+        .map_err(AppError::database)?;
+
+    mark_read_query.take(2).map_err(AppError::deserialization)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::migrations::MIGRATIONS;
+    use lib::database::memory::connect_in_memory;
+
+    fn order_confirmed(user_id: &str) -> NewNotification {
+        NewNotification {
+            user_id: user_id.to_string(),
+            category: NotificationCategory::Orders,
+            title: "Payment received".to_string(),
+            body: "Your templates are ready for download".to_string(),
+            link: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn preferences_decide_where_notifications_go() {
+        let db = connect_in_memory(MIGRATIONS).await.unwrap();
+
+        let posted = post_notification(&db, order_confirmed("buyer-1"))
+            .await
+            .unwrap();
+        assert!(posted.notification.is_some());
+        assert!(posted.email_enabled);
+
+        // Marketing is opt-in
+        let marketing = NewNotification {
+            category: NotificationCategory::Marketing,
+            ..order_confirmed("buyer-1")
+        };
+        let posted = post_notification(&db, marketing).await.unwrap();
+        assert!(posted.notification.is_none());
+        assert!(!posted.email_enabled);
+
+        set_notification_preference(
+            &db,
+            "buyer-1",
+            NotificationPreference {
+                channel: NotificationChannel::Email,
+                category: NotificationCategory::Orders,
+                enabled: false,
+            },
+        )
+        .await
+        .unwrap();
+        let posted = post_notification(&db, order_confirmed("buyer-1"))
+            .await
+            .unwrap();
+        assert!(posted.notification.is_some());
+        assert!(!posted.email_enabled);
+
+        let preferences = get_notification_preferences(&db, "buyer-1").await.unwrap();
+        assert_eq!(preferences.len(), 8);
+        assert!(!preferences.contains(&NotificationPreference {
+            channel: NotificationChannel::Email,
+            category: NotificationCategory::Orders,
+            enabled: true,
+        }));
+        assert!(preferences.contains(&NotificationPreference {
+            channel: NotificationChannel::InApp,
+            category: NotificationCategory::Orders,
+            enabled: true,
+        }));
+    }
+
+    #[tokio::test]
+    async fn users_only_read_their_own_notifications() {
+        let db = connect_in_memory(MIGRATIONS).await.unwrap();
+
+        let mine = post_notification(&db, order_confirmed("buyer-1"))
+            .await
+            .unwrap()
+            .notification
+            .unwrap();
+        let theirs = post_notification(&db, order_confirmed("buyer-2"))
+            .await
+            .unwrap()
+            .notification
+            .unwrap();
+        let ids: Vec<String> = [&mine, &theirs]
+            .iter()
+            .map(|notification| notification.id.as_ref().unwrap().id.to_raw())
+            .collect();
+
+        let marked = mark_notifications_read(&db, "buyer-1", ids).await.unwrap();
+        assert_eq!(marked.len(), 1);
+        assert!(marked[0].read_at.is_some());
+
+        let unread = get_notifications(&db, "buyer-1", true, 10).await.unwrap();
+        assert!(unread.is_empty());
+        let unread = get_notifications(&db, "buyer-2", true, 10).await.unwrap();
+        assert_eq!(unread.len(), 1);
+        assert_eq!(unread[0].title, "Payment received");
+    }
+}